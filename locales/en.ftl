@@ -0,0 +1,140 @@
+# Texts of the bot in English, see src/i18n.rs. Every message has to be in every catalog.
+
+## History browser
+
+back = ‹ Back
+history-edited = (edited)
+history-page = page { $page } / { $pages }
+history-undo = ↩ Undo
+calendar-month = { $month ->
+    [1] January
+    [2] February
+    [3] March
+    [4] April
+    [5] May
+    [6] June
+    [7] July
+    [8] August
+    [9] September
+    [10] October
+    [11] November
+   *[12] December
+} { $year }
+history-bad-date = Can't read the date { $date }, use 2022-06-23, yesterday or monday
+history-empty = Nothing is saved yet, send messages to the chat and browse them here
+jump-empty = No saved messages since { $date }
+delete-last = Deleted the last message, /trash keeps it
+undo-too-late = Too late to undo, restore the message from /trash
+trash-empty = The trash is empty
+trash-closed = This trash browser is closed, open /trash again
+favorites-empty = No favorites yet, star messages with ☆ in /history
+
+## Tags
+
+tag-usage = Use /tag with tag names, e.g. /tag work later
+tag-done = Tagged with { $tags }
+tag-no-message = Reply to a message or open it in /history to tag it
+tag-invalid = #{ $name } is not a tag
+tag-empty = No messages tagged #{ $name }
+tags-none = No tags yet, add #hashtags to your messages or use /tag
+
+## Forget
+
+admin-only = Only administrators of the group can do this
+forget-question = Delete every saved message, tag and star of this chat together with its history? This can't be undone.
+forget-confirm = Forget everything
+forget-cancel = Cancel
+forget-done = { $count ->
+    [one] Forgot { $count } saved message of this chat
+   *[other] Forgot { $count } saved messages of this chat
+}
+
+## Export and import
+
+export-usage = Export formats are json, csv, md and html, e.g. /export csv
+export-empty = Nothing to export yet
+import-usage = Send result.json from Telegram Desktop (Export chat history, JSON format) with the caption /import
+import-too-big = The file is too big for bots to download, import it with tgbot-admin import <file> { $chat }
+import-unreadable = Can't read the export: { $error }
+import-done = { $imported ->
+    [one] Imported { $imported } message
+   *[other] Imported { $imported } messages
+}, { $existing } were saved before and { $skipped } had no text. Browse them with /history
+import-conflicts = { $count ->
+    [one] { $count } message was not imported, its id is taken by another saved message: { $ids }
+   *[other] { $count } messages were not imported, their ids are taken by other saved messages: { $ids }
+}
+
+## API keys and feeds
+
+apikey-issued = API key for this chat: { $key }
+    Send it as "Authorization: Bearer <key>", /apikey revoke disables it
+apikey-issued-group = API key for { $title }: { $key }
+    Send it as "Authorization: Bearer <key>", /apikey revoke in the group disables it
+apikey-sent = Sent you the API key in a private message
+apikey-start-private = Start a private chat with me first, then send /apikey here again
+apikey-revoked = { $count ->
+    [one] Revoked { $count } API key of this chat
+   *[other] Revoked { $count } API keys of this chat
+}
+feed-on = Atom feed of this chat: { $url }
+    Anyone with the link can read it, /feed new replaces the link and /feed off turns it off
+feed-off = Feed is off, its link doesn't work anymore
+feed-none = This chat has no feed
+feed-unavailable = Feeds are off on this bot, its HTTP server needs API_ADDR and PUBLIC_URL
+feed-usage = Use /feed, /feed new or /feed off
+
+## Reminders
+
+remind-usage = Use /remind <when> <text>, e.g. /remind in 2 hours call Ann, /remind tomorrow 9am standup or /remind в пятницу в 18:00 кино
+remind-none = No reminders, set one with /remind 2h call Ann
+remind-cancel-hint = /remind cancel <number> cancels one
+remind-cancelled = Cancelled reminder #{ $id }
+remind-not-pending = There is no pending reminder #{ $id }
+remind-cancel-usage = Use /remind cancel <number> with a number from /remind
+remind-passed = That time has already passed
+remind-set = Reminder #{ $id } set for { $date }
+remind-in-hour = in 1 hour
+remind-in-hours = in 3 hours
+remind-tomorrow = tomorrow
+remind-in-week = in a week
+reminder-late = This was due { $date }, the bot was offline then
+
+## Settings
+
+settings-summary = Settings
+
+    Timezone: { $timezone } (now { $time })
+    Language: { $language }
+    Export format: { $format }
+    Reminders: { $quiet ->
+        [on] silent
+       *[off] with sound
+    }
+settings-timezone = 🕒 Timezone
+settings-language = 🌐 Language
+settings-export = 📦 Export format
+settings-quiet-on = 🔕 Reminders: silent
+settings-quiet-off = 🔔 Reminders: with sound
+settings-done = Done
+settings-usage = Use /settings tz Europe/Moscow, /settings lang ru, /settings export csv or /settings quiet on
+settings-bad-timezone = Unknown timezone { $name }, use a name like Europe/Moscow or America/New_York
+settings-bad-language = Languages are en and ru
+settings-bad-format = Export formats are json, csv, md and html
+settings-bad-quiet = Use /settings quiet on or /settings quiet off
+
+## Command menu, `command-<name>` for every command of the router
+
+command-history = Browse saved messages, /history 2022-06-23 starts at a date
+command-exit = Close the history message
+command-tag = Tag the replied or open message
+command-tags = Tags of this chat
+command-favorites = Browse starred messages
+command-trash = Browse and restore deleted messages
+command-forget = Delete everything saved in this chat
+command-export = Download the history as json, csv, md or html
+command-import = Import a Telegram Desktop export
+command-apikey = Get or revoke a key for the REST API
+command-feed = Turn the Atom feed of this chat on or off
+command-remind = Set, list or cancel reminders
+command-settings = Timezone, language, export format and reminders
@@ -0,0 +1,144 @@
+# Тексты бота на русском, см. src/i18n.rs. Каждое сообщение должно быть во всех каталогах.
+
+## History browser
+
+back = ‹ Назад
+history-edited = (изменено)
+history-page = стр. { $page } / { $pages }
+history-undo = ↩ Вернуть
+calendar-month = { $month ->
+    [1] Январь
+    [2] Февраль
+    [3] Март
+    [4] Апрель
+    [5] Май
+    [6] Июнь
+    [7] Июль
+    [8] Август
+    [9] Сентябрь
+    [10] Октябрь
+    [11] Ноябрь
+   *[12] Декабрь
+} { $year }
+history-bad-date = Не понимаю дату { $date }, напишите 2022-06-23, вчера или понедельник
+history-empty = Пока ничего не сохранено, пишите в чат и смотрите сообщения здесь
+jump-empty = Нет сохранённых сообщений с { $date }
+delete-last = Последнее сообщение удалено, оно лежит в /trash
+undo-too-late = Отменить уже нельзя, верните сообщение из /trash
+trash-empty = Корзина пуста
+trash-closed = Эта корзина уже закрыта, откройте /trash заново
+favorites-empty = Избранного пока нет, отмечайте сообщения ☆ в /history
+
+## Tags
+
+tag-usage = Напишите /tag и названия тегов, например /tag работа потом
+tag-done = Добавлены теги { $tags }
+tag-no-message = Ответьте на сообщение или откройте его в /history, чтобы добавить теги
+tag-invalid = #{ $name } не может быть тегом
+tag-empty = Нет сообщений с тегом #{ $name }
+tags-none = Тегов пока нет, добавляйте #хэштеги в сообщения или используйте /tag
+
+## Forget
+
+admin-only = Это могут только администраторы группы
+forget-question = Удалить все сохранённые сообщения, теги и избранное этого чата вместе с историей? Это нельзя отменить.
+forget-confirm = Удалить всё
+forget-cancel = Отмена
+forget-done = { $count ->
+    [one] Удалено { $count } сохранённое сообщение этого чата
+    [few] Удалено { $count } сохранённых сообщения этого чата
+   *[many] Удалено { $count } сохранённых сообщений этого чата
+}
+
+## Export and import
+
+export-usage = Форматы выгрузки: json, csv, md и html, например /export csv
+export-empty = Выгружать пока нечего
+import-usage = Отправьте result.json из Telegram Desktop (Экспорт истории чата, формат JSON) с подписью /import
+import-too-big = Файл слишком большой для ботов, загрузите его командой tgbot-admin import <file> { $chat }
+import-unreadable = Не удалось прочитать выгрузку: { $error }
+import-done = { $imported ->
+    [one] Загружено { $imported } сообщение
+    [few] Загружено { $imported } сообщения
+   *[many] Загружено { $imported } сообщений
+}, { $existing } уже были сохранены, в { $skipped } не было текста. Смотрите их в /history
+import-conflicts = { $count ->
+    [one] { $count } сообщение не загружено, его номер занят другим сохранённым сообщением: { $ids }
+    [few] { $count } сообщения не загружены, их номера заняты другими сохранёнными сообщениями: { $ids }
+   *[many] { $count } сообщений не загружено, их номера заняты другими сохранёнными сообщениями: { $ids }
+}
+
+## API keys and feeds
+
+apikey-issued = API-ключ этого чата: { $key }
+    Передавайте его как "Authorization: Bearer <key>", /apikey revoke отключает ключи
+apikey-issued-group = API-ключ чата { $title }: { $key }
+    Передавайте его как "Authorization: Bearer <key>", /apikey revoke в группе отключает ключи
+apikey-sent = Отправил API-ключ вам в личные сообщения
+apikey-start-private = Сначала начните личный чат со мной, а потом снова отправьте /apikey здесь
+apikey-revoked = { $count ->
+    [one] Отозван { $count } API-ключ этого чата
+    [few] Отозвано { $count } API-ключа этого чата
+   *[many] Отозвано { $count } API-ключей этого чата
+}
+feed-on = Atom-лента этого чата: { $url }
+    Её может читать любой, у кого есть ссылка, /feed new меняет ссылку, /feed off выключает ленту
+feed-off = Лента выключена, ссылка больше не работает
+feed-none = У этого чата нет ленты
+feed-unavailable = Ленты на этом боте выключены, его HTTP-серверу нужны API_ADDR и PUBLIC_URL
+feed-usage = Используйте /feed, /feed new или /feed off
+
+## Reminders
+
+remind-usage = Напишите /remind <когда> <текст>, например /remind через 2 часа позвонить Ане, /remind завтра в 9 планёрка или /remind in 2 hours call Ann
+remind-none = Напоминаний нет, поставьте его так: /remind 2h позвонить Ане
+remind-cancel-hint = /remind cancel <номер> отменяет напоминание
+remind-cancelled = Напоминание #{ $id } отменено
+remind-not-pending = Нет ожидающего напоминания #{ $id }
+remind-cancel-usage = Напишите /remind cancel <номер> с номером из /remind
+remind-passed = Это время уже прошло
+remind-set = Напоминание #{ $id } на { $date }
+remind-in-hour = через час
+remind-in-hours = через 3 часа
+remind-tomorrow = завтра
+remind-in-week = через неделю
+reminder-late = Напоминание было на { $date }, но бот тогда не работал
+
+## Settings
+
+settings-summary = Настройки
+
+    Часовой пояс: { $timezone } (сейчас { $time })
+    Язык: { $language }
+    Формат выгрузки: { $format }
+    Напоминания: { $quiet ->
+        [on] без звука
+       *[off] со звуком
+    }
+settings-timezone = 🕒 Часовой пояс
+settings-language = 🌐 Язык
+settings-export = 📦 Формат выгрузки
+settings-quiet-on = 🔕 Напоминания: без звука
+settings-quiet-off = 🔔 Напоминания: со звуком
+settings-done = Готово
+settings-usage = Используйте /settings tz Europe/Moscow, /settings lang en, /settings export csv или /settings quiet on
+settings-bad-timezone = Неизвестный часовой пояс { $name }, укажите название вроде Europe/Moscow или Asia/Yekaterinburg
+settings-bad-language = Доступные языки: en и ru
+settings-bad-format = Форматы выгрузки: json, csv, md и html
+settings-bad-quiet = Используйте /settings quiet on или /settings quiet off
+
+## Command menu, `command-<name>` for every command of the router
+
+command-history = Сохранённые сообщения, /history 2022-06-23 начнёт с даты
+command-exit = Закрыть сообщение истории
+command-tag = Добавить теги к сообщению, на которое вы ответили или которое открыто
+command-tags = Теги этого чата
+command-favorites = Избранные сообщения
+command-trash = Удалённые сообщения, их можно восстановить
+command-forget = Удалить всё сохранённое в этом чате
+command-export = Выгрузить историю в json, csv, md или html
+command-import = Загрузить выгрузку Telegram Desktop
+command-apikey = Получить или отозвать ключ для REST API
+command-feed = Включить или выключить Atom-ленту этого чата
+command-remind = Поставить, показать или отменить напоминания
+command-settings = Часовой пояс, язык, формат выгрузки и напоминания
@@ -0,0 +1,134 @@
+CREATE TABLE IF NOT EXISTS link_message
+(
+    id          INTEGER NOT NULL,
+    text        TEXT    NOT NULL,
+    chat_id     INTEGER NOT NULL,
+    message_id  INTEGER NOT NULL,
+    filter      TEXT,
+    PRIMARY KEY (chat_id, id)
+);
+
+CREATE TABLE IF NOT EXISTS "update"
+(
+    id        INTEGER PRIMARY KEY,
+    update_id INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS message
+(
+    message_id  INTEGER NOT NULL,
+    text        TEXT    NOT NULL,
+    chat_id     INTEGER NOT NULL,
+    date        INTEGER NOT NULL DEFAULT 0,
+    edit_date   INTEGER,
+    from_id     INTEGER,
+    entities    TEXT,
+    deleted_at  INTEGER,
+    PRIMARY KEY (chat_id, message_id)
+);
+
+CREATE TABLE IF NOT EXISTS chat
+(
+    id        INTEGER PRIMARY KEY,
+    chat_type TEXT    NOT NULL,
+    title     TEXT,
+    username  TEXT
+);
+
+CREATE TABLE IF NOT EXISTS tg_user
+(
+    id         INTEGER PRIMARY KEY,
+    username   TEXT,
+    first_name TEXT NOT NULL,
+    last_name  TEXT
+);
+
+INSERT OR IGNORE INTO "update" VALUES (1, 1);
+
+CREATE TABLE IF NOT EXISTS update_log
+(
+    update_id INTEGER PRIMARY KEY,
+    body      TEXT    NOT NULL,
+    chat_id   INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS tag
+(
+    id      INTEGER PRIMARY KEY,
+    chat_id INTEGER NOT NULL,
+    name    TEXT    NOT NULL,
+    UNIQUE (chat_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS message_tag
+(
+    tag_id     INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    PRIMARY KEY (tag_id, message_id)
+);
+
+CREATE TABLE IF NOT EXISTS favorite
+(
+    chat_id    INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    PRIMARY KEY (chat_id, message_id)
+);
+
+CREATE TABLE IF NOT EXISTS purge_log
+(
+    chat_id   INTEGER NOT NULL,
+    user_id   INTEGER,
+    messages  INTEGER NOT NULL,
+    purged_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS message_revision
+(
+    chat_id    INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    text       TEXT    NOT NULL,
+    entities   TEXT,
+    date       INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS message_revision_message ON message_revision (chat_id, message_id);
+
+CREATE TABLE IF NOT EXISTS api_key
+(
+    key        TEXT PRIMARY KEY,
+    chat_id    INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS chat_feed
+(
+    chat_id    INTEGER PRIMARY KEY,
+    token      TEXT    NOT NULL UNIQUE,
+    title      TEXT,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS reminder
+(
+    id         INTEGER PRIMARY KEY,
+    chat_id    INTEGER NOT NULL,
+    message_id INTEGER,
+    text       TEXT    NOT NULL,
+    due_at     INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    sent_at    INTEGER,
+    silent     BOOLEAN NOT NULL DEFAULT FALSE,
+    language   TEXT    NOT NULL DEFAULT 'en',
+    timezone   TEXT    NOT NULL DEFAULT 'UTC'
+);
+
+CREATE INDEX IF NOT EXISTS reminder_pending ON reminder (due_at) WHERE sent_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS user_settings
+(
+    user_id         INTEGER PRIMARY KEY,
+    timezone        TEXT    NOT NULL,
+    language        TEXT    NOT NULL,
+    export_format   TEXT    NOT NULL,
+    quiet_reminders BOOLEAN NOT NULL
+);
@@ -1,7 +1,7 @@
 CREATE TABLE IF NOT EXISTS link_message
 (
     id          BIGSERIAL PRIMARY KEY,
-    text        VARCHAR(256)    NOT NULL,
+    text        TEXT            NOT NULL,
     chat_id     BIGSERIAL NOT NULL,
     message_id  BIGSERIAL NOT NULL
 );
@@ -15,8 +15,196 @@ CREATE TABLE IF NOT EXISTS update
 CREATE TABLE IF NOT EXISTS message
 (
     message_id  BIGSERIAL PRIMARY KEY,
-    text        VARCHAR(256)    NOT NULL,
+    text        TEXT            NOT NULL,
     chat_id     BIGSERIAL NOT NULL
 );
 
-INSERT INTO update VALUES (1, 1)
+INSERT INTO update VALUES (1, 1) ON CONFLICT DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS update_log
+(
+    update_id BIGINT PRIMARY KEY,
+    body      TEXT NOT NULL
+);
+
+ALTER TABLE message ADD COLUMN IF NOT EXISTS date BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE message ADD COLUMN IF NOT EXISTS edit_date BIGINT;
+ALTER TABLE message ADD COLUMN IF NOT EXISTS from_id BIGINT;
+
+CREATE TABLE IF NOT EXISTS chat
+(
+    id        BIGINT PRIMARY KEY,
+    chat_type VARCHAR(32) NOT NULL,
+    title     TEXT,
+    username  TEXT
+);
+
+CREATE TABLE IF NOT EXISTS tg_user
+(
+    id         BIGINT PRIMARY KEY,
+    username   TEXT,
+    first_name TEXT NOT NULL,
+    last_name  TEXT
+);
+
+-- message ids are only unique within a chat
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1
+                   FROM pg_index i
+                            JOIN pg_class c ON c.oid = i.indrelid
+                   WHERE c.relname = 'message'
+                     AND c.relnamespace = current_schema()::regnamespace
+                     AND i.indisprimary
+                     AND i.indnatts = 2) THEN
+        ALTER TABLE message DROP CONSTRAINT IF EXISTS message_pkey;
+        ALTER TABLE message ADD PRIMARY KEY (chat_id, message_id);
+    END IF;
+END
+$$;
+
+-- Telegram messages are up to 4096 characters, the type is changed once
+-- since the change rewrites the table under an exclusive lock
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1
+               FROM information_schema.columns
+               WHERE table_schema = current_schema()
+                 AND table_name = 'message'
+                 AND column_name = 'text'
+                 AND data_type <> 'text') THEN
+        ALTER TABLE message ALTER COLUMN text TYPE TEXT;
+    END IF;
+    IF EXISTS (SELECT 1
+               FROM information_schema.columns
+               WHERE table_schema = current_schema()
+                 AND table_name = 'link_message'
+                 AND column_name = 'text'
+                 AND data_type <> 'text') THEN
+        ALTER TABLE link_message ALTER COLUMN text TYPE TEXT;
+    END IF;
+END
+$$;
+
+ALTER TABLE message ADD COLUMN IF NOT EXISTS entities TEXT;
+
+ALTER TABLE link_message ADD COLUMN IF NOT EXISTS filter TEXT;
+
+-- browser messages are bot messages, their ids are only unique within a chat too
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1
+                   FROM pg_index i
+                            JOIN pg_class c ON c.oid = i.indrelid
+                   WHERE c.relname = 'link_message'
+                     AND c.relnamespace = current_schema()::regnamespace
+                     AND i.indisprimary
+                     AND i.indnatts = 2) THEN
+        ALTER TABLE link_message DROP CONSTRAINT IF EXISTS link_message_pkey;
+        ALTER TABLE link_message ADD PRIMARY KEY (chat_id, id);
+    END IF;
+END
+$$;
+
+CREATE TABLE IF NOT EXISTS tag
+(
+    id      BIGSERIAL PRIMARY KEY,
+    chat_id BIGINT NOT NULL,
+    name    TEXT   NOT NULL,
+    UNIQUE (chat_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS message_tag
+(
+    tag_id     BIGINT NOT NULL REFERENCES tag (id) ON DELETE CASCADE,
+    message_id BIGINT NOT NULL,
+    PRIMARY KEY (tag_id, message_id)
+);
+
+CREATE TABLE IF NOT EXISTS favorite
+(
+    chat_id    BIGINT NOT NULL,
+    message_id BIGINT NOT NULL,
+    PRIMARY KEY (chat_id, message_id)
+);
+
+ALTER TABLE message ADD COLUMN IF NOT EXISTS deleted_at BIGINT;
+
+-- the log is backfilled once, when the column is added
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1
+                   FROM information_schema.columns
+                   WHERE table_schema = current_schema()
+                     AND table_name = 'update_log'
+                     AND column_name = 'chat_id') THEN
+        ALTER TABLE update_log ADD COLUMN chat_id BIGINT;
+        UPDATE update_log
+        SET chat_id = COALESCE(body::jsonb #>> '{message,chat,id}',
+                               body::jsonb #>> '{edited_message,chat,id}',
+                               body::jsonb #>> '{callback_query,message,chat,id}')::bigint;
+    END IF;
+END
+$$;
+
+CREATE TABLE IF NOT EXISTS purge_log
+(
+    chat_id   BIGINT NOT NULL,
+    user_id   BIGINT,
+    messages  BIGINT NOT NULL,
+    purged_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS message_revision
+(
+    chat_id    BIGINT NOT NULL,
+    message_id BIGINT NOT NULL,
+    text       TEXT   NOT NULL,
+    entities   TEXT,
+    date       BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS message_revision_message ON message_revision (chat_id, message_id);
+
+CREATE TABLE IF NOT EXISTS api_key
+(
+    key        TEXT PRIMARY KEY,
+    chat_id    BIGINT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS chat_feed
+(
+    chat_id    BIGINT PRIMARY KEY,
+    token      TEXT   NOT NULL UNIQUE,
+    title      TEXT,
+    created_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS reminder
+(
+    id         BIGSERIAL PRIMARY KEY,
+    chat_id    BIGINT NOT NULL,
+    message_id BIGINT,
+    text       TEXT   NOT NULL,
+    due_at     BIGINT NOT NULL,
+    created_at BIGINT NOT NULL,
+    sent_at    BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS reminder_pending ON reminder (due_at) WHERE sent_at IS NULL;
+
+ALTER TABLE reminder ADD COLUMN IF NOT EXISTS silent BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS user_settings
+(
+    user_id         BIGINT PRIMARY KEY,
+    timezone        TEXT    NOT NULL,
+    language        TEXT    NOT NULL,
+    export_format   TEXT    NOT NULL,
+    quiet_reminders BOOLEAN NOT NULL
+);
+
+ALTER TABLE reminder ADD COLUMN IF NOT EXISTS language TEXT NOT NULL DEFAULT 'en';
+
+ALTER TABLE reminder ADD COLUMN IF NOT EXISTS timezone TEXT NOT NULL DEFAULT 'UTC';
@@ -0,0 +1,637 @@
+//! Read-only REST API over the saved messages for tools that shouldn't touch the database.
+//!
+//! Every request carries `Authorization: Bearer <key>` with a key issued by `/apikey`,
+//! a key reads the one chat it was issued in:
+//!
+//! - `GET /api/chats` the chat of the key with its message count
+//! - `GET /api/chats/{chat_id}/messages?after=&limit=` messages in id order, page by page
+//! - `GET /api/chats/{chat_id}/messages/{message_id}` one message with its earlier texts
+//! - `GET /api/chats/{chat_id}/search?q=&limit=` messages containing `q`, newest first
+//! - `GET /stream` server-sent events: `message` for every saved message of the chat and
+//!   `edit` for every edit, as they happen. `EventSource` can't set headers, so the key
+//!   may come as `?key=` here instead.
+//!
+//! `GET /feed/{token}` is the one route without a key: the Atom feed `/feed` turns on,
+//! the token in its path is the secret.
+//!
+//! Trashed messages are left out. Errors come as `{"error": "..."}` with the matching status.
+
+use crate::export;
+use crate::feed;
+use crate::models::{ApiKey, EditedMessage, Message, MessageRevision};
+use crate::storage::{self, Storage, StorageEvent};
+use crate::web::MessageEntity;
+use chrono::Utc;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use warp::http::StatusCode;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+const KEY_LENGTH: usize = 32;
+
+/// A fresh key for the chat, valid once stored with `Storage::insert_api_key`.
+pub fn new_key(chat_id: i64) -> ApiKey {
+    ApiKey {
+        key: random_token(),
+        chat_id,
+        created_at: Utc::now().timestamp(),
+    }
+}
+
+/// Random alphanumeric secret for API keys and feed URLs.
+pub fn random_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(KEY_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// Serves the API on `addr` until the process exits. `events` feeds `/stream`,
+/// see `NotifyingStorage::events`.
+pub fn spawn(
+    storage: Arc<dyn Storage>,
+    events: broadcast::Sender<StorageEvent>,
+    addr: SocketAddr,
+) -> JoinHandle<()> {
+    tokio::spawn(warp::serve(routes(storage, events)).run(addr))
+}
+
+pub fn routes(
+    storage: Arc<dyn Storage>,
+    events: broadcast::Sender<StorageEvent>,
+) -> impl Filter<Extract = (Response,), Error = Infallible> + Clone {
+    let events = warp::any().map(move || events.clone());
+    let storage = warp::any().map(move || storage.clone());
+    let key = warp::header::optional::<String>("authorization");
+    let chats = warp::path!("api" / "chats")
+        .and(key)
+        .and(storage.clone())
+        .then(list_chats);
+    let messages = warp::path!("api" / "chats" / i64 / "messages")
+        .and(warp::query::<PageQuery>())
+        .and(key)
+        .and(storage.clone())
+        .then(list_messages);
+    let message = warp::path!("api" / "chats" / i64 / "messages" / i64)
+        .and(key)
+        .and(storage.clone())
+        .then(get_message);
+    let search = warp::path!("api" / "chats" / i64 / "search")
+        .and(warp::query::<SearchQuery>())
+        .and(key)
+        .and(storage.clone())
+        .then(search_messages);
+    let stream = warp::path!("stream")
+        .and(warp::query::<StreamQuery>())
+        .and(key)
+        .and(storage.clone())
+        .and(events)
+        .then(stream_events);
+    let feed = warp::path!("feed" / String).and(storage).then(serve_feed);
+    warp::get()
+        .and(
+            chats
+                .or(messages)
+                .unify()
+                .or(message)
+                .unify()
+                .or(search)
+                .unify()
+                .or(stream)
+                .unify()
+                .or(feed)
+                .unify(),
+        )
+        .recover(rejection)
+        .unify()
+}
+
+#[derive(Deserialize)]
+struct PageQuery {
+    after: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    key: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ApiChat {
+    chat_id: i64,
+    title: Option<String>,
+    messages: i64,
+}
+
+/// A saved message with its entities parsed, dates in unix seconds.
+#[derive(Serialize)]
+struct ApiMessage {
+    chat_id: i64,
+    message_id: i64,
+    date: i64,
+    edit_date: Option<i64>,
+    from_id: Option<i64>,
+    text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    entities: Vec<MessageEntity>,
+}
+
+impl From<&Message> for ApiMessage {
+    fn from(message: &Message) -> Self {
+        Self {
+            chat_id: message.chat_id,
+            message_id: message.message_id,
+            date: message.date,
+            edit_date: message.edit_date,
+            from_id: message.from_id,
+            text: message.text.clone(),
+            entities: message.entities(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ApiRevision {
+    date: i64,
+    text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    entities: Vec<MessageEntity>,
+}
+
+impl From<&MessageRevision> for ApiRevision {
+    fn from(revision: &MessageRevision) -> Self {
+        Self {
+            date: revision.date,
+            text: revision.text.clone(),
+            entities: revision.entities(),
+        }
+    }
+}
+
+/// The new text of an edited message, sent as the `edit` event of `/stream`.
+#[derive(Serialize)]
+struct ApiEdit {
+    chat_id: i64,
+    message_id: i64,
+    edit_date: Option<i64>,
+    text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    entities: Vec<MessageEntity>,
+}
+
+impl From<&EditedMessage> for ApiEdit {
+    fn from(edited: &EditedMessage) -> Self {
+        Self {
+            chat_id: edited.chat_id,
+            message_id: edited.message_id,
+            edit_date: edited.edit_date,
+            text: edited.text.clone(),
+            entities: edited.entities(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ApiError {
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    BadRequest(&'static str),
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        if storage::is_not_found(&e) {
+            ApiError::NotFound
+        } else {
+            ApiError::Internal(e)
+        }
+    }
+}
+
+impl Reply for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::Unauthorized => (StatusCode::UNAUTHORIZED, "missing or unknown API key"),
+            ApiError::Forbidden => (StatusCode::FORBIDDEN, "the key doesn't cover this chat"),
+            ApiError::NotFound => (StatusCode::NOT_FOUND, "not found"),
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::Internal(e) => {
+                eprintln!("{:?}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal error")
+            }
+        };
+        error_response(status, message)
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    let body = warp::reply::json(&json!({ "error": message }));
+    warp::reply::with_status(body, status).into_response()
+}
+
+fn respond(result: Result<serde_json::Value, ApiError>) -> Response {
+    match result {
+        Ok(body) => warp::reply::json(&body).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Looks the key up and checks it covers `chat_id` when the request is about a chat.
+async fn authorize(
+    storage: &dyn Storage,
+    header: Option<String>,
+    chat_id: Option<i64>,
+) -> Result<ApiKey, ApiError> {
+    let key = header
+        .as_deref()
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .ok_or(ApiError::Unauthorized)?;
+    let api_key = storage
+        .select_api_key(key.trim())
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+    match chat_id {
+        Some(chat_id) if chat_id != api_key.chat_id => Err(ApiError::Forbidden),
+        _ => Ok(api_key),
+    }
+}
+
+fn limit(limit: Option<i64>) -> Result<i64, ApiError> {
+    match limit.unwrap_or(DEFAULT_LIMIT) {
+        limit @ 1..=MAX_LIMIT => Ok(limit),
+        _ => Err(ApiError::BadRequest("limit must be between 1 and 200")),
+    }
+}
+
+async fn list_chats(header: Option<String>, storage: Arc<dyn Storage>) -> Response {
+    respond(
+        async {
+            let api_key = authorize(storage.as_ref(), header, None).await?;
+            let chats: Vec<ApiChat> = storage
+                .list_chats()
+                .await?
+                .into_iter()
+                .filter(|c| c.chat_id == api_key.chat_id)
+                .map(|c| ApiChat {
+                    chat_id: c.chat_id,
+                    title: c.title,
+                    messages: c.messages,
+                })
+                .collect();
+            Ok(json!({ "chats": chats }))
+        }
+        .await,
+    )
+}
+
+async fn list_messages(
+    chat_id: i64,
+    query: PageQuery,
+    header: Option<String>,
+    storage: Arc<dyn Storage>,
+) -> Response {
+    respond(
+        async {
+            authorize(storage.as_ref(), header, Some(chat_id)).await?;
+            let limit = limit(query.limit)?;
+            let after = query.after.unwrap_or(0);
+            let page = storage.select_messages_after(chat_id, after, limit).await?;
+            // A full page may have more behind it.
+            let next_after = match page.last() {
+                Some(last) if page.len() as i64 == limit => Some(last.message_id),
+                _ => None,
+            };
+            let messages: Vec<ApiMessage> = page.iter().map(ApiMessage::from).collect();
+            Ok(json!({ "messages": messages, "next_after": next_after }))
+        }
+        .await,
+    )
+}
+
+async fn get_message(
+    chat_id: i64,
+    message_id: i64,
+    header: Option<String>,
+    storage: Arc<dyn Storage>,
+) -> Response {
+    respond(
+        async {
+            authorize(storage.as_ref(), header, Some(chat_id)).await?;
+            let message = storage.select_message(chat_id, message_id).await?;
+            if message.deleted_at.is_some() {
+                return Err(ApiError::NotFound);
+            }
+            let revisions: Vec<ApiRevision> = storage
+                .select_revisions(chat_id, message_id)
+                .await?
+                .iter()
+                .map(ApiRevision::from)
+                .collect();
+            Ok(json!({ "message": ApiMessage::from(&message), "revisions": revisions }))
+        }
+        .await,
+    )
+}
+
+async fn search_messages(
+    chat_id: i64,
+    query: SearchQuery,
+    header: Option<String>,
+    storage: Arc<dyn Storage>,
+) -> Response {
+    respond(
+        async {
+            authorize(storage.as_ref(), header, Some(chat_id)).await?;
+            let limit = limit(query.limit)?;
+            let q = query.q.trim();
+            if q.is_empty() {
+                return Err(ApiError::BadRequest("q must not be empty"));
+            }
+            let found = storage.search_messages(chat_id, q, limit).await?;
+            let messages: Vec<ApiMessage> = found.iter().map(ApiMessage::from).collect();
+            Ok(json!({ "messages": messages }))
+        }
+        .await,
+    )
+}
+
+/// Keeps the connection open and forwards the events of the key's chat.
+/// The stream ends when the key is revoked.
+async fn stream_events(
+    query: StreamQuery,
+    header: Option<String>,
+    storage: Arc<dyn Storage>,
+    events: broadcast::Sender<StorageEvent>,
+) -> Response {
+    let header = header.or_else(|| query.key.map(|key| format!("Bearer {}", key)));
+    let api_key = match authorize(storage.as_ref(), header, None).await {
+        Ok(api_key) => api_key,
+        Err(e) => return e.into_response(),
+    };
+    // Subscribed before the response goes out, so nothing saved after it is missed.
+    let mut receiver = events.subscribe();
+    let stream = async_stream::stream! {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => match sse_event(api_key.chat_id, &event) {
+                    Some(event) => event,
+                    None => continue,
+                },
+                Err(RecvError::Lagged(missed)) => {
+                    warp::sse::Event::default().event("lagged").data(missed.to_string())
+                }
+                Err(RecvError::Closed) => break,
+            };
+            match storage.select_api_key(&api_key.key).await {
+                Ok(Some(_)) => yield Ok::<_, Infallible>(event),
+                Ok(None) => break,
+                Err(e) => eprintln!("{:?}", e),
+            }
+        }
+    };
+    warp::sse::reply(warp::sse::keep_alive().stream(stream)).into_response()
+}
+
+async fn serve_feed(token: String, storage: Arc<dyn Storage>) -> Response {
+    let result: Result<String, ApiError> = async {
+        let feed = storage
+            .select_feed(&token)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        let messages = storage
+            .select_latest_messages(feed.chat_id, feed::ENTRIES)
+            .await?;
+        let entries = export::annotate(storage.as_ref(), feed.chat_id, messages).await?;
+        let base = dotenv::var("PUBLIC_URL").unwrap_or_default();
+        Ok(feed::render(&feed, &feed::url(&base, &token), &entries))
+    }
+    .await;
+    match result {
+        Ok(atom) => {
+            warp::reply::with_header(atom, "content-type", "application/atom+xml; charset=utf-8")
+                .into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+fn sse_event(chat_id: i64, event: &StorageEvent) -> Option<warp::sse::Event> {
+    let event = match event {
+        StorageEvent::Saved(message) if message.chat_id == chat_id => warp::sse::Event::default()
+            .event("message")
+            .id(message.message_id.to_string())
+            .json_data(ApiMessage::from(message)),
+        StorageEvent::Edited(edited) if edited.chat_id == chat_id => warp::sse::Event::default()
+            .event("edit")
+            .id(edited.message_id.to_string())
+            .json_data(ApiEdit::from(edited)),
+        _ => return None,
+    };
+    event.map_err(|e| eprintln!("{:?}", e)).ok()
+}
+
+async fn rejection(rejection: Rejection) -> Result<Response, Infallible> {
+    let response = if rejection.is_not_found() {
+        error_response(StatusCode::NOT_FOUND, "not found")
+    } else if rejection.find::<warp::reject::InvalidQuery>().is_some() {
+        error_response(StatusCode::BAD_REQUEST, "invalid query string")
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        error_response(StatusCode::METHOD_NOT_ALLOWED, "the API is read-only")
+    } else {
+        error_response(StatusCode::BAD_REQUEST, "bad request")
+    };
+    Ok(response)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::ChatFeed;
+    use crate::storage::{MemoryStorage, NotifyingStorage};
+    use serde_json::Value;
+    use std::time::Duration;
+
+    async fn storage_with_messages() -> Arc<dyn Storage> {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        for (chat_id, message_id, text) in [(42, 1, "Buy milk"), (42, 2, "call Ann"), (7, 1, "x")] {
+            let message = Message::new(text.to_string(), chat_id, message_id).await;
+            storage.insert_message(&message).await.unwrap();
+        }
+        let key = ApiKey {
+            key: "key42".to_string(),
+            chat_id: 42,
+            created_at: 0,
+        };
+        storage.insert_api_key(&key).await.unwrap();
+        storage
+    }
+
+    async fn get(storage: &Arc<dyn Storage>, path: &str, key: Option<&str>) -> (u16, Value) {
+        let mut request = warp::test::request().path(path);
+        if let Some(key) = key {
+            request = request.header("authorization", format!("Bearer {}", key));
+        }
+        let events = broadcast::channel(1).0;
+        let response = request.reply(&routes(storage.clone(), events)).await;
+        let body = serde_json::from_slice(response.body()).unwrap();
+        (response.status().as_u16(), body)
+    }
+
+    #[tokio::test]
+    async fn requires_key_of_the_chat() {
+        let storage = storage_with_messages().await;
+        assert_eq!(get(&storage, "/api/chats", None).await.0, 401);
+        assert_eq!(get(&storage, "/api/chats", Some("wrong")).await.0, 401);
+        let (status, body) = get(&storage, "/api/chats/7/messages", Some("key42")).await;
+        assert_eq!(status, 403);
+        assert_eq!(body["error"], "the key doesn't cover this chat");
+
+        let (status, body) = get(&storage, "/api/chats", Some("key42")).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["chats"][0]["chat_id"], 42);
+        assert_eq!(body["chats"][0]["messages"], 2);
+        assert_eq!(body["chats"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn pages_through_messages() {
+        let storage = storage_with_messages().await;
+        let path = "/api/chats/42/messages?limit=1";
+        let (status, body) = get(&storage, path, Some("key42")).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["messages"][0]["text"], "Buy milk");
+        assert_eq!(body["next_after"], 1);
+
+        let path = "/api/chats/42/messages?after=1&limit=1";
+        let (_, body) = get(&storage, path, Some("key42")).await;
+        assert_eq!(body["messages"][0]["message_id"], 2);
+        let (_, body) = get(&storage, "/api/chats/42/messages?after=2", Some("key42")).await;
+        assert!(body["next_after"].is_null());
+
+        let path = "/api/chats/42/messages?limit=1000";
+        assert_eq!(get(&storage, path, Some("key42")).await.0, 400);
+        let path = "/api/chats/42/messages?after=soon";
+        assert_eq!(get(&storage, path, Some("key42")).await.0, 400);
+    }
+
+    #[tokio::test]
+    async fn fetches_message_with_revisions() {
+        let storage = storage_with_messages().await;
+        let edited = EditedMessage::new(2, "call Bob".to_string(), 42, Some(100)).await;
+        storage.change_message_text(&edited).await.unwrap();
+
+        let (status, body) = get(&storage, "/api/chats/42/messages/2", Some("key42")).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["message"]["text"], "call Bob");
+        assert_eq!(body["message"]["edit_date"], 100);
+        assert_eq!(body["revisions"][0]["text"], "call Ann");
+
+        storage.delete_message(42, 2, 200).await.unwrap();
+        let (status, body) = get(&storage, "/api/chats/42/messages/2", Some("key42")).await;
+        assert_eq!(status, 404);
+        assert_eq!(body["error"], "not found");
+        assert_eq!(
+            get(&storage, "/api/chats/42/messages/9", Some("key42"))
+                .await
+                .0,
+            404
+        );
+    }
+
+    #[tokio::test]
+    async fn searches_messages() {
+        let storage = storage_with_messages().await;
+        let (status, body) = get(&storage, "/api/chats/42/search?q=MILK", Some("key42")).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["messages"][0]["message_id"], 1);
+        assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            get(&storage, "/api/chats/42/search?q=", Some("key42"))
+                .await
+                .0,
+            400
+        );
+    }
+
+    #[tokio::test]
+    async fn streams_messages_of_the_chat() {
+        let storage = Arc::new(NotifyingStorage::new(storage_with_messages().await));
+        let routes = routes(storage.clone(), storage.events());
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let url = format!("http://{}/stream", addr);
+        assert_eq!(reqwest::get(&url).await.unwrap().status(), 401);
+
+        let mut response = reqwest::get(format!("{}?key=key42", url)).await.unwrap();
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        let other = Message::new("other chat".to_string(), 7, 5).await;
+        other.insert(storage.as_ref()).await.unwrap();
+        let fresh = Message::new("fresh".to_string(), 42, 3).await;
+        fresh.insert(storage.as_ref()).await.unwrap();
+        let edited = EditedMessage::new(3, "fresh edit".to_string(), 42, Some(10)).await;
+        edited.change_message_text(storage.as_ref()).await.unwrap();
+
+        let mut body = String::new();
+        while !body.contains("event:edit") {
+            let chunk = tokio::time::timeout(Duration::from_secs(5), response.chunk())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            body.push_str(&String::from_utf8_lossy(&chunk));
+        }
+        assert!(body.contains("event:message\ndata:{\"chat_id\":42,\"message_id\":3,"));
+        assert!(body.contains("\"text\":\"fresh edit\""));
+        assert!(!body.contains("other chat"));
+    }
+
+    #[tokio::test]
+    async fn serves_feed_by_token() {
+        let storage = storage_with_messages().await;
+        let feed = ChatFeed {
+            chat_id: 42,
+            token: "secret".to_string(),
+            title: Some("Notes".to_string()),
+            created_at: 0,
+        };
+        storage.upsert_feed(&feed).await.unwrap();
+        let events = broadcast::channel(1).0;
+        let routes = routes(storage.clone(), events);
+
+        let response = warp::test::request()
+            .path("/feed/secret")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/atom+xml; charset=utf-8"
+        );
+        let atom = String::from_utf8(response.body().to_vec()).unwrap();
+        assert!(atom.contains("<content type=\"text\">Buy milk</content>"));
+        assert!(atom.contains("<content type=\"text\">call Ann</content>"));
+        assert!(!atom.contains(">x</content>"));
+
+        assert_eq!(get(&storage, "/feed/guess", None).await.0, 404);
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::Result;
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use tgbot::handlers::{EditMessage, SaveMessage};
+use tgbot::import;
+use tgbot::models::PurgeRecord;
+use tgbot::web::WUpdate;
+use tgbot::{storage, BotBuilder, TgClient};
+
+/// Operates the bot storage without raw SQL, reads the same .env as the bot.
+#[derive(Parser)]
+#[command(name = "tgbot-admin")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Shows or resets the offset passed to getUpdates
+    Offset {
+        #[command(subcommand)]
+        action: OffsetAction,
+    },
+    /// Lists chats with their saved message counts
+    Chats,
+    /// Deletes everything saved for a chat and records it in the purge log
+    Purge { chat_id: i64 },
+    /// Lists purged chats with who purged them and when
+    Purges,
+    /// Creates missing tables
+    Migrate,
+    /// Saves logged messages and edits again, commands and button presses are skipped
+    Replay {
+        #[arg(default_value_t = 0)]
+        from_update_id: i64,
+    },
+    /// Saves messages of a Telegram Desktop export (result.json) into a chat, keeps those saved before
+    Import { file: PathBuf, chat_id: i64 },
+    /// Sends a text message to check the bot token
+    Send { chat_id: i64, text: String },
+}
+
+#[derive(Subcommand)]
+enum OffsetAction {
+    Show,
+    /// 0 makes Telegram resend every unconfirmed update
+    Reset {
+        #[arg(default_value_t = 0)]
+        update_id: i64,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let storage = storage::from_env().await;
+    match cli.command {
+        Command::Offset {
+            action: OffsetAction::Show,
+        } => {
+            println!("{}", storage.get_last_update().await?.update_id);
+        }
+        Command::Offset {
+            action: OffsetAction::Reset { update_id },
+        } => {
+            storage.set_update_offset(update_id).await?;
+            println!("offset set to {}", update_id);
+        }
+        Command::Chats => {
+            for chat in storage.list_chats().await? {
+                println!(
+                    "{}\t{}\t{}",
+                    chat.chat_id,
+                    chat.messages,
+                    chat.title.unwrap_or_default()
+                );
+            }
+        }
+        Command::Purge { chat_id } => {
+            let deleted = storage.purge_chat(chat_id).await?;
+            let record = PurgeRecord {
+                chat_id,
+                user_id: None,
+                messages: deleted as i64,
+                purged_at: Utc::now().timestamp(),
+            };
+            storage.log_purge(&record).await?;
+            println!("deleted {} messages of chat {}", deleted, chat_id);
+        }
+        Command::Purges => {
+            for purge in storage.list_purges().await? {
+                let user = purge.user_id.map(|id| id.to_string());
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    purge.purged_at,
+                    purge.chat_id,
+                    purge.messages,
+                    user.unwrap_or_else(|| "admin".to_string())
+                );
+            }
+        }
+        Command::Migrate => {
+            storage.migrate().await?;
+            println!("migrated");
+        }
+        Command::Replay { from_update_id } => {
+            let bot = BotBuilder::new()
+                .storage(storage.clone())
+                .message_handler(SaveMessage)
+                .edited_message_handler(EditMessage)
+                .build()
+                .await;
+            let mut replayed = 0;
+            for logged in storage.select_logged_updates(from_update_id).await? {
+                let upd: WUpdate = serde_json::from_str(&logged.body)?;
+                let is_command = upd
+                    .message
+                    .as_ref()
+                    .is_some_and(|wm| wm.text.starts_with('/'));
+                if is_command || upd.callback_query.is_some() {
+                    continue;
+                }
+                bot.handle_update(&upd).await?;
+                replayed += 1;
+            }
+            println!("replayed {} updates", replayed);
+        }
+        Command::Import { file, chat_id } => {
+            let parsed = import::parse(&std::fs::read_to_string(&file)?, chat_id)?;
+            let report = import::save(storage.as_ref(), &parsed).await?;
+            println!(
+                "imported {} messages into chat {}, {} saved before, {} skipped",
+                report.imported, chat_id, report.existing, report.skipped
+            );
+            if !report.conflicts.is_empty() {
+                println!(
+                    "not imported, ids taken by other messages: {:?}",
+                    report.conflicts
+                );
+            }
+        }
+        Command::Send { chat_id, text } => {
+            let sent = TgClient::new().send_message(chat_id, text).await?;
+            println!("sent message {}", sent.message_id);
+        }
+    }
+    Ok(())
+}
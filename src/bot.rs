@@ -0,0 +1,1330 @@
+use crate::handlers;
+use crate::models::{LoggedUpdate, Update};
+use crate::reminders;
+use crate::router::{Audience, Handler, Router};
+use crate::settings;
+use crate::storage::{self, Storage};
+use crate::tg_service::{TgClient, TgClientConfig};
+use crate::web::{BotCommandScope, WUpdate};
+use futures::pin_mut;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+
+const POLL_PAUSE: u64 = 3;
+
+/// Assembles a [`Bot`]. Without a config the bot url is read from the `TG` variable,
+/// without a storage the backend is picked by `storage::from_env`.
+///
+/// ```no_run
+/// # async fn run() {
+/// let bot = tgbot::BotBuilder::new()
+///     .with_default_handlers()
+///     .build()
+///     .await;
+/// bot.run().await;
+/// # }
+/// ```
+#[derive(Default)]
+pub struct BotBuilder {
+    config: Option<TgClientConfig>,
+    tg_client: Option<TgClient>,
+    storage: Option<Arc<dyn Storage>>,
+    router: Router,
+}
+
+impl BotBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn config(mut self, config: TgClientConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Uses a ready client instead of building one from the config.
+    pub fn tg_client(mut self, tg_client: TgClient) -> Self {
+        self.tg_client = Some(tg_client);
+        self
+    }
+
+    pub fn storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn command(mut self, command: &str, handler: impl Handler + 'static) -> Self {
+        self.router.command(command, Arc::new(handler));
+        self
+    }
+
+    /// A command listed in private chats and for group administrators only.
+    pub fn admin_command(mut self, command: &str, handler: impl Handler + 'static) -> Self {
+        self.router
+            .command_for(command, Audience::Admins, Arc::new(handler));
+        self
+    }
+
+    pub fn callback(mut self, data: &str, handler: impl Handler + 'static) -> Self {
+        self.router.callback(data, Arc::new(handler));
+        self
+    }
+
+    pub fn message_handler(mut self, handler: impl Handler + 'static) -> Self {
+        self.router.message(Arc::new(handler));
+        self
+    }
+
+    pub fn edited_message_handler(mut self, handler: impl Handler + 'static) -> Self {
+        self.router.edited_message(Arc::new(handler));
+        self
+    }
+
+    /// The history browser: saves messages and edits, `/history`, `/exit`, `/tag`, `/tags`,
+    /// `/favorites`, `/trash`, `/forget`, `/export`, `/import`, `/apikey`, `/feed`, `/remind`,
+    /// `/settings`, the step, jump, star, reminder, delete and close buttons, the calendar
+    /// and the settings menu. Group menus list `/forget`, `/import`, `/apikey` and `/feed`
+    /// for administrators only.
+    pub fn with_default_handlers(self) -> Self {
+        self.message_handler(handlers::SaveMessage)
+            .edited_message_handler(handlers::EditMessage)
+            .command("/history", handlers::History)
+            .command("/exit", handlers::Exit)
+            .command("/tag", handlers::Tag)
+            .command("/tags", handlers::Tags)
+            .command("/favorites", handlers::Favorites)
+            .command("/trash", handlers::Trash)
+            .admin_command("/forget", handlers::Forget)
+            .command("/export", handlers::Export)
+            .admin_command("/import", handlers::Import)
+            .admin_command("/apikey", handlers::ApiKeys)
+            .admin_command("/feed", handlers::Feed::from_env())
+            .command("/remind", handlers::Remind)
+            .command("/settings", handlers::Settings)
+            .callback("/next", handlers::Next)
+            .callback("/previous", handlers::Previous)
+            // sent as "previous" by keyboards of older history messages
+            .callback("/last", handlers::Previous)
+            .callback("/first", handlers::First)
+            .callback("/end", handlers::End)
+            .callback("/goto", handlers::Goto)
+            .callback("/star", handlers::Star)
+            .callback("/remind", handlers::RemindButton)
+            .callback("/delete", handlers::Delete)
+            .callback("/undo", handlers::Undo)
+            .callback("/restore", handlers::Restore)
+            .callback("/purge", handlers::Purge)
+            .callback("/forget", handlers::ConfirmForget)
+            .callback("/page", handlers::Page)
+            .callback("/exit", handlers::Exit)
+            .callback("/calendar", handlers::Calendar)
+            .callback("/jump", handlers::Jump)
+            .callback("/browse", handlers::Browse)
+            .callback("/ignore", handlers::Ignore)
+            .callback("/settings", handlers::SettingsButton)
+    }
+
+    pub async fn build(self) -> Bot {
+        let tg_client = match (self.tg_client, self.config) {
+            (Some(tg_client), _) => tg_client,
+            (None, Some(config)) => TgClient::from_config(&config),
+            (None, None) => TgClient::new(),
+        };
+        let storage = match self.storage {
+            Some(storage) => storage,
+            None => storage::from_env().await,
+        };
+        Bot {
+            tg_client: Arc::new(tg_client),
+            storage,
+            router: Arc::new(self.router),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Bot {
+    tg_client: Arc<TgClient>,
+    storage: Arc<dyn Storage>,
+    router: Arc<Router>,
+}
+
+impl Bot {
+    pub fn tg_client(&self) -> &TgClient {
+        &self.tg_client
+    }
+
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    /// Polls Telegram forever.
+    pub async fn run(&self) {
+        loop {
+            self.poll_updates().await;
+            tokio::time::sleep(tokio::time::Duration::from_secs(POLL_PAUSE)).await;
+        }
+    }
+
+    /// Publishes the command menu, then polls Telegram and sends due reminders in the background.
+    pub fn spawn(self) -> JoinHandle<()> {
+        reminders::spawn(self.tg_client.clone(), self.storage.clone());
+        tokio::spawn(async move {
+            if let Err(e) = self.set_commands().await {
+                eprintln!("{:?}", e)
+            }
+            self.run().await
+        })
+    }
+
+    /// Replaces the command menus of private chats, groups and group administrators
+    /// with the commands of the router, once per language of `settings::LANGUAGES`.
+    pub async fn set_commands(&self) -> anyhow::Result<()> {
+        let scopes = [
+            BotCommandScope::AllPrivateChats,
+            BotCommandScope::AllGroupChats,
+            BotCommandScope::AllChatAdministrators,
+        ];
+        for scope in scopes {
+            for (i, (language, _)) in settings::LANGUAGES.iter().enumerate() {
+                // the first language also serves users of languages the bot doesn't speak
+                let language_code = (i > 0).then(|| language.to_string());
+                let commands = self.router.menu(scope, language);
+                self.tg_client
+                    .set_my_commands(commands, scope, language_code)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the handlers of one update without touching the stored offset.
+    pub async fn handle_update(&self, upd: &WUpdate) -> anyhow::Result<()> {
+        self.router
+            .dispatch(&self.tg_client, self.storage(), upd)
+            .await
+    }
+
+    async fn log_update(&self, upd: &WUpdate) {
+        let logged = serde_json::to_string(upd).map(|body| LoggedUpdate {
+            update_id: upd.update_id,
+            body,
+            chat_id: upd.chat_id(),
+        });
+        let result = match logged {
+            Ok(logged) => self.storage.log_update(&logged).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            eprintln!("{:?}", e)
+        }
+    }
+
+    /// One long polling round: handles every update of the batch until the first error.
+    /// The offset is stored before an update is handled, so a failing handler never replays it.
+    pub async fn poll_updates(&self) {
+        let updates = self.tg_client.get_updates(self.storage()).await;
+        pin_mut!(updates);
+        while let Some(upd) = updates.next().await {
+            match upd {
+                Ok(upd) => {
+                    let update = Update::new(1, upd.update_id)
+                        .await
+                        .insert(self.storage())
+                        .await;
+                    if let Err(e) = update {
+                        eprintln!("{:?}", e);
+                        continue;
+                    }
+                    self.log_update(&upd).await;
+                    if let Err(e) = self.handle_update(&upd).await {
+                        eprintln!("{:?}", e)
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::Filter;
+    use crate::storage::MemoryStorage;
+    use crate::testkit::{
+        callback_update, document_update, edited_message_update, message_update, FakeBotApi,
+    };
+
+    const CHAT_ID: i64 = 42;
+
+    async fn bot_with_saved_messages() -> (FakeBotApi, Bot) {
+        let api = FakeBotApi::start().await;
+        let bot = BotBuilder::new()
+            .tg_client(api.client())
+            .storage(Arc::new(MemoryStorage::new()))
+            .with_default_handlers()
+            .build()
+            .await;
+        api.push_updates(vec![
+            message_update(1, CHAT_ID, 1, "first"),
+            message_update(2, CHAT_ID, 2, "second"),
+            message_update(3, CHAT_ID, 3, "third"),
+        ]);
+        bot.poll_updates().await;
+        (api, bot)
+    }
+
+    #[tokio::test]
+    async fn history_sends_first_message_with_buttons() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![message_update(4, CHAT_ID, 4, "/history")]);
+        bot.poll_updates().await;
+
+        assert_eq!(api.sent_texts(), vec!["first"]);
+        let sent = &api.calls_to("sendMessage")[0];
+        assert_eq!(sent["chat_id"], CHAT_ID);
+        let keyboard = &sent["reply_markup"]["inline_keyboard"];
+        assert_eq!(keyboard[0][0]["text"], "1 / 3");
+        assert_eq!(keyboard[0][1]["callback_data"], "/next");
+        assert_eq!(keyboard[1][1]["callback_data"], "/star:1");
+        assert_eq!(keyboard[1][2]["callback_data"], "/remind");
+        assert_eq!(keyboard[1][3]["callback_data"], "/delete");
+        assert_eq!(keyboard[1][5]["callback_data"], "/exit");
+    }
+
+    #[tokio::test]
+    async fn goto_jumps_to_position() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![message_update(4, CHAT_ID, 4, "/history")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(5, CHAT_ID, 1001, "/goto:3")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(6, CHAT_ID, 1002, "/exit")]);
+        bot.poll_updates().await;
+
+        assert_eq!(api.sent_texts(), vec!["first", "third"]);
+        let sent = &api.calls_to("sendMessage")[1];
+        assert_eq!(
+            sent["reply_markup"]["inline_keyboard"][0][2]["text"],
+            "3 / 3"
+        );
+        let deleted: Vec<_> = api
+            .calls_to("deleteMessage")
+            .iter()
+            .map(|b| b["message_id"].as_i64().unwrap())
+            .collect();
+        assert_eq!(deleted, vec![1001, 1002]);
+    }
+
+    #[tokio::test]
+    async fn next_and_previous_walk_through_history() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![message_update(4, CHAT_ID, 4, "/history")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(5, CHAT_ID, 1001, "/next")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(6, CHAT_ID, 1002, "/next")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(7, CHAT_ID, 1003, "/previous")]);
+        bot.poll_updates().await;
+
+        assert_eq!(api.sent_texts(), vec!["first", "second", "third", "second"]);
+        let deleted: Vec<_> = api
+            .calls_to("deleteMessage")
+            .iter()
+            .map(|b| b["message_id"].as_i64().unwrap())
+            .collect();
+        assert_eq!(deleted, vec![1001, 1002, 1003]);
+    }
+
+    #[tokio::test]
+    async fn long_text_is_paged() {
+        let (api, bot) = bot_with_saved_messages().await;
+        let long = format!("{}\n{}", "a".repeat(4000), "b".repeat(1000));
+        api.push_updates(vec![
+            message_update(4, CHAT_ID, 4, &long),
+            message_update(5, CHAT_ID, 5, "/history"),
+        ]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(6, CHAT_ID, 1001, "/goto:4")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(7, CHAT_ID, 1002, "/page:2")]);
+        bot.poll_updates().await;
+
+        let sent = &api.calls_to("sendMessage")[1];
+        assert_eq!(sent["text"], format!("{}\n", "a".repeat(4000)));
+        assert_eq!(
+            sent["reply_markup"]["inline_keyboard"][0][0]["text"],
+            "page 1 / 2"
+        );
+        let edited = &api.calls_to("editMessageText")[0];
+        assert_eq!(edited["message_id"], 1002);
+        assert_eq!(edited["text"], "b".repeat(1000));
+        assert_eq!(
+            edited["reply_markup"]["inline_keyboard"][0][0]["callback_data"],
+            "/page:1"
+        );
+    }
+
+    #[tokio::test]
+    async fn exit_deletes_history_message() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![message_update(4, CHAT_ID, 4, "/history")]);
+        bot.poll_updates().await;
+        api.clear_calls();
+        api.push_updates(vec![message_update(5, CHAT_ID, 5, "/exit")]);
+        bot.poll_updates().await;
+
+        let deleted = api.calls_to("deleteMessage");
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0]["chat_id"], CHAT_ID);
+        assert_eq!(deleted[0]["message_id"], 1001);
+        assert!(api.sent_texts().is_empty());
+    }
+
+    #[tokio::test]
+    async fn history_shows_edited_text() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![
+            edited_message_update(4, CHAT_ID, 1, "first, edited"),
+            message_update(5, CHAT_ID, 5, "/history"),
+        ]);
+        bot.poll_updates().await;
+
+        assert_eq!(api.sent_texts(), vec!["first, edited"]);
+    }
+
+    #[tokio::test]
+    async fn history_shows_author_and_date() {
+        let (api, bot) = bot_with_saved_messages().await;
+        let mut update = message_update(4, CHAT_ID, 4, "signed");
+        update["message"]["date"] = 1656000000.into();
+        update["message"]["from"] =
+            serde_json::json!({ "id": 7, "first_name": "Ann", "username": "ann" });
+        api.push_updates(vec![update, message_update(5, CHAT_ID, 5, "/history")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![
+            callback_update(6, CHAT_ID, 1001, "/next"),
+            callback_update(7, CHAT_ID, 1002, "/next"),
+            callback_update(8, CHAT_ID, 1003, "/next"),
+        ]);
+        bot.poll_updates().await;
+
+        assert_eq!(
+            api.sent_texts().last().unwrap(),
+            "Ann (@ann) · 2022-06-23 16:00\nsigned"
+        );
+    }
+
+    #[tokio::test]
+    async fn history_keeps_formatting() {
+        let api = FakeBotApi::start().await;
+        let bot = BotBuilder::new()
+            .tg_client(api.client())
+            .storage(Arc::new(MemoryStorage::new()))
+            .with_default_handlers()
+            .build()
+            .await;
+        let mut update = message_update(1, CHAT_ID, 1, "see docs");
+        update["message"]["date"] = 1656000000.into();
+        update["message"]["entities"] = serde_json::json!([
+            { "type": "bold", "offset": 0, "length": 3 },
+            { "type": "text_link", "offset": 4, "length": 4, "url": "https://example.com" }
+        ]);
+        api.push_updates(vec![update, message_update(2, CHAT_ID, 2, "/history")]);
+        bot.poll_updates().await;
+
+        let sent = &api.calls_to("sendMessage")[0];
+        assert_eq!(sent["text"], "2022-06-23 16:00\nsee docs");
+        // the header takes the first 17 units
+        assert_eq!(sent["entities"][0]["offset"], 17);
+        assert_eq!(sent["entities"][1]["type"], "text_link");
+        assert_eq!(sent["entities"][1]["offset"], 21);
+        assert_eq!(sent["entities"][1]["url"], "https://example.com");
+    }
+
+    #[tokio::test]
+    async fn history_reads_relative_dates() {
+        let api = FakeBotApi::start().await;
+        let bot = BotBuilder::new()
+            .tg_client(api.client())
+            .storage(Arc::new(MemoryStorage::new()))
+            .with_default_handlers()
+            .build()
+            .await;
+        let now = chrono::Utc::now().timestamp();
+        let mut updates = Vec::new();
+        for (id, days_ago) in [(1, 10), (2, 2)] {
+            let mut update = message_update(id, CHAT_ID, id, &format!("note {}", id));
+            update["message"]["date"] = (now - days_ago * 86400).into();
+            updates.push(update);
+        }
+        updates.push(message_update(3, CHAT_ID, 3, "/history 3 days ago"));
+        updates.push(message_update(4, CHAT_ID, 4, "/history someday"));
+        api.push_updates(updates);
+        bot.poll_updates().await;
+
+        let texts = api.sent_texts();
+        assert!(texts[0].ends_with("note 2"));
+        assert_eq!(
+            texts[1],
+            "Can't read the date \"someday\", use 2022-06-23, yesterday or monday"
+        );
+    }
+
+    #[tokio::test]
+    async fn history_jumps_to_date() {
+        let api = FakeBotApi::start().await;
+        let bot = BotBuilder::new()
+            .tg_client(api.client())
+            .storage(Arc::new(MemoryStorage::new()))
+            .with_default_handlers()
+            .build()
+            .await;
+        let mut updates = Vec::new();
+        // 2022-06-01, 2022-06-10 and 2022-07-01 at noon UTC
+        for (id, date) in [(1, 1654084800), (2, 1654862400), (3, 1656676800)] {
+            let mut update = message_update(id, CHAT_ID, id, &format!("note {}", id));
+            update["message"]["date"] = date.into();
+            updates.push(update);
+        }
+        updates.push(message_update(4, CHAT_ID, 4, "/history 2022-06-05"));
+        api.push_updates(updates);
+        bot.poll_updates().await;
+        api.push_updates(vec![
+            callback_update(5, CHAT_ID, 1001, "/calendar:2022-07"),
+            callback_update(6, CHAT_ID, 1001, "/jump:2022-06-11"),
+            message_update(7, CHAT_ID, 7, "/history 2023-01-01"),
+        ]);
+        bot.poll_updates().await;
+
+        let texts = api.sent_texts();
+        assert!(texts[0].ends_with("note 2"));
+        assert!(texts[1].ends_with("note 3"));
+        assert_eq!(texts[2], "No saved messages since 2023-01-01");
+        let calendar = &api.calls_to("editMessageReplyMarkup")[0];
+        assert_eq!(calendar["message_id"], 1001);
+        assert_eq!(
+            calendar["reply_markup"]["inline_keyboard"][0][1]["text"],
+            "July 2022"
+        );
+        assert_eq!(api.calls_to("deleteMessage")[0]["message_id"], 1001);
+    }
+
+    #[tokio::test]
+    async fn history_filters_by_tag() {
+        let (api, bot) = bot_with_saved_messages().await;
+        let mut reply = message_update(6, CHAT_ID, 6, "/tag Later");
+        reply["message"]["reply_to_message"] =
+            message_update(0, CHAT_ID, 2, "second")["message"].clone();
+        api.push_updates(vec![
+            message_update(4, CHAT_ID, 4, "plan #work"),
+            message_update(5, CHAT_ID, 5, "ship it #Work #later"),
+            reply,
+            message_update(7, CHAT_ID, 7, "/tags"),
+            message_update(8, CHAT_ID, 8, "/history #work"),
+        ]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(9, CHAT_ID, 1003, "/next")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![message_update(10, CHAT_ID, 10, "/history #nothing")]);
+        bot.poll_updates().await;
+
+        assert_eq!(
+            api.sent_texts(),
+            vec![
+                "Tagged with #later",
+                "#later — 2\n#work — 2",
+                "plan #work",
+                "ship it #Work #later",
+                "No messages tagged #nothing",
+            ]
+        );
+        let sent = &api.calls_to("sendMessage")[3];
+        assert_eq!(
+            sent["reply_markup"]["inline_keyboard"][0][2]["text"],
+            "2 / 2 #work"
+        );
+    }
+
+    #[tokio::test]
+    async fn favorites_browse_starred_messages() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![
+            message_update(4, CHAT_ID, 4, "/favorites"),
+            message_update(5, CHAT_ID, 5, "/history"),
+        ]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(6, CHAT_ID, 1002, "/goto:3")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(7, CHAT_ID, 1003, "/star:1")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![
+            message_update(8, CHAT_ID, 8, "/exit"),
+            message_update(9, CHAT_ID, 9, "/favorites"),
+        ]);
+        bot.poll_updates().await;
+
+        assert_eq!(
+            api.sent_texts(),
+            vec![
+                "No favorites yet, star messages with ☆ in /history",
+                "first",
+                "third",
+                "third",
+            ]
+        );
+        let starred = &api.calls_to("editMessageText")[0];
+        assert_eq!(starred["message_id"], 1003);
+        assert_eq!(
+            starred["reply_markup"]["inline_keyboard"][1][2]["text"],
+            "⭐"
+        );
+        let sent = &api.calls_to("sendMessage")[3];
+        assert_eq!(
+            sent["reply_markup"]["inline_keyboard"][0][0]["text"],
+            "1 / 1 ⭐"
+        );
+    }
+
+    #[tokio::test]
+    async fn history_replaces_the_open_browser() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![
+            message_update(4, CHAT_ID, 4, "/history"),
+            message_update(5, CHAT_ID, 5, "/history"),
+            message_update(6, -5, 1, "/history"),
+        ]);
+        bot.poll_updates().await;
+
+        assert_eq!(
+            api.sent_texts(),
+            vec![
+                "first",
+                "first",
+                "Nothing is saved yet, send messages to the chat and browse them here"
+            ]
+        );
+        let deleted = api.calls_to("deleteMessage");
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0]["message_id"], 1001);
+        let storage = bot.storage();
+        assert_eq!(
+            storage.delete_and_return_link(CHAT_ID).await.unwrap().id,
+            1002
+        );
+        assert!(storage.select_link(CHAT_ID).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn delete_moves_messages_to_trash() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![message_update(4, CHAT_ID, 4, "/history")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(5, CHAT_ID, 1001, "/delete")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(6, CHAT_ID, 1002, "/undo:1")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(7, CHAT_ID, 1003, "/next")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(8, CHAT_ID, 1004, "/delete")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![message_update(9, CHAT_ID, 9, "/trash")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(10, CHAT_ID, 1006, "/purge")]);
+        bot.poll_updates().await;
+
+        assert_eq!(
+            api.sent_texts(),
+            vec![
+                "first",
+                "second",
+                "first",
+                "second",
+                "third",
+                "second",
+                "The trash is empty"
+            ]
+        );
+        let sent = api.calls_to("sendMessage");
+        let undo = &sent[1]["reply_markup"]["inline_keyboard"][2][0];
+        assert_eq!(undo["callback_data"], "/undo:1");
+        assert_eq!(
+            sent[1]["reply_markup"]["inline_keyboard"][0][0]["text"],
+            "1 / 2"
+        );
+        assert_eq!(
+            sent[5]["reply_markup"]["inline_keyboard"][0][0]["text"],
+            "1 / 1 🗑"
+        );
+        let history = bot.storage().select_all_by_chat_id(CHAT_ID).await.unwrap();
+        let texts: Vec<_> = history.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec!["first", "third"]);
+    }
+
+    #[tokio::test]
+    async fn purge_only_works_in_the_open_trash_browser() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![message_update(4, CHAT_ID, 4, "/history")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(5, CHAT_ID, 1001, "/delete")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(6, CHAT_ID, 1002, "/purge")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![message_update(7, CHAT_ID, 7, "/trash")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(8, CHAT_ID, 1002, "/purge")]);
+        bot.poll_updates().await;
+
+        let storage = bot.storage();
+        assert_eq!(
+            storage.select_message(CHAT_ID, 2).await.unwrap().text,
+            "second"
+        );
+        assert_eq!(
+            storage
+                .count_messages(CHAT_ID, &Filter::Trash)
+                .await
+                .unwrap(),
+            1
+        );
+        api.push_updates(vec![callback_update(9, CHAT_ID, 1004, "/purge")]);
+        bot.poll_updates().await;
+        assert_eq!(
+            api.sent_texts(),
+            vec![
+                "first",
+                "second",
+                "This trash browser is closed, open /trash again",
+                "first",
+                "This trash browser is closed, open /trash again",
+                "The trash is empty"
+            ]
+        );
+        let history = storage.select_all_by_chat_id(CHAT_ID).await.unwrap();
+        let texts: Vec<_> = history.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec!["second", "third"]);
+    }
+
+    const GROUP_ID: i64 = -100;
+
+    /// `update` sent by the user `user_id`.
+    fn sent_by(mut update: serde_json::Value, user_id: i64) -> serde_json::Value {
+        let kind = if update.get("message").is_some() {
+            "message"
+        } else {
+            "callback_query"
+        };
+        update[kind]["from"] = serde_json::json!({ "id": user_id, "first_name": "User" });
+        update
+    }
+
+    #[tokio::test]
+    async fn forget_in_groups_is_for_admins() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![
+            message_update(4, GROUP_ID, 1, "first"),
+            message_update(5, GROUP_ID, 2, "second"),
+        ]);
+        bot.poll_updates().await;
+        api.add_admin(GROUP_ID, 7);
+
+        api.push_updates(vec![
+            sent_by(message_update(6, GROUP_ID, 3, "/forget"), 8),
+            sent_by(message_update(7, GROUP_ID, 4, "/forget"), 7),
+        ]);
+        bot.poll_updates().await;
+        assert_eq!(
+            api.sent_texts()[0],
+            "Only administrators of the group can do this"
+        );
+        let prompt = &api.calls_to("sendMessage")[1];
+        assert_eq!(
+            prompt["reply_markup"]["inline_keyboard"][0][0]["callback_data"],
+            "/forget:yes"
+        );
+
+        api.push_updates(vec![sent_by(
+            callback_update(8, GROUP_ID, 1002, "/forget:yes"),
+            8,
+        )]);
+        bot.poll_updates().await;
+        let storage = bot.storage();
+        assert_eq!(
+            storage
+                .count_messages(GROUP_ID, &Filter::All)
+                .await
+                .unwrap(),
+            2
+        );
+        assert!(api.calls_to("deleteMessage").is_empty());
+
+        api.push_updates(vec![sent_by(
+            callback_update(9, GROUP_ID, 1002, "/forget:yes"),
+            7,
+        )]);
+        bot.poll_updates().await;
+        assert_eq!(
+            storage
+                .count_messages(GROUP_ID, &Filter::All)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            storage.count_messages(CHAT_ID, &Filter::All).await.unwrap(),
+            3
+        );
+        let members = api.calls_to("getChatMember");
+        assert_eq!(members[0]["chat_id"], GROUP_ID);
+        assert_eq!(members[0]["user_id"], 8);
+    }
+
+    #[tokio::test]
+    async fn forget_purges_chat_after_confirmation() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![
+            message_update(4, CHAT_ID, 4, "/history"),
+            message_update(5, CHAT_ID, 5, "/forget"),
+        ]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(6, CHAT_ID, 1002, "/forget:no")]);
+        bot.poll_updates().await;
+        assert_eq!(
+            bot.storage()
+                .count_messages(CHAT_ID, &Filter::All)
+                .await
+                .unwrap(),
+            3
+        );
+
+        let mut confirm = callback_update(8, CHAT_ID, 1003, "/forget:yes");
+        confirm["callback_query"]["from"] = serde_json::json!({ "id": 7, "first_name": "Ann" });
+        api.push_updates(vec![message_update(7, CHAT_ID, 7, "/forget"), confirm]);
+        bot.poll_updates().await;
+
+        let prompt = &api.calls_to("sendMessage")[1];
+        assert_eq!(
+            prompt["reply_markup"]["inline_keyboard"][0][0]["callback_data"],
+            "/forget:yes"
+        );
+        let deleted: Vec<_> = api
+            .calls_to("deleteMessage")
+            .iter()
+            .map(|b| b["message_id"].as_i64().unwrap())
+            .collect();
+        assert_eq!(deleted, vec![1002, 1003, 1001]);
+        assert_eq!(
+            api.sent_texts().last().unwrap(),
+            "Forgot 3 saved messages of this chat"
+        );
+        let storage = bot.storage();
+        assert!(storage
+            .select_all_by_chat_id(CHAT_ID)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(storage.select_link(CHAT_ID).await.is_err());
+        assert!(storage.select_logged_updates(0).await.unwrap().is_empty());
+        let purges = storage.list_purges().await.unwrap();
+        assert_eq!(purges.len(), 1);
+        assert_eq!(
+            (purges[0].chat_id, purges[0].user_id, purges[0].messages),
+            (CHAT_ID, Some(7), 3)
+        );
+    }
+
+    #[tokio::test]
+    async fn export_sends_history_as_document() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![
+            message_update(4, CHAT_ID, 4, "tagged #work"),
+            message_update(5, CHAT_ID, 5, "/export csv"),
+            message_update(6, CHAT_ID, 6, "/export pdf"),
+        ]);
+        bot.poll_updates().await;
+
+        let documents = api.calls_to("sendDocument");
+        assert_eq!(documents.len(), 1);
+        let upload = documents[0].as_str().unwrap();
+        assert!(upload.contains("filename=\"history-42.csv\""));
+        assert!(upload.contains("message_id,date,edit_date,author,tags,text\r\n"));
+        assert!(upload.contains(",#work,tagged #work\r\n"));
+        assert_eq!(
+            api.sent_texts(),
+            vec!["Export formats are json, csv, md and html, e.g. /export csv"]
+        );
+    }
+
+    #[tokio::test]
+    async fn import_saves_desktop_export() {
+        let (api, bot) = bot_with_saved_messages().await;
+        let export = r##"{"messages": [
+            {"id": 2, "type": "message", "date_unixtime": "1600000000", "text": "old second"},
+            {"id": 3, "type": "service", "date_unixtime": "1600000000", "text": ""},
+            {"id": 10, "type": "message", "date_unixtime": "1600000000",
+             "from": "Ann", "from_id": "user7", "text": ["from ", {"type": "bold", "text": "#desktop"}]}
+        ]}"##;
+        api.serve_file("export-file", export.as_bytes());
+        api.push_updates(vec![
+            message_update(4, CHAT_ID, 4, "/import"),
+            document_update(5, CHAT_ID, 5, "export-file", "/import"),
+            document_update(6, CHAT_ID, 6, "export-file", "/import"),
+        ]);
+        bot.poll_updates().await;
+
+        let texts = api.sent_texts();
+        assert!(texts[0].starts_with("Send result.json from Telegram Desktop"));
+        assert_eq!(
+            texts[1..],
+            [
+                "Imported 1 message, 0 were saved before and 1 had no text. Browse them with /history\n\
+                 1 message was not imported, its id is taken by another saved message: #2",
+                "Imported 0 messages, 1 were saved before and 1 had no text. Browse them with /history\n\
+                 1 message was not imported, its id is taken by another saved message: #2",
+            ]
+        );
+        let storage = bot.storage();
+        assert_eq!(
+            storage.select_message(CHAT_ID, 2).await.unwrap().text,
+            "second"
+        );
+        let imported = storage.select_message(CHAT_ID, 10).await.unwrap();
+        assert_eq!(
+            (imported.text.as_str(), imported.date),
+            ("from #desktop", 1600000000)
+        );
+        assert_eq!(
+            storage.select_user(7).await.unwrap().unwrap().first_name,
+            "Ann"
+        );
+        let tagged = Filter::Tag("desktop".to_string());
+        assert_eq!(storage.count_messages(CHAT_ID, &tagged).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn import_in_groups_is_for_admins() {
+        let (api, bot) = bot_with_saved_messages().await;
+        let export = r#"{"messages": [
+            {"id": 10, "type": "message", "date_unixtime": "1600000000", "text": "note"}
+        ]}"#;
+        api.serve_file("export-file", export.as_bytes());
+        api.add_admin(GROUP_ID, 7);
+        api.push_updates(vec![sent_by(
+            document_update(4, GROUP_ID, 1, "export-file", "/import"),
+            8,
+        )]);
+        bot.poll_updates().await;
+        assert_eq!(
+            api.sent_texts(),
+            vec!["Only administrators of the group can do this"]
+        );
+        assert!(api.calls_to("getFile").is_empty());
+
+        api.push_updates(vec![sent_by(
+            document_update(5, GROUP_ID, 2, "export-file", "/import"),
+            7,
+        )]);
+        bot.poll_updates().await;
+        let storage = bot.storage();
+        assert_eq!(
+            storage
+                .count_messages(GROUP_ID, &Filter::All)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn apikey_issues_and_revokes_keys() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![
+            message_update(4, CHAT_ID, 4, "/apikey"),
+            message_update(5, CHAT_ID, 5, "/apikey"),
+        ]);
+        bot.poll_updates().await;
+
+        let sent = api.sent_texts();
+        let keys: Vec<&str> = sent
+            .iter()
+            .map(|issued| {
+                issued
+                    .strip_prefix("API key for this chat: ")
+                    .and_then(|rest| rest.split('\n').next())
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(keys[0].len(), 32);
+        assert_ne!(keys[0], keys[1]);
+        for key in &keys {
+            let api_key = bot.storage().select_api_key(key).await.unwrap().unwrap();
+            assert_eq!(api_key.chat_id, CHAT_ID);
+        }
+
+        api.push_updates(vec![message_update(6, CHAT_ID, 6, "/apikey revoke")]);
+        bot.poll_updates().await;
+        assert_eq!(api.sent_texts()[2], "Revoked 2 API keys of this chat");
+        assert!(bot
+            .storage()
+            .select_api_key(keys[0])
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn apikey_of_groups_goes_to_admins_privately() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.add_admin(GROUP_ID, 7);
+        api.push_updates(vec![
+            sent_by(message_update(4, GROUP_ID, 1, "/apikey"), 8),
+            sent_by(message_update(5, GROUP_ID, 2, "/apikey revoke"), 8),
+            sent_by(message_update(6, GROUP_ID, 3, "/apikey"), 7),
+        ]);
+        bot.poll_updates().await;
+
+        let sent = api.calls_to("sendMessage");
+        let chats: Vec<_> = sent
+            .iter()
+            .map(|b| b["chat_id"].as_i64().unwrap())
+            .collect();
+        assert_eq!(chats, vec![GROUP_ID, GROUP_ID, 7, GROUP_ID]);
+        assert_eq!(
+            sent[0]["text"],
+            "Only administrators of the group can do this"
+        );
+        assert_eq!(sent[3]["text"], "Sent you the API key in a private message");
+        let key = sent[2]["text"]
+            .as_str()
+            .unwrap()
+            .strip_prefix("API key for -100: ")
+            .and_then(|rest| rest.split('\n').next())
+            .unwrap();
+        let api_key = bot.storage().select_api_key(key).await.unwrap().unwrap();
+        assert_eq!(api_key.chat_id, GROUP_ID);
+    }
+
+    /// A bot whose HTTP server is at `https://bot.example`, so `/feed` hands out links.
+    async fn bot_serving_feeds() -> (FakeBotApi, Bot) {
+        let api = FakeBotApi::start().await;
+        let bot = BotBuilder::new()
+            .tg_client(api.client())
+            .storage(Arc::new(MemoryStorage::new()))
+            .with_default_handlers()
+            .admin_command(
+                "/feed",
+                handlers::Feed::new(Some("https://bot.example/".to_string())),
+            )
+            .build()
+            .await;
+        (api, bot)
+    }
+
+    #[tokio::test]
+    async fn feed_needs_the_http_server() {
+        let api = FakeBotApi::start().await;
+        let bot = BotBuilder::new()
+            .tg_client(api.client())
+            .storage(Arc::new(MemoryStorage::new()))
+            .with_default_handlers()
+            .admin_command("/feed", handlers::Feed::new(None))
+            .build()
+            .await;
+        api.push_updates(vec![
+            message_update(1, CHAT_ID, 1, "/feed"),
+            message_update(2, CHAT_ID, 2, "/feed off"),
+        ]);
+        bot.poll_updates().await;
+
+        assert_eq!(
+            api.sent_texts(),
+            vec![
+                "Feeds are off on this bot, its HTTP server needs API_ADDR and PUBLIC_URL",
+                "This chat has no feed"
+            ]
+        );
+        let storage = bot.storage();
+        assert!(storage.select_chat_feed(CHAT_ID).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn feed_in_groups_is_for_admins() {
+        let (api, bot) = bot_serving_feeds().await;
+        api.add_admin(GROUP_ID, 7);
+        api.push_updates(vec![
+            sent_by(message_update(4, GROUP_ID, 1, "/feed"), 8),
+            sent_by(message_update(5, GROUP_ID, 2, "/feed"), 7),
+            sent_by(message_update(6, GROUP_ID, 3, "/feed off"), 8),
+        ]);
+        bot.poll_updates().await;
+
+        let texts = api.sent_texts();
+        assert_eq!(texts[0], "Only administrators of the group can do this");
+        assert!(texts[1].starts_with("Atom feed of this chat: "));
+        assert_eq!(texts[2], "Only administrators of the group can do this");
+        let storage = bot.storage();
+        assert!(storage.select_chat_feed(GROUP_ID).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn feed_turns_on_rotates_and_off() {
+        let (api, bot) = bot_serving_feeds().await;
+        let token = |text: &str| {
+            let link = "https://bot.example/feed/";
+            let rest = &text[text.find(link).unwrap() + link.len()..];
+            rest.split('\n').next().unwrap().to_string()
+        };
+        api.push_updates(vec![message_update(4, CHAT_ID, 4, "/feed")]);
+        bot.poll_updates().await;
+        let first = token(&api.sent_texts()[0]);
+        let feed = bot.storage().select_feed(&first).await.unwrap().unwrap();
+        assert_eq!(feed.chat_id, CHAT_ID);
+
+        api.push_updates(vec![message_update(5, CHAT_ID, 5, "/feed")]);
+        bot.poll_updates().await;
+        assert_eq!(token(&api.sent_texts()[1]), first);
+
+        api.push_updates(vec![message_update(6, CHAT_ID, 6, "/feed new")]);
+        bot.poll_updates().await;
+        let second = token(&api.sent_texts()[2]);
+        assert_ne!(second, first);
+        assert!(bot.storage().select_feed(&first).await.unwrap().is_none());
+
+        api.push_updates(vec![message_update(7, CHAT_ID, 7, "/feed off")]);
+        bot.poll_updates().await;
+        assert_eq!(
+            api.sent_texts()[3],
+            "Feed is off, its link doesn't work anymore"
+        );
+        assert!(bot
+            .storage()
+            .select_chat_feed(CHAT_ID)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn remind_schedules_lists_and_cancels() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![
+            message_update(4, CHAT_ID, 4, "/remind 2h call Ann"),
+            message_update(5, CHAT_ID, 5, "/remind tomorrow 9:00 standup"),
+            message_update(6, CHAT_ID, 6, "/remind soon"),
+        ]);
+        bot.poll_updates().await;
+        let sent = api.sent_texts();
+        assert!(sent[0].starts_with("Reminder #1 set for "));
+        assert!(sent[1].starts_with("Reminder #2 set for ") && sent[1].ends_with(" 09:00 UTC"));
+        assert!(sent[2].starts_with("Use /remind <when> <text>"));
+
+        let pending = bot.storage().select_reminders(CHAT_ID).await.unwrap();
+        assert_eq!(pending[0].text, "call Ann");
+        assert_eq!(pending[0].message_id, Some(4));
+        let in_two_hours = chrono::Utc::now().timestamp() + 2 * 3600;
+        assert!((in_two_hours - pending[0].due_at).abs() < 60);
+
+        api.push_updates(vec![
+            message_update(7, CHAT_ID, 7, "/remind cancel 2"),
+            message_update(8, CHAT_ID, 8, "/remind"),
+        ]);
+        bot.poll_updates().await;
+        let sent = api.sent_texts();
+        assert_eq!(sent[3], "Cancelled reminder #2");
+        assert!(sent[4].starts_with("#1 ") && sent[4].contains(" — call Ann\n"));
+
+        api.clear_calls();
+        reminders::deliver_due(bot.tg_client(), bot.storage(), in_two_hours + 60)
+            .await
+            .unwrap();
+        let delivered = api.calls_to("sendMessage");
+        assert_eq!(delivered[0]["text"], "⏰ call Ann");
+        assert_eq!(delivered[0]["reply_to_message_id"], 4);
+        assert!(bot
+            .storage()
+            .select_reminders(CHAT_ID)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    fn from_ann(mut update: serde_json::Value) -> serde_json::Value {
+        let kind = if update.get("message").is_some() {
+            "message"
+        } else {
+            "callback_query"
+        };
+        update[kind]["from"] =
+            serde_json::json!({ "id": 7, "first_name": "Ann", "language_code": "ru" });
+        update
+    }
+
+    #[tokio::test]
+    async fn settings_menu_changes_settings() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![from_ann(message_update(4, CHAT_ID, 4, "/settings"))]);
+        bot.poll_updates().await;
+        let menu = &api.calls_to("sendMessage")[0];
+        let text = menu["text"].as_str().unwrap();
+        assert!(text.starts_with("Настройки\n\nЧасовой пояс: UTC"));
+        assert!(text.contains("Язык: Русский"));
+        assert_eq!(
+            menu["reply_markup"]["inline_keyboard"][0][0]["callback_data"],
+            "/settings:tz"
+        );
+
+        api.push_updates(vec![
+            from_ann(callback_update(5, CHAT_ID, 1001, "/settings:tz")),
+            from_ann(callback_update(
+                6,
+                CHAT_ID,
+                1001,
+                "/settings:tz=Europe/Moscow",
+            )),
+            from_ann(callback_update(7, CHAT_ID, 1001, "/settings:quiet=on")),
+        ]);
+        bot.poll_updates().await;
+        let choices = &api.calls_to("editMessageReplyMarkup")[0]["reply_markup"];
+        assert_eq!(choices["inline_keyboard"][0][0]["text"], "• UTC");
+        let edits = api.calls_to("editMessageText");
+        assert!(edits[0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("Часовой пояс: Europe/Moscow"));
+        assert!(edits[1]["text"]
+            .as_str()
+            .unwrap()
+            .contains("Напоминания: без звука"));
+
+        api.clear_calls();
+        api.push_updates(vec![
+            from_ann(message_update(8, CHAT_ID, 8, "/settings export csv")),
+            from_ann(message_update(9, CHAT_ID, 9, "/settings tz Mars/Olympus")),
+            from_ann(message_update(10, CHAT_ID, 10, "/export")),
+            message_update(11, CHAT_ID, 11, "/export"),
+        ]);
+        bot.poll_updates().await;
+        let texts = api.sent_texts();
+        assert!(texts[0].contains("Формат выгрузки: csv"));
+        assert!(texts[1].starts_with("Неизвестный часовой пояс \"Mars/Olympus\""));
+        let documents = api.calls_to("sendDocument");
+        assert!(documents[0]
+            .as_str()
+            .unwrap()
+            .contains("filename=\"history-42.csv\""));
+        assert!(documents[1]
+            .as_str()
+            .unwrap()
+            .contains("filename=\"history-42.json\""));
+
+        let stored = bot
+            .storage()
+            .select_user_settings(7)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.timezone, "Europe/Moscow");
+        assert_eq!(stored.language, "ru");
+        assert!(stored.quiet_reminders);
+    }
+
+    #[tokio::test]
+    async fn reminders_follow_user_settings() {
+        let (api, bot) = bot_with_saved_messages().await;
+        let mut settings = crate::models::UserSettings::new(7, None);
+        settings.timezone = "Europe/Moscow".to_string();
+        settings.quiet_reminders = true;
+        bot.storage().upsert_user_settings(&settings).await.unwrap();
+        api.push_updates(vec![from_ann(message_update(
+            4,
+            CHAT_ID,
+            4,
+            "/remind tomorrow 9:00 standup",
+        ))]);
+        bot.poll_updates().await;
+        assert!(api.sent_texts()[0].ends_with(" 09:00 MSK"));
+
+        let pending = bot.storage().select_reminders(CHAT_ID).await.unwrap();
+        assert_eq!(pending[0].due_at % 86400, 6 * 3600);
+        assert!(pending[0].silent);
+    }
+
+    #[tokio::test]
+    async fn reminder_button_reminds_of_the_open_message() {
+        let (api, bot) = bot_with_saved_messages().await;
+        api.push_updates(vec![message_update(4, CHAT_ID, 4, "/history")]);
+        bot.poll_updates().await;
+        api.push_updates(vec![callback_update(5, CHAT_ID, 1001, "/remind")]);
+        bot.poll_updates().await;
+        let presets = &api.calls_to("editMessageReplyMarkup")[0]["reply_markup"];
+        assert_eq!(
+            presets["inline_keyboard"][0][0]["callback_data"],
+            "/remind:1h"
+        );
+
+        api.push_updates(vec![callback_update(6, CHAT_ID, 1001, "/remind:1w")]);
+        bot.poll_updates().await;
+        let pending = bot.storage().select_reminders(CHAT_ID).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(
+            (pending[0].message_id, pending[0].text.as_str()),
+            (Some(1), "first")
+        );
+        assert!(api.sent_texts()[1].starts_with("Reminder #1 set for "));
+        assert_eq!(api.calls_to("editMessageText").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn logs_raw_updates() {
+        let (_api, bot) = bot_with_saved_messages().await;
+        let logged = bot.storage().select_logged_updates(2).await.unwrap();
+        assert_eq!(logged.len(), 2);
+        assert!(logged[0].body.contains("second"));
+    }
+
+    #[tokio::test]
+    async fn stores_offset_after_each_update() {
+        let (_api, bot) = bot_with_saved_messages().await;
+        assert_eq!(bot.storage().get_last_update().await.unwrap().update_id, 4);
+    }
+
+    #[tokio::test]
+    async fn sets_command_menus_per_scope_and_language() {
+        let (api, bot) = bot_with_saved_messages().await;
+        bot.set_commands().await.unwrap();
+
+        let calls = api.calls_to("setMyCommands");
+        assert_eq!(calls.len(), 6);
+        let menu = |scope: &str, language: Option<&str>| {
+            calls
+                .iter()
+                .find(|c| c["scope"]["type"] == scope && c["language_code"].as_str() == language)
+                .map(|c| c["commands"].as_array().unwrap().clone())
+                .unwrap()
+        };
+        let private = menu("all_private_chats", None);
+        assert_eq!(private[0]["command"], "history");
+        assert!(private.iter().any(|c| c["command"] == "forget"));
+        assert!(!menu("all_group_chats", None)
+            .iter()
+            .any(|c| c["command"] == "forget"));
+        assert_eq!(
+            menu("all_chat_administrators", Some("ru")).len(),
+            private.len()
+        );
+        assert_eq!(
+            menu("all_group_chats", Some("ru"))[1]["description"],
+            "Закрыть сообщение истории"
+        );
+        // every command of the router has a description in every language
+        for call in &calls {
+            for command in call["commands"].as_array().unwrap() {
+                let description = command["description"].as_str().unwrap();
+                assert!(!description.starts_with("command-"), "{}", description);
+            }
+        }
+    }
+}
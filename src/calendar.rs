@@ -0,0 +1,91 @@
+//! Inline month calendar used to jump the history browser to a date.
+
+use crate::i18n;
+use crate::keyboard::Keyboard;
+use crate::web::InlineKeyboardMarkup;
+use chrono::{Datelike, NaiveDate};
+
+/// Accepts `2022-06-23`, `23.06.2022` and `2022-06` (the first day of the month).
+pub fn parse_date(text: &str) -> Option<NaiveDate> {
+    let text = text.trim();
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(text, "%d.%m.%Y"))
+        .or_else(|_| NaiveDate::parse_from_str(&format!("{}-01", text), "%Y-%m-%d"))
+        .ok()
+}
+
+/// Unix seconds of the start of the day in UTC.
+pub fn day_start(date: NaiveDate) -> i64 {
+    date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp()
+}
+
+/// `« June 2022 »` header, one row per week starting on Monday and a row to go back to the browser.
+/// Days send `/jump:YYYY-MM-DD`, the arrows `/calendar:YYYY-MM` of the neighbouring month,
+/// the month title jumps to its first day.
+pub fn month_keyboard(year: i32, month: u32, language: &str) -> InlineKeyboardMarkup {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).expect("valid month");
+    let previous = first.pred_opt().unwrap_or(first);
+    let next = first
+        .checked_add_months(chrono::Months::new(1))
+        .unwrap_or(first);
+
+    let mut keyboard = Keyboard::new()
+        .button("«", format!("/calendar:{}", previous.format("%Y-%m")))
+        .button(
+            i18n::text(
+                language,
+                "calendar-month",
+                &[("month", month.into()), ("year", year.into())],
+            ),
+            format!("/jump:{}", first.format("%Y-%m-%d")),
+        )
+        .button("»", format!("/calendar:{}", next.format("%Y-%m")))
+        .row();
+
+    let empty = (" ".to_string(), "/ignore".to_string());
+    let mut cells = vec![empty.clone(); first.weekday().num_days_from_monday() as usize];
+    for day in first.iter_days().take_while(|d| d.month() == month) {
+        cells.push((
+            day.day().to_string(),
+            format!("/jump:{}", day.format("%Y-%m-%d")),
+        ));
+    }
+    cells.resize(cells.len().div_ceil(7) * 7, empty);
+    for week in cells.chunks(7) {
+        for (text, callback_data) in week {
+            keyboard = keyboard.button(text, callback_data);
+        }
+        keyboard = keyboard.row();
+    }
+    keyboard.row().button("↩", "/browse").build()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_supported_formats() {
+        let date = NaiveDate::from_ymd_opt(2022, 6, 23).unwrap();
+        assert_eq!(parse_date("2022-06-23"), Some(date));
+        assert_eq!(parse_date(" 23.06.2022 "), Some(date));
+        assert_eq!(parse_date("2022-06"), NaiveDate::from_ymd_opt(2022, 6, 1));
+        assert_eq!(parse_date("yesterday"), None);
+    }
+
+    #[test]
+    fn builds_month_grid() {
+        // June 2022 starts on Wednesday and has 30 days
+        let keyboard = month_keyboard(2022, 6, "en").inline_keyboard;
+        assert_eq!(keyboard[0][1].text, "June 2022");
+        assert_eq!(keyboard[0][0].callback_data, "/calendar:2022-05");
+        assert_eq!(keyboard[0][2].callback_data, "/calendar:2022-07");
+        assert_eq!(keyboard[1][2].callback_data, "/jump:2022-06-01");
+        assert_eq!(keyboard[1][1].callback_data, "/ignore");
+        assert_eq!(keyboard[5][3].callback_data, "/jump:2022-06-30");
+        assert!(keyboard[1..6].iter().all(|week| week.len() == 7));
+        assert_eq!(keyboard[6][0].callback_data, "/browse");
+        let keyboard = month_keyboard(2022, 12, "ru").inline_keyboard;
+        assert_eq!(keyboard[0][1].text, "Декабрь 2022");
+    }
+}
@@ -0,0 +1,530 @@
+//! Renders the saved history of a chat into the files `/export` sends. `write` goes through
+//! the history page by page, so the file is uploaded while it is written.
+
+use crate::models::{Message, MessageRevision};
+use crate::storage::Storage;
+use crate::web::MessageEntity;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+
+/// Messages read from the storage at once while writing a file.
+const PAGE: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+    Markdown,
+    Html,
+}
+
+impl Format {
+    /// `/export` arguments, JSON when none is given.
+    pub fn parse(args: &str) -> Option<Format> {
+        match args.trim().to_lowercase().as_str() {
+            "" | "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "md" | "markdown" => Some(Format::Markdown),
+            "html" => Some(Format::Html),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Csv => "csv",
+            Format::Markdown => "md",
+            Format::Html => "html",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Csv => "text/csv",
+            Format::Markdown => "text/markdown",
+            Format::Html => "text/html",
+        }
+    }
+}
+
+/// A saved message with what the export adds to it.
+#[derive(Debug, Clone)]
+pub struct ExportedMessage {
+    pub message: Message,
+    pub author: Option<String>,
+    /// Tag names without `#`.
+    pub tags: Vec<String>,
+    /// Earlier texts, oldest first, empty unless asked for.
+    pub revisions: Vec<MessageRevision>,
+}
+
+/// Looks up the authors and tags of messages of one chat.
+pub async fn annotate(
+    storage: &dyn Storage,
+    chat_id: i64,
+    messages: Vec<Message>,
+) -> Result<Vec<ExportedMessage>> {
+    Annotator::new(storage, chat_id)
+        .await?
+        .annotate(messages)
+        .await
+}
+
+/// Authors and tags for messages of one chat that come in pages, every author is looked up once.
+struct Annotator<'a> {
+    storage: &'a dyn Storage,
+    tags: HashMap<i64, Vec<String>>,
+    authors: HashMap<i64, Option<String>>,
+}
+
+impl<'a> Annotator<'a> {
+    async fn new(storage: &'a dyn Storage, chat_id: i64) -> Result<Annotator<'a>> {
+        let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+        for tag in storage.select_message_tags(chat_id).await? {
+            tags.entry(tag.message_id).or_default().push(tag.name);
+        }
+        Ok(Self {
+            storage,
+            tags,
+            authors: HashMap::new(),
+        })
+    }
+
+    async fn annotate(&mut self, messages: Vec<Message>) -> Result<Vec<ExportedMessage>> {
+        let mut exported = Vec::with_capacity(messages.len());
+        for message in messages {
+            let author = match message.from_id {
+                Some(from_id) => match self.authors.get(&from_id) {
+                    Some(author) => author.clone(),
+                    None => {
+                        let user = self.storage.select_user(from_id).await?;
+                        let author = user.map(|u| u.display_name());
+                        self.authors.insert(from_id, author.clone());
+                        author
+                    }
+                },
+                None => None,
+            };
+            exported.push(ExportedMessage {
+                tags: self.tags.remove(&message.message_id).unwrap_or_default(),
+                author,
+                message,
+                revisions: Vec::new(),
+            });
+        }
+        Ok(exported)
+    }
+}
+
+/// Writes the history of the chat with edit revisions into `chunks`, a page of messages
+/// per chunk. An error is passed on to `chunks` too, so the upload fails instead of
+/// sending a cut file.
+pub async fn write(
+    storage: &dyn Storage,
+    chat_id: i64,
+    format: Format,
+    chunks: mpsc::Sender<std::io::Result<Vec<u8>>>,
+) -> Result<()> {
+    let written = write_pages(storage, chat_id, format, &chunks).await;
+    if let Err(e) = &written {
+        let error = std::io::Error::other(e.to_string());
+        let _ = chunks.send(Err(error)).await;
+    }
+    written
+}
+
+async fn write_pages(
+    storage: &dyn Storage,
+    chat_id: i64,
+    format: Format,
+    chunks: &mpsc::Sender<std::io::Result<Vec<u8>>>,
+) -> Result<()> {
+    let mut annotator = Annotator::new(storage, chat_id).await?;
+    let mut chunk = head(format, chat_id);
+    let mut after = 0;
+    let mut first = true;
+    loop {
+        let page = storage.select_messages_after(chat_id, after, PAGE).await?;
+        let last_page = (page.len() as i64) < PAGE;
+        if let Some(last) = page.last() {
+            after = last.message_id;
+        }
+        for mut m in annotator.annotate(page).await? {
+            // only edits leave revisions behind
+            if m.message.edit_date.is_some() {
+                m.revisions = storage
+                    .select_revisions(chat_id, m.message.message_id)
+                    .await?;
+            }
+            chunk.push_str(&entry(format, &m, first));
+            first = false;
+        }
+        if last_page {
+            break;
+        }
+        if chunks.send(Ok(chunk.into_bytes())).await.is_err() {
+            // the upload gave up, it reports why
+            return Ok(());
+        }
+        chunk = String::new();
+    }
+    chunk.push_str(&tail(format));
+    let _ = chunks.send(Ok(chunk.into_bytes())).await;
+    Ok(())
+}
+
+/// One JSON record, dates in UTC ISO 8601.
+#[derive(Serialize)]
+struct JsonMessage<'a> {
+    message_id: i64,
+    date: String,
+    edit_date: Option<String>,
+    from_id: Option<i64>,
+    author: Option<&'a str>,
+    text: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    entities: Vec<MessageEntity>,
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    revisions: Vec<JsonRevision<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonRevision<'a> {
+    date: String,
+    text: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    entities: Vec<MessageEntity>,
+}
+
+/// The whole file at once, `write` produces the same in pages.
+pub fn render(format: Format, chat_id: i64, messages: &[ExportedMessage]) -> String {
+    let mut out = head(format, chat_id);
+    for (i, m) in messages.iter().enumerate() {
+        out.push_str(&entry(format, m, i == 0));
+    }
+    out.push_str(&tail(format));
+    out
+}
+
+/// Start of the file, before the first message.
+fn head(format: Format, chat_id: i64) -> String {
+    match format {
+        Format::Json => "[".to_string(),
+        Format::Csv => "message_id,date,edit_date,author,tags,text\r\n".to_string(),
+        Format::Markdown => format!("# History of chat {}\n", chat_id),
+        Format::Html => html_head(chat_id),
+    }
+}
+
+/// One message, `first` tells whether it opens the list.
+fn entry(format: Format, m: &ExportedMessage, first: bool) -> String {
+    match format {
+        Format::Json => json_entry(m, first),
+        Format::Csv => csv_entry(m),
+        Format::Markdown => markdown_entry(m),
+        Format::Html => html_entry(m),
+    }
+}
+
+fn tail(format: Format) -> String {
+    match format {
+        Format::Json => "\n]".to_string(),
+        Format::Csv | Format::Markdown => String::new(),
+        Format::Html => "</body>\n</html>\n".to_string(),
+    }
+}
+
+/// A record of the array as `serde_json::to_string_pretty` lays it out.
+fn json_entry(m: &ExportedMessage, first: bool) -> String {
+    let record = JsonMessage {
+        message_id: m.message.message_id,
+        date: iso_date(m.message.date),
+        edit_date: m.message.edit_date.map(iso_date),
+        from_id: m.message.from_id,
+        author: m.author.as_deref(),
+        text: &m.message.text,
+        entities: m.message.entities(),
+        tags: &m.tags,
+        revisions: m
+            .revisions
+            .iter()
+            .map(|r| JsonRevision {
+                date: iso_date(r.date),
+                text: &r.text,
+                entities: r.entities(),
+            })
+            .collect(),
+    };
+    let record = serde_json::to_string_pretty(&record).unwrap_or_default();
+    let mut out = String::from(if first { "\n" } else { ",\n" });
+    let lines: Vec<String> = record.lines().map(|line| format!("  {}", line)).collect();
+    out.push_str(&lines.join("\n"));
+    out
+}
+
+fn csv_entry(m: &ExportedMessage) -> String {
+    let fields = [
+        m.message.message_id.to_string(),
+        iso_date(m.message.date),
+        m.message.edit_date.map(iso_date).unwrap_or_default(),
+        m.author.clone().unwrap_or_default(),
+        hashtags(&m.tags),
+        m.message.text.clone(),
+    ];
+    let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+/// Quotes fields with separators, quotes or line breaks, doubling inner quotes.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn markdown_entry(m: &ExportedMessage) -> String {
+    let mut out = format!("\n## {}\n\n", heading(m));
+    if !m.tags.is_empty() {
+        out.push_str(&format!("{}\n\n", hashtags(&m.tags)));
+    }
+    // quoted, like the revisions below, so saved text can't open headings of its own
+    out.push_str(&format!("> {}\n", quote(&m.message.text)));
+    if !m.revisions.is_empty() {
+        out.push_str("\nEarlier versions:\n");
+        for revision in &m.revisions {
+            out.push_str(&format!(
+                "\n> {}\n>\n> {}\n",
+                human_date(revision.date),
+                quote(&revision.text)
+            ));
+        }
+    }
+    out
+}
+
+/// Continues a markdown blockquote over every line of the text.
+fn quote(text: &str) -> String {
+    text.replace('\n', "\n> ")
+}
+
+fn html_head(chat_id: i64) -> String {
+    let title = format!("History of chat {}", chat_id);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\nbody {{ font-family: sans-serif; max-width: 40em; margin: 2em auto; }}\n\
+         article {{ border-bottom: 1px solid #ddd; padding: 1em 0; }}\n\
+         header, .tags, summary {{ color: #777; font-size: 0.9em; }}\n\
+         p {{ white-space: pre-wrap; }}\n</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, title
+    )
+}
+
+fn html_entry(m: &ExportedMessage) -> String {
+    let mut out = String::from("<article>\n");
+    out.push_str(&format!("<header>{}</header>\n", escape(&heading(m))));
+    if !m.tags.is_empty() {
+        let tags = escape(&hashtags(&m.tags));
+        out.push_str(&format!("<div class=\"tags\">{}</div>\n", tags));
+    }
+    out.push_str(&format!("<p>{}</p>\n", escape(&m.message.text)));
+    if !m.revisions.is_empty() {
+        out.push_str("<details>\n<summary>Earlier versions</summary>\n");
+        for revision in &m.revisions {
+            out.push_str(&format!(
+                "<header>{}</header>\n<p>{}</p>\n",
+                human_date(revision.date),
+                escape(&revision.text)
+            ));
+        }
+        out.push_str("</details>\n");
+    }
+    out.push_str("</article>\n");
+    out
+}
+
+/// `Ann · 2022-06-23 12:00 UTC (edited 2022-06-23 12:05 UTC)`
+fn heading(m: &ExportedMessage) -> String {
+    let mut heading = match &m.author {
+        Some(author) => format!("{} · {}", author, human_date(m.message.date)),
+        None => human_date(m.message.date),
+    };
+    if let Some(edit_date) = m.message.edit_date {
+        heading.push_str(&format!(" (edited {})", human_date(edit_date)));
+    }
+    heading
+}
+
+fn hashtags(tags: &[String]) -> String {
+    let tags: Vec<String> = tags.iter().map(|t| format!("#{}", t)).collect();
+    tags.join(" ")
+}
+
+/// Escapes text for HTML and XML.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub(crate) fn iso_date(timestamp: i64) -> String {
+    format_date(timestamp, "%Y-%m-%dT%H:%M:%SZ")
+}
+
+pub(crate) fn human_date(timestamp: i64) -> String {
+    format_date(timestamp, "%Y-%m-%d %H:%M UTC")
+}
+
+fn format_date(timestamp: i64, format: &str) -> String {
+    DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .map(|date| date.format(format).to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn exported() -> Vec<ExportedMessage> {
+        let mut first = Message {
+            text: "hello, \"world\"".to_string(),
+            chat_id: 42,
+            message_id: 1,
+            date: 1656000000,
+            edit_date: Some(1656000300),
+            from_id: Some(7),
+            entities: None,
+            deleted_at: None,
+        };
+        let second = Message {
+            text: "<b>not bold</b>".to_string(),
+            message_id: 2,
+            edit_date: None,
+            ..first.clone()
+        };
+        first.from_id = None;
+        vec![
+            ExportedMessage {
+                message: first,
+                author: None,
+                tags: vec!["work".to_string(), "later".to_string()],
+                revisions: vec![MessageRevision {
+                    chat_id: 42,
+                    message_id: 1,
+                    text: "hello".to_string(),
+                    entities: None,
+                    date: 1656000000,
+                }],
+            },
+            ExportedMessage {
+                message: second,
+                author: Some("Ann".to_string()),
+                tags: vec![],
+                revisions: vec![],
+            },
+        ]
+    }
+
+    #[test]
+    fn parses_formats() {
+        assert_eq!(Format::parse(""), Some(Format::Json));
+        assert_eq!(Format::parse(" MD "), Some(Format::Markdown));
+        assert_eq!(Format::parse("pdf"), None);
+    }
+
+    #[test]
+    fn renders_json() {
+        let json: serde_json::Value =
+            serde_json::from_str(&render(Format::Json, 42, &exported())).unwrap();
+        assert_eq!(json[0]["date"], "2022-06-23T16:00:00Z");
+        assert_eq!(json[0]["edit_date"], "2022-06-23T16:05:00Z");
+        assert_eq!(json[0]["tags"][1], "later");
+        assert_eq!(json[1]["author"], "Ann");
+        assert!(json[1]["edit_date"].is_null());
+        assert_eq!(json[0]["revisions"][0]["text"], "hello");
+        assert_eq!(json[0]["revisions"][0]["date"], "2022-06-23T16:00:00Z");
+        assert!(json[1].get("revisions").is_none());
+    }
+
+    #[test]
+    fn renders_csv() {
+        let csv = render(Format::Csv, 42, &exported());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "message_id,date,edit_date,author,tags,text");
+        assert_eq!(
+            lines[1],
+            "1,2022-06-23T16:00:00Z,2022-06-23T16:05:00Z,,#work #later,\"hello, \"\"world\"\"\""
+        );
+    }
+
+    #[test]
+    fn renders_markdown_and_html() {
+        let md = render(Format::Markdown, 42, &exported());
+        assert!(md.starts_with("# History of chat 42\n"));
+        assert!(
+            md.contains("## 2022-06-23 16:00 UTC (edited 2022-06-23 16:05 UTC)\n\n#work #later\n")
+        );
+        assert!(md.contains("\n\n> hello, \"world\"\n\nEarlier versions:\n"));
+        assert!(md.contains("\nEarlier versions:\n\n> 2022-06-23 16:00 UTC\n>\n> hello\n"));
+        let html = render(Format::Html, 42, &exported());
+        assert!(html.contains("<header>Ann · 2022-06-23 16:00 UTC</header>"));
+        assert!(html.contains("<p>&lt;b&gt;not bold&lt;/b&gt;</p>"));
+        assert!(html.contains(
+            "<summary>Earlier versions</summary>\n<header>2022-06-23 16:00 UTC</header>\n<p>hello</p>"
+        ));
+        assert!(html.ends_with("</body>\n</html>\n"));
+    }
+
+    #[test]
+    fn quotes_markdown_headings_in_messages() {
+        let mut messages = exported();
+        messages[1].message.text = "# not a title\n## nor a section".to_string();
+        let md = render(Format::Markdown, 42, &messages);
+        assert!(md.contains("\n\n> # not a title\n> ## nor a section\n"));
+        let headings = md
+            .lines()
+            .filter(|l| l.starts_with("# ") || l.starts_with("## "));
+        assert_eq!(headings.count(), 3);
+    }
+
+    #[tokio::test]
+    async fn writes_history_in_pages() {
+        use crate::models::EditedMessage;
+        use crate::storage::MemoryStorage;
+
+        let storage = MemoryStorage::new();
+        for message_id in 1..=PAGE + 1 {
+            let message = Message::new(format!("note {}", message_id), 42, message_id).await;
+            storage.insert_message(&message).await.unwrap();
+        }
+        let edited = EditedMessage::new(3, "edited".to_string(), 42, Some(100)).await;
+        storage.change_message_text(&edited).await.unwrap();
+
+        let (sender, mut receiver) = mpsc::channel::<std::io::Result<Vec<u8>>>(1);
+        let read = async {
+            let mut chunks = Vec::new();
+            while let Some(chunk) = receiver.recv().await {
+                chunks.push(chunk.unwrap());
+            }
+            chunks
+        };
+        let (written, chunks) = tokio::join!(write(&storage, 42, Format::Json, sender), read);
+        written.unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        let json: serde_json::Value = serde_json::from_slice(&chunks.concat()).unwrap();
+        let records = json.as_array().unwrap();
+        assert_eq!(records.len(), PAGE as usize + 1);
+        assert_eq!(records[2]["text"], "edited");
+        assert_eq!(records[2]["revisions"][0]["text"], "note 3");
+        assert_eq!(records[PAGE as usize]["text"], format!("note {}", PAGE + 1));
+    }
+}
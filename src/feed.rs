@@ -0,0 +1,144 @@
+//! Atom feed of the saved messages of a chat, turned on with `/feed`.
+
+use crate::export::{escape, iso_date, ExportedMessage};
+use crate::models::ChatFeed;
+use crate::pages;
+
+/// Messages a feed shows, the newest ones.
+pub const ENTRIES: i64 = 50;
+
+const ENTRY_TITLE_CHARS: usize = 80;
+
+/// `PUBLIC_URL`, the address of the bot's HTTP server, when this process runs that server
+/// (`API_ADDR` is set). Without both a feed link would lead nowhere.
+pub fn public_url() -> Option<String> {
+    let served = dotenv::var("API_ADDR").is_ok_and(|addr| !addr.is_empty());
+    let base = dotenv::var("PUBLIC_URL").unwrap_or_default();
+    (served && !base.is_empty()).then_some(base)
+}
+
+/// Where the feed with `token` is served on the server at `base`, just the path when `base` is empty.
+pub fn url(base: &str, token: &str) -> String {
+    format!("{}/feed/{}", base.trim_end_matches('/'), token)
+}
+
+/// Renders `messages`, newest first, as an Atom 1.0 document.
+pub fn render(feed: &ChatFeed, self_url: &str, messages: &[ExportedMessage]) -> String {
+    let title = match &feed.title {
+        Some(title) => format!("Saved messages of {}", title),
+        None => format!("Saved messages of chat {}", feed.chat_id),
+    };
+    let updated = messages
+        .iter()
+        .map(updated_at)
+        .max()
+        .unwrap_or(feed.created_at);
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         <id>urn:tgbot:chat:{}</id>\n\
+         <title>{}</title>\n\
+         <updated>{}</updated>\n\
+         <link rel=\"self\" href=\"{}\"/>\n\
+         <author><name>{}</name></author>\n",
+        feed.chat_id,
+        escape(&title),
+        iso_date(updated),
+        escape(self_url),
+        escape(feed.title.as_deref().unwrap_or("tgbot")),
+    );
+    for m in messages {
+        out.push_str("<entry>\n");
+        out.push_str(&format!(
+            "<id>urn:tgbot:chat:{}:message:{}</id>\n",
+            feed.chat_id, m.message.message_id
+        ));
+        out.push_str(&format!(
+            "<title>{}</title>\n",
+            escape(&pages::headline(&m.message.text, ENTRY_TITLE_CHARS))
+        ));
+        out.push_str(&format!(
+            "<published>{}</published>\n",
+            iso_date(m.message.date)
+        ));
+        out.push_str(&format!("<updated>{}</updated>\n", iso_date(updated_at(m))));
+        if let Some(author) = &m.author {
+            out.push_str(&format!(
+                "<author><name>{}</name></author>\n",
+                escape(author)
+            ));
+        }
+        for tag in &m.tags {
+            out.push_str(&format!("<category term=\"{}\"/>\n", escape(tag)));
+        }
+        out.push_str(&format!(
+            "<content type=\"text\">{}</content>\n",
+            escape(&m.message.text)
+        ));
+        out.push_str("</entry>\n");
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+fn updated_at(m: &ExportedMessage) -> i64 {
+    m.message.edit_date.unwrap_or(m.message.date)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::Message;
+
+    fn feed() -> ChatFeed {
+        ChatFeed {
+            chat_id: 42,
+            token: "secret".to_string(),
+            title: Some("Notes & ideas".to_string()),
+            created_at: 1600000000,
+        }
+    }
+
+    fn entry(message_id: i64, text: &str, date: i64, edit_date: Option<i64>) -> ExportedMessage {
+        ExportedMessage {
+            message: Message {
+                text: text.to_string(),
+                chat_id: 42,
+                message_id,
+                date,
+                edit_date,
+                from_id: None,
+                entities: None,
+                deleted_at: None,
+            },
+            author: None,
+            tags: vec![],
+            revisions: vec![],
+        }
+    }
+
+    #[test]
+    fn renders_entries() {
+        let mut second = entry(2, "first line\n<second> line", 1656000000, Some(1656000300));
+        second.author = Some("Ann".to_string());
+        second.tags = vec!["work".to_string()];
+        let messages = vec![second, entry(1, "older", 1655000000, None)];
+        let atom = render(&feed(), "https://bot.example/feed/secret", &messages);
+
+        assert!(atom.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed"));
+        assert!(atom.contains("<title>Saved messages of Notes &amp; ideas</title>"));
+        assert!(atom.contains("<updated>2022-06-23T16:05:00Z</updated>\n<link rel=\"self\""));
+        assert!(atom.contains("<id>urn:tgbot:chat:42:message:2</id>\n<title>first line</title>"));
+        assert!(atom.contains("<author><name>Ann</name></author>\n<category term=\"work\"/>"));
+        assert!(atom.contains("<content type=\"text\">first line\n&lt;second&gt; line</content>"));
+        assert_eq!(atom.matches("<entry>").count(), 2);
+        assert!(atom.ends_with("</feed>\n"));
+    }
+
+    #[test]
+    fn empty_feed_is_updated_when_created() {
+        let atom = render(&feed(), "/feed/secret", &[]);
+        assert!(atom.contains("<updated>2020-09-13T12:26:40Z</updated>"));
+        assert!(!atom.contains("<entry>"));
+    }
+}
@@ -0,0 +1,1260 @@
+//! Handlers of the history browser registered by `BotBuilder::with_default_handlers`.
+
+use crate::api;
+use crate::calendar;
+use crate::export;
+use crate::feed;
+use crate::import;
+use crate::keyboard;
+use crate::models::{
+    Chat, ChatFeed, EditedMessage, Filter, LinkMessage, Message, PurgeRecord, Reminder, User,
+};
+use crate::pages;
+use crate::reminders;
+use crate::router::{Context, Handler};
+use crate::settings;
+use crate::storage;
+use crate::tags;
+use crate::tg_service::TgClient;
+use crate::web::WMessage;
+use crate::when::{self, Direction};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Utc};
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Saves every plain text message of the chat together with its chat, author and hashtags.
+pub struct SaveMessage;
+
+#[async_trait]
+impl Handler for SaveMessage {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(wm) = &ctx.update.message {
+            if wm.text.is_empty() {
+                return Ok(());
+            }
+            Chat::from(&wm.chat).upsert(ctx.storage).await?;
+            if let Some(from) = &wm.from {
+                User::from(from).upsert(ctx.storage).await?;
+            }
+            Message::from(wm).insert(ctx.storage).await?;
+            save_tags(ctx, wm.chat.id, wm.message_id, &wm.text).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the saved copy in sync when the user edits a message, hashtags added by the edit
+/// tag the message too.
+pub struct EditMessage;
+
+#[async_trait]
+impl Handler for EditMessage {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(wem) = &ctx.update.edited_message {
+            EditedMessage::from(wem)
+                .change_message_text(ctx.storage)
+                .await?;
+            save_tags(ctx, wem.chat.id, wem.message_id, &wem.text).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn save_tags(ctx: &Context<'_>, chat_id: i64, message_id: i64, text: &str) -> Result<()> {
+    let tags = tags::extract(text);
+    if tags.is_empty() {
+        return Ok(());
+    }
+    ctx.storage.add_tags(chat_id, message_id, &tags).await
+}
+
+/// `/tag work later` tags the message it replies to, or the one open in the browser.
+pub struct Tag;
+
+#[async_trait]
+impl Handler for Tag {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        let wm = match &ctx.update.message {
+            Some(wm) => wm,
+            None => return Ok(()),
+        };
+        let chat_id = wm.chat.id;
+        let tags = match tags::parse_args(ctx.args) {
+            Some(tags) => tags,
+            None => {
+                let text = ctx.text("tag-usage", &[]);
+                ctx.tg_client.send_message(chat_id, text).await?;
+                return Ok(());
+            }
+        };
+        let message_id = match &wm.reply_to_message {
+            Some(reply) => Some(reply.message_id),
+            None => ctx
+                .storage
+                .select_link(chat_id)
+                .await
+                .ok()
+                .map(|l| l.message_id),
+        };
+        let text = match message_id {
+            Some(message_id) => {
+                ctx.storage.add_tags(chat_id, message_id, &tags).await?;
+                let names: Vec<String> = tags.iter().map(|t| format!("#{}", t)).collect();
+                ctx.text("tag-done", &[("tags", names.join(" ").into())])
+            }
+            None => ctx.text("tag-no-message", &[]),
+        };
+        ctx.tg_client.send_message(chat_id, text).await?;
+        Ok(())
+    }
+}
+
+/// `/favorites` opens the browser over starred messages only.
+pub struct Favorites;
+
+#[async_trait]
+impl Handler for Favorites {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(chat_id) = ctx.chat_id() {
+            let empty = ctx.text("favorites-empty", &[]);
+            browse(ctx, chat_id, &Filter::Favorites, "favorites_link", &empty).await?;
+        }
+        Ok(())
+    }
+}
+
+/// `/star:N` stars the message open in the browser or takes the star back,
+/// then redraws page N so the button shows the new state.
+pub struct Star;
+
+#[async_trait]
+impl Handler for Star {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let (Some(chat_id), Some(message_id)) = (ctx.chat_id(), ctx.callback_message_id()) {
+            let link = ctx.storage.select_link(chat_id).await?;
+            ctx.storage
+                .toggle_favorite(chat_id, link.message_id)
+                .await?;
+            let page = ctx.args.parse::<usize>().unwrap_or(1);
+            show_page(ctx, chat_id, message_id, page).await?;
+        }
+        Ok(())
+    }
+}
+
+/// How long after a delete its undo button still works, in seconds.
+const UNDO_SECONDS: i64 = 60;
+
+/// Moves the message open in the browser to the trash and shows its neighbour
+/// with an undo button.
+pub struct Delete;
+
+#[async_trait]
+impl Handler for Delete {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(chat_id) = ctx.chat_id() {
+            let link = ctx.storage.select_link(chat_id).await?;
+            let filter = link.filter();
+            ctx.storage
+                .delete_message(chat_id, link.message_id, Utc::now().timestamp())
+                .await?;
+            match neighbour(ctx, chat_id, &filter, link.message_id).await? {
+                Some(message) => {
+                    let mut buttons = TgClient::history_buttons(
+                        ctx.storage,
+                        chat_id,
+                        &filter,
+                        &message,
+                        ctx.language(),
+                    )
+                    .await?;
+                    buttons.reply_markup =
+                        keyboard::with_undo(buttons.reply_markup, link.message_id, ctx.language());
+                    ctx.tg_client
+                        .show_history_buttons(
+                            ctx.storage,
+                            &message,
+                            &filter,
+                            "delete_link",
+                            buttons,
+                        )
+                        .await?;
+                }
+                None => {
+                    ctx.tg_client.exit(ctx.storage, chat_id).await;
+                    let text = ctx.text("delete-last", &[]);
+                    ctx.tg_client.send_message(chat_id, text).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `/undo:N` takes the message N back from the trash shortly after it was deleted.
+pub struct Undo;
+
+#[async_trait]
+impl Handler for Undo {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let (Some(chat_id), Ok(message_id)) = (ctx.chat_id(), ctx.args.parse::<i64>()) {
+            let message = ctx.storage.select_message(chat_id, message_id).await?;
+            match message.deleted_at {
+                Some(deleted_at) if Utc::now().timestamp() - deleted_at <= UNDO_SECONDS => {
+                    ctx.storage.restore_message(chat_id, message_id).await?;
+                    let filter = browsing_filter(ctx, chat_id).await;
+                    ctx.tg_client
+                        .show_history_message(
+                            ctx.storage,
+                            chat_id,
+                            &message,
+                            &filter,
+                            "undo_link",
+                            ctx.language(),
+                        )
+                        .await?;
+                }
+                Some(_) => {
+                    let text = ctx.text("undo-too-late", &[]);
+                    ctx.tg_client.send_message(chat_id, text).await?;
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `/trash` opens the browser over deleted messages.
+pub struct Trash;
+
+#[async_trait]
+impl Handler for Trash {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(chat_id) = ctx.chat_id() {
+            let empty = ctx.text("trash-empty", &[]);
+            browse(ctx, chat_id, &Filter::Trash, "trash_link", &empty).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Puts the deleted message open in the browser back into the history.
+/// Groups take it from administrators only.
+pub struct Restore;
+
+#[async_trait]
+impl Handler for Restore {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let (Some(chat_id), Some(message_id)) = (ctx.chat_id(), ctx.callback_message_id()) {
+            if !ctx.is_admin().await? {
+                return admin_only(ctx, chat_id).await;
+            }
+            let link = match trash_link(ctx, chat_id, message_id).await? {
+                Some(link) => link,
+                None => return trash_closed(ctx, chat_id).await,
+            };
+            ctx.storage
+                .restore_message(chat_id, link.message_id)
+                .await?;
+            leave_trashed(ctx, chat_id, link.message_id).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Deletes the message open in the trash browser for good.
+/// Groups take it from administrators only.
+pub struct Purge;
+
+#[async_trait]
+impl Handler for Purge {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let (Some(chat_id), Some(message_id)) = (ctx.chat_id(), ctx.callback_message_id()) {
+            if !ctx.is_admin().await? {
+                return admin_only(ctx, chat_id).await;
+            }
+            let link = match trash_link(ctx, chat_id, message_id).await? {
+                Some(link) => link,
+                None => return trash_closed(ctx, chat_id).await,
+            };
+            ctx.storage.purge_message(chat_id, link.message_id).await?;
+            leave_trashed(ctx, chat_id, link.message_id).await?;
+        }
+        Ok(())
+    }
+}
+
+/// The link of the browser `message_id` when it is the open trash browser,
+/// buttons of an older browser or of the history must not touch the trash.
+async fn trash_link(
+    ctx: &Context<'_>,
+    chat_id: i64,
+    message_id: i64,
+) -> Result<Option<LinkMessage>> {
+    match ctx.storage.select_link(chat_id).await {
+        Ok(link) if link.id == message_id && link.filter() == Filter::Trash => Ok(Some(link)),
+        Ok(_) => Ok(None),
+        Err(e) if storage::is_not_found(&e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+async fn trash_closed(ctx: &Context<'_>, chat_id: i64) -> Result<()> {
+    let text = ctx.text("trash-closed", &[]);
+    ctx.tg_client.send_message(chat_id, text).await?;
+    Ok(())
+}
+
+/// Moves the trash browser on from `message_id`, closes it once the trash is empty.
+async fn leave_trashed(ctx: &Context<'_>, chat_id: i64, message_id: i64) -> Result<()> {
+    match neighbour(ctx, chat_id, &Filter::Trash, message_id).await? {
+        Some(message) => {
+            ctx.tg_client
+                .show_history_message(
+                    ctx.storage,
+                    chat_id,
+                    &message,
+                    &Filter::Trash,
+                    "trash_link",
+                    ctx.language(),
+                )
+                .await
+        }
+        None => {
+            ctx.tg_client.exit(ctx.storage, chat_id).await;
+            ctx.tg_client
+                .send_message(chat_id, ctx.text("trash-empty", &[]))
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+/// The message after `message_id` passing `filter`, or the one before it at the end.
+async fn neighbour(
+    ctx: &Context<'_>,
+    chat_id: i64,
+    filter: &Filter,
+    message_id: i64,
+) -> Result<Option<Message>> {
+    let found = match ctx
+        .storage
+        .select_next_message(chat_id, filter, message_id)
+        .await
+    {
+        Err(e) if storage::is_not_found(&e) => {
+            ctx.storage
+                .select_previous_message(chat_id, filter, message_id)
+                .await
+        }
+        found => found,
+    };
+    match found {
+        Ok(message) => Ok(Some(message)),
+        Err(e) if storage::is_not_found(&e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// `/forget` asks to confirm before everything the bot keeps about the chat is deleted.
+pub struct Forget;
+
+#[async_trait]
+impl Handler for Forget {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(chat_id) = ctx.chat_id() {
+            if !ctx.is_admin().await? {
+                return admin_only(ctx, chat_id).await;
+            }
+            let text = ctx.text("forget-question", &[]);
+            let keyboard = keyboard::Keyboard::new()
+                .button(ctx.text("forget-confirm", &[]), "/forget:yes")
+                .button(ctx.text("forget-cancel", &[]), "/forget:no")
+                .build();
+            let buttons = TgClient::create_buttons(chat_id, text, keyboard).await;
+            ctx.tg_client
+                .call::<_, WMessage>("sendMessage", &buttons)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Answer to the `/forget` question: `/forget:yes` closes the browser, purges the chat
+/// and records who did it, anything else only removes the question.
+pub struct ConfirmForget;
+
+#[async_trait]
+impl Handler for ConfirmForget {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let (Some(chat_id), Some(message_id)) = (ctx.chat_id(), ctx.callback_message_id()) {
+            // the question stays for the administrators
+            if !ctx.is_admin().await? {
+                return admin_only(ctx, chat_id).await;
+            }
+            if let Err(e) = ctx.tg_client.delete_message(chat_id, message_id).await {
+                eprintln!("{:?}", e)
+            }
+            if ctx.args != "yes" {
+                return Ok(());
+            }
+            ctx.tg_client.exit(ctx.storage, chat_id).await;
+            let messages = ctx.storage.purge_chat(chat_id).await?;
+            let record = PurgeRecord {
+                chat_id,
+                user_id: ctx.update.user_id(),
+                messages: messages as i64,
+                purged_at: Utc::now().timestamp(),
+            };
+            ctx.storage.log_purge(&record).await?;
+            let text = ctx.text("forget-done", &[("count", messages.into())]);
+            ctx.tg_client.send_message(chat_id, text).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Refuses a command that changes the whole chat to a group member who isn't an administrator.
+async fn admin_only(ctx: &Context<'_>, chat_id: i64) -> Result<()> {
+    let text = ctx.text("admin-only", &[]);
+    ctx.tg_client.send_message(chat_id, text).await?;
+    Ok(())
+}
+
+/// `/export csv` sends the saved history of the chat as a file: `json`, `csv`, `md` or `html`,
+/// without an argument in the format picked in `/settings`. Edited messages come with
+/// their earlier texts.
+pub struct Export;
+
+#[async_trait]
+impl Handler for Export {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        let chat_id = match ctx.chat_id() {
+            Some(chat_id) => chat_id,
+            None => return Ok(()),
+        };
+        let args = match ctx.args {
+            "" => ctx.settings.export_format.as_str(),
+            args => args,
+        };
+        let format = match export::Format::parse(args) {
+            Some(format) => format,
+            None => {
+                let text = ctx.text("export-usage", &[]);
+                ctx.tg_client.send_message(chat_id, text).await?;
+                return Ok(());
+            }
+        };
+        if ctx.storage.count_messages(chat_id, &Filter::All).await? == 0 {
+            let text = ctx.text("export-empty", &[]);
+            ctx.tg_client.send_message(chat_id, text).await?;
+            return Ok(());
+        }
+        // a page is written while the previous one is uploaded
+        let (sender, receiver) = tokio::sync::mpsc::channel(1);
+        let file_name = format!("history-{}.{}", chat_id, format.extension());
+        let upload = ctx.tg_client.send_document(
+            chat_id,
+            file_name,
+            format.mime(),
+            ReceiverStream::new(receiver),
+        );
+        let write = export::write(ctx.storage, chat_id, format, sender);
+        let (uploaded, written) = tokio::join!(upload, write);
+        written?;
+        uploaded
+    }
+}
+
+/// Largest file the Bot API lets bots download.
+const MAX_DOWNLOAD_SIZE: i64 = 20 * 1024 * 1024;
+
+/// `/import` as the caption of `result.json` from Telegram Desktop, or as a reply to it,
+/// saves the exported messages into this chat with their original ids, dates and authors.
+/// Messages saved before are skipped, so importing the same file twice is harmless,
+/// and messages whose ids are taken by other saved messages are reported, not imported.
+/// Groups take imports from administrators only.
+pub struct Import;
+
+#[async_trait]
+impl Handler for Import {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        let wm = match &ctx.update.message {
+            Some(wm) => wm,
+            None => return Ok(()),
+        };
+        let chat_id = wm.chat.id;
+        if !ctx.is_admin().await? {
+            return admin_only(ctx, chat_id).await;
+        }
+        let reply = wm.reply_to_message.as_deref();
+        let document = match wm
+            .document
+            .as_ref()
+            .or(reply.and_then(|r| r.document.as_ref()))
+        {
+            Some(document) => document,
+            None => {
+                let text = ctx.text("import-usage", &[]);
+                ctx.tg_client.send_message(chat_id, text).await?;
+                return Ok(());
+            }
+        };
+        if document.file_size.unwrap_or(0) > MAX_DOWNLOAD_SIZE {
+            let text = ctx.text("import-too-big", &[("chat", chat_id.to_string().into())]);
+            ctx.tg_client.send_message(chat_id, text).await?;
+            return Ok(());
+        }
+        let content = ctx.tg_client.download_file(&document.file_id).await?;
+        let parsed = match import::parse(&String::from_utf8_lossy(&content), chat_id) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                let text = ctx.text("import-unreadable", &[("error", e.to_string().into())]);
+                ctx.tg_client.send_message(chat_id, text).await?;
+                return Ok(());
+            }
+        };
+        Chat::from(&wm.chat).upsert(ctx.storage).await?;
+        let report = import::save(ctx.storage, &parsed).await?;
+        let mut text = ctx.text(
+            "import-done",
+            &[
+                ("imported", report.imported.into()),
+                ("existing", report.existing.into()),
+                ("skipped", report.skipped.into()),
+            ],
+        );
+        if !report.conflicts.is_empty() {
+            let ids: Vec<_> = report
+                .conflicts
+                .iter()
+                .map(|id| format!("#{}", id))
+                .collect();
+            let args = [
+                ("count", report.conflicts.len().into()),
+                ("ids", ids.join(", ").into()),
+            ];
+            text = format!("{}\n{}", text, ctx.text("import-conflicts", &args));
+        }
+        ctx.tg_client.send_message(chat_id, text).await?;
+        Ok(())
+    }
+}
+
+/// `/apikey` issues a key for the REST API that reads this chat, `/apikey revoke`
+/// disables every key issued here. Both are for administrators in groups, and the key
+/// of a group goes to the administrator in a private message.
+pub struct ApiKeys;
+
+#[async_trait]
+impl Handler for ApiKeys {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        let chat_id = match ctx.chat_id() {
+            Some(chat_id) => chat_id,
+            None => return Ok(()),
+        };
+        if !ctx.is_admin().await? {
+            return admin_only(ctx, chat_id).await;
+        }
+        if ctx.args == "revoke" {
+            let revoked = ctx.storage.revoke_api_keys(chat_id).await?;
+            let text = ctx.text("apikey-revoked", &[("count", revoked.into())]);
+            ctx.tg_client.send_message(chat_id, text).await?;
+            return Ok(());
+        }
+        let api_key = api::new_key(chat_id);
+        let user_id = match ctx.update.user_id() {
+            Some(user_id) if user_id != chat_id => user_id,
+            _ => {
+                let text = ctx.text("apikey-issued", &[("key", api_key.key.as_str().into())]);
+                ctx.tg_client.send_message(chat_id, text).await?;
+                return ctx.storage.insert_api_key(&api_key).await;
+            }
+        };
+        let title = ctx
+            .update
+            .message
+            .as_ref()
+            .and_then(|wm| wm.chat.title.clone())
+            .unwrap_or_else(|| chat_id.to_string());
+        let text = ctx.text(
+            "apikey-issued-group",
+            &[
+                ("key", api_key.key.as_str().into()),
+                ("title", title.into()),
+            ],
+        );
+        // the key is stored only once it reached the administrator
+        let text = match ctx.tg_client.send_message(user_id, text).await {
+            Ok(_) => {
+                ctx.storage.insert_api_key(&api_key).await?;
+                ctx.text("apikey-sent", &[])
+            }
+            Err(e) => {
+                eprintln!("{:?}", e);
+                ctx.text("apikey-start-private", &[])
+            }
+        };
+        ctx.tg_client.send_message(chat_id, text).await?;
+        Ok(())
+    }
+}
+
+/// `/feed` turns on an Atom feed of the chat at a secret URL, `/feed new` replaces
+/// the URL with a fresh one and `/feed off` turns the feed off. In groups only
+/// administrators change the feed. Feeds are only handed out when the bot knows
+/// the public address of its HTTP server.
+pub struct Feed {
+    public_url: Option<String>,
+}
+
+impl Feed {
+    pub fn new(public_url: Option<String>) -> Self {
+        Self { public_url }
+    }
+
+    /// Links to `feed::public_url`.
+    pub fn from_env() -> Self {
+        Self::new(feed::public_url())
+    }
+}
+
+#[async_trait]
+impl Handler for Feed {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        let chat_id = match ctx.chat_id() {
+            Some(chat_id) => chat_id,
+            None => return Ok(()),
+        };
+        if !ctx.is_admin().await? {
+            return admin_only(ctx, chat_id).await;
+        }
+        let text = match ctx.args {
+            "off" => {
+                if ctx.storage.delete_feed(chat_id).await? {
+                    ctx.text("feed-off", &[])
+                } else {
+                    ctx.text("feed-none", &[])
+                }
+            }
+            "new" | "" if self.public_url.is_none() => ctx.text("feed-unavailable", &[]),
+            "new" | "" => {
+                if ctx.args == "new" {
+                    ctx.storage.delete_feed(chat_id).await?;
+                }
+                let feed = match ctx.storage.select_chat_feed(chat_id).await? {
+                    Some(feed) => feed,
+                    None => {
+                        let chat = ctx.update.message.as_ref().map(|wm| &wm.chat);
+                        let feed = ChatFeed {
+                            chat_id,
+                            token: api::random_token(),
+                            title: chat
+                                .and_then(|c| c.title.clone().or_else(|| c.username.clone())),
+                            created_at: Utc::now().timestamp(),
+                        };
+                        ctx.storage.upsert_feed(&feed).await?;
+                        feed
+                    }
+                };
+                let base = self.public_url.as_deref().unwrap_or_default();
+                ctx.text("feed-on", &[("url", feed::url(base, &feed.token).into())])
+            }
+            _ => ctx.text("feed-usage", &[]),
+        };
+        ctx.tg_client.send_message(chat_id, text).await?;
+        Ok(())
+    }
+}
+
+/// `/remind in 2 hours call Ann` sends "call Ann" back in two hours, `when` lists what the
+/// time can be. As a reply without a text it reminds of the replied message.
+/// `/remind` alone lists the pending reminders and `/remind cancel 3` cancels one.
+pub struct Remind;
+
+#[async_trait]
+impl Handler for Remind {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        let wm = match &ctx.update.message {
+            Some(wm) => wm,
+            None => return Ok(()),
+        };
+        let chat_id = wm.chat.id;
+        let tz = ctx.settings.tz();
+        if ctx.args.is_empty() {
+            let pending = ctx.storage.select_reminders(chat_id).await?;
+            let text = if pending.is_empty() {
+                ctx.text("remind-none", &[])
+            } else {
+                let lines: Vec<String> = pending
+                    .iter()
+                    .map(|r| format!("#{} {} — {}", r.id, when::human_date(r.due_at, tz), r.text))
+                    .collect();
+                format!(
+                    "{}\n\n{}",
+                    lines.join("\n"),
+                    ctx.text("remind-cancel-hint", &[])
+                )
+            };
+            ctx.tg_client.send_message(chat_id, text).await?;
+            return Ok(());
+        }
+        if let Some(id) = ctx.args.strip_prefix("cancel") {
+            let text = match id.trim().parse::<i64>() {
+                Ok(id) if ctx.storage.delete_reminder(chat_id, id).await? => {
+                    ctx.text("remind-cancelled", &[("id", id.to_string().into())])
+                }
+                Ok(id) => ctx.text("remind-not-pending", &[("id", id.to_string().into())]),
+                Err(_) => ctx.text("remind-cancel-usage", &[]),
+            };
+            ctx.tg_client.send_message(chat_id, text).await?;
+            return Ok(());
+        }
+        let now = Utc::now();
+        let reminder = match when::parse(ctx.args, now, tz, Direction::Future) {
+            Some((due, _)) if due <= now => Err("remind-passed"),
+            Some((due, "")) => match wm.reply_to_message.as_deref() {
+                Some(reply) => Ok((
+                    due,
+                    reply.message_id,
+                    pages::headline(&reply.text, reminders::PREVIEW_CHARS),
+                )),
+                None => Err("remind-usage"),
+            },
+            Some((due, text)) => Ok((due, wm.message_id, text.to_string())),
+            None => Err("remind-usage"),
+        };
+        match reminder {
+            Ok((due, message_id, text)) => schedule(ctx, chat_id, message_id, text, due).await,
+            Err(key) => {
+                ctx.tg_client
+                    .send_message(chat_id, ctx.text(key, &[]))
+                    .await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The ⏰ button of the browser swaps the history buttons for `reminders::PRESETS`,
+/// `/remind:1h` sets a reminder of the open message and puts the history buttons back.
+pub struct RemindButton;
+
+#[async_trait]
+impl Handler for RemindButton {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let (Some(chat_id), Some(message_id)) = (ctx.chat_id(), ctx.callback_message_id()) {
+            if ctx.args.is_empty() {
+                ctx.tg_client
+                    .edit_reply_markup(
+                        chat_id,
+                        message_id,
+                        keyboard::reminder_presets(ctx.language()),
+                    )
+                    .await?;
+                return Ok(());
+            }
+            let due = when::parse(ctx.args, Utc::now(), ctx.settings.tz(), Direction::Future);
+            if let Some((due, _)) = due {
+                let link = ctx.storage.select_link(chat_id).await?;
+                let message = ctx.storage.select_message(chat_id, link.message_id).await?;
+                show_page(ctx, chat_id, message_id, 1).await?;
+                let text = pages::headline(&message.text, reminders::PREVIEW_CHARS);
+                schedule(ctx, chat_id, link.message_id, text, due).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn schedule(
+    ctx: &Context<'_>,
+    chat_id: i64,
+    message_id: i64,
+    text: String,
+    due: DateTime<Utc>,
+) -> Result<()> {
+    let reminder = Reminder {
+        id: 0,
+        chat_id,
+        message_id: Some(message_id),
+        text,
+        due_at: due.timestamp(),
+        created_at: Utc::now().timestamp(),
+        sent_at: None,
+        silent: ctx.settings.quiet_reminders,
+        language: ctx.settings.language.clone(),
+        timezone: ctx.settings.timezone.clone(),
+    };
+    let id = ctx.storage.insert_reminder(&reminder).await?;
+    let date = when::human_date(reminder.due_at, ctx.settings.tz());
+    let text = ctx.text(
+        "remind-set",
+        &[("id", id.to_string().into()), ("date", date.into())],
+    );
+    ctx.tg_client.send_message(chat_id, text).await?;
+    Ok(())
+}
+
+/// `/settings` opens the menu of the sender's settings, `/settings tz Europe/Moscow`
+/// changes one right away, `settings::apply` knows the keys.
+pub struct Settings;
+
+#[async_trait]
+impl Handler for Settings {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        let chat_id = match (ctx.chat_id(), ctx.update.user()) {
+            (Some(chat_id), Some(_)) => chat_id,
+            _ => return Ok(()),
+        };
+        if ctx.args.is_empty() {
+            let text = settings::describe(ctx.settings);
+            let keyboard = keyboard::settings_menu(ctx.settings);
+            let buttons = TgClient::create_buttons(chat_id, text, keyboard).await;
+            ctx.tg_client
+                .call::<_, WMessage>("sendMessage", &buttons)
+                .await?;
+            return Ok(());
+        }
+        let (key, value) = ctx
+            .args
+            .split_once(char::is_whitespace)
+            .unwrap_or((ctx.args, ""));
+        let mut user = ctx.settings.clone();
+        let text = match settings::apply(&mut user, &key.to_lowercase(), value) {
+            Ok(()) => {
+                user.upsert(ctx.storage).await?;
+                settings::describe(&user)
+            }
+            Err(text) => text,
+        };
+        ctx.tg_client.send_message(chat_id, text).await?;
+        Ok(())
+    }
+}
+
+/// Buttons of the settings menu: `/settings:tz` shows the choices of one setting,
+/// `/settings:tz=Europe/Moscow` picks one and goes back to the menu, `/settings:close`
+/// removes the menu. Whoever presses a button changes their own settings.
+pub struct SettingsButton;
+
+#[async_trait]
+impl Handler for SettingsButton {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        let (chat_id, message_id) = match (ctx.chat_id(), ctx.callback_message_id()) {
+            (Some(chat_id), Some(message_id)) if ctx.update.user().is_some() => {
+                (chat_id, message_id)
+            }
+            _ => return Ok(()),
+        };
+        if ctx.args == "close" {
+            return ctx.tg_client.delete_message(chat_id, message_id).await;
+        }
+        let mut user = ctx.settings.clone();
+        if let Some((key, value)) = ctx.args.split_once('=') {
+            if settings::apply(&mut user, key, value).is_ok() {
+                user.upsert(ctx.storage).await?;
+            }
+        } else if let Some(keyboard) = keyboard::settings_submenu(ctx.args, &user) {
+            return ctx
+                .tg_client
+                .edit_reply_markup(chat_id, message_id, keyboard)
+                .await;
+        }
+        let keyboard = keyboard::settings_menu(&user);
+        ctx.tg_client
+            .edit_message_text(
+                chat_id,
+                message_id,
+                settings::describe(&user),
+                Vec::new(),
+                keyboard,
+            )
+            .await
+    }
+}
+
+/// `/tags` lists the tags of the chat with the number of tagged messages.
+pub struct Tags;
+
+#[async_trait]
+impl Handler for Tags {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(chat_id) = ctx.chat_id() {
+            let tags = ctx.storage.list_tags(chat_id).await?;
+            let text = if tags.is_empty() {
+                ctx.text("tags-none", &[])
+            } else {
+                let lines: Vec<String> = tags
+                    .iter()
+                    .map(|t| format!("#{} — {}", t.name, t.messages))
+                    .collect();
+                lines.join("\n")
+            };
+            ctx.tg_client.send_message(chat_id, text).await?;
+        }
+        Ok(())
+    }
+}
+
+/// `/history` opens the browser at the first saved message, `/history 2022-06-23` at that day,
+/// also written as `yesterday`, `monday` or `3 дня назад`, and `/history #tag` walks only
+/// through messages with the tag.
+pub struct History;
+
+#[async_trait]
+impl Handler for History {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(chat_id) = ctx.chat_id() {
+            if ctx.args.is_empty() {
+                let empty = ctx.text("history-empty", &[]);
+                return browse(ctx, chat_id, &Filter::All, "link", &empty).await;
+            }
+            if let Some(name) = ctx.args.strip_prefix('#') {
+                return browse_tag(ctx, chat_id, name).await;
+            }
+            match when::parse_day(ctx.args, Utc::now(), ctx.settings.tz()) {
+                Some(date) => jump(ctx, chat_id, date, &Filter::All).await?,
+                None => {
+                    let date = format!("{:?}", ctx.args);
+                    let text = ctx.text("history-bad-date", &[("date", date.into())]);
+                    ctx.tg_client.send_message(chat_id, text).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn browse_tag(ctx: &Context<'_>, chat_id: i64, name: &str) -> Result<()> {
+    let filter = match tags::normalize(name) {
+        Some(name) => Filter::Tag(name),
+        None => {
+            let text = ctx.text("tag-invalid", &[("name", name.into())]);
+            ctx.tg_client.send_message(chat_id, text).await?;
+            return Ok(());
+        }
+    };
+    let empty = ctx.text("tag-empty", &[("name", name.into())]);
+    browse(ctx, chat_id, &filter, "tag_link", &empty).await
+}
+
+/// Opens the browser at the first message passing `filter`, sends `empty` if there is none.
+async fn browse(
+    ctx: &Context<'_>,
+    chat_id: i64,
+    filter: &Filter,
+    link_text: &str,
+    empty: &str,
+) -> Result<()> {
+    match Message::select_first_message(chat_id, ctx.storage, filter).await {
+        Ok(message) => {
+            ctx.tg_client
+                .show_history_message(
+                    ctx.storage,
+                    chat_id,
+                    &message,
+                    filter,
+                    link_text,
+                    ctx.language(),
+                )
+                .await
+        }
+        Err(e) if storage::is_not_found(&e) => {
+            ctx.tg_client
+                .send_message(chat_id, empty.to_string())
+                .await?;
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Day or month picked in the calendar.
+pub struct Jump;
+
+#[async_trait]
+impl Handler for Jump {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let (Some(chat_id), Some(date)) = (ctx.chat_id(), calendar::parse_date(ctx.args)) {
+            let filter = browsing_filter(ctx, chat_id).await;
+            jump(ctx, chat_id, date, &filter).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn jump(
+    ctx: &Context<'_>,
+    chat_id: i64,
+    date: chrono::NaiveDate,
+    filter: &Filter,
+) -> Result<()> {
+    let since = when::day_start(date, ctx.settings.tz());
+    match ctx
+        .storage
+        .select_first_message_since(chat_id, filter, since)
+        .await
+    {
+        Ok(message) => {
+            ctx.tg_client
+                .show_history_message(
+                    ctx.storage,
+                    chat_id,
+                    &message,
+                    filter,
+                    "jump_link",
+                    ctx.language(),
+                )
+                .await
+        }
+        Err(e) if storage::is_not_found(&e) => {
+            let date = date.format("%Y-%m-%d").to_string();
+            let text = ctx.text("jump-empty", &[("date", date.into())]);
+            ctx.tg_client.send_message(chat_id, text).await?;
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Swaps the history buttons for a month calendar, `/calendar:2022-06` switches months.
+pub struct Calendar;
+
+#[async_trait]
+impl Handler for Calendar {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let (Some(chat_id), Some(message_id)) = (ctx.chat_id(), ctx.callback_message_id()) {
+            let today = Utc::now().with_timezone(&ctx.settings.tz()).date_naive();
+            let month = calendar::parse_date(ctx.args).unwrap_or(today);
+            let keyboard = calendar::month_keyboard(month.year(), month.month(), ctx.language());
+            ctx.tg_client
+                .edit_reply_markup(chat_id, message_id, keyboard)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Puts the history buttons back after the calendar.
+pub struct Browse;
+
+#[async_trait]
+impl Handler for Browse {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let (Some(chat_id), Some(message_id)) = (ctx.chat_id(), ctx.callback_message_id()) {
+            show_page(ctx, chat_id, message_id, 1).await?;
+        }
+        Ok(())
+    }
+}
+
+/// `/page:N` turns the browser message to the N-th page of a long text.
+pub struct Page;
+
+#[async_trait]
+impl Handler for Page {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let (Some(chat_id), Some(message_id), Ok(page)) = (
+            ctx.chat_id(),
+            ctx.callback_message_id(),
+            ctx.args.parse::<usize>(),
+        ) {
+            show_page(ctx, chat_id, message_id, page).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Rewrites the browser message `message_id` with a page of the message it is linked to.
+async fn show_page(ctx: &Context<'_>, chat_id: i64, message_id: i64, page: usize) -> Result<()> {
+    let link = ctx.storage.select_link(chat_id).await?;
+    let message = ctx.storage.select_message(chat_id, link.message_id).await?;
+    let page = TgClient::history_page(
+        ctx.storage,
+        chat_id,
+        &link.filter(),
+        &message,
+        page,
+        ctx.language(),
+    )
+    .await?;
+    ctx.tg_client
+        .edit_message_text(chat_id, message_id, page.text, page.entities, page.keyboard)
+        .await
+}
+
+/// `/goto:N` shows the N-th saved message, used by the first/end and ±10 buttons.
+pub struct Goto;
+
+#[async_trait]
+impl Handler for Goto {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let (Some(chat_id), Ok(position)) = (ctx.chat_id(), ctx.args.parse::<i64>()) {
+            let filter = browsing_filter(ctx, chat_id).await;
+            let message = ctx
+                .storage
+                .select_message_at(chat_id, &filter, position)
+                .await?;
+            ctx.tg_client
+                .show_history_message(
+                    ctx.storage,
+                    chat_id,
+                    &message,
+                    &filter,
+                    "goto_link",
+                    ctx.language(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Placeholder buttons such as empty calendar cells.
+pub struct Ignore;
+
+#[async_trait]
+impl Handler for Ignore {
+    async fn handle(&self, _ctx: &Context<'_>) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Exit;
+
+#[async_trait]
+impl Handler for Exit {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(chat_id) = ctx.chat_id() {
+            ctx.tg_client.exit(ctx.storage, chat_id).await;
+        }
+        Ok(())
+    }
+}
+
+/// Moves the browser to the next message it walks through, stays at the last one.
+pub struct Next;
+
+#[async_trait]
+impl Handler for Next {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(chat_id) = ctx.chat_id() {
+            step(ctx, chat_id, Step::Next).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Moves the browser to the previous message it walks through, stays at the first one.
+pub struct Previous;
+
+#[async_trait]
+impl Handler for Previous {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(chat_id) = ctx.chat_id() {
+            step(ctx, chat_id, Step::Previous).await?;
+        }
+        Ok(())
+    }
+}
+
+enum Step {
+    Next,
+    Previous,
+}
+
+/// Shows the neighbour of the message open in the browser, nothing happens
+/// when the browser is closed or already at the end.
+async fn step(ctx: &Context<'_>, chat_id: i64, step: Step) -> Result<()> {
+    let link = match ctx.storage.select_link(chat_id).await {
+        Ok(link) => link,
+        Err(e) if storage::is_not_found(&e) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let filter = link.filter();
+    let (found, link_text) = match step {
+        Step::Next => (
+            Message::select_next_message(chat_id, ctx.storage, &filter, link.message_id).await,
+            "next_link",
+        ),
+        Step::Previous => (
+            Message::select_previous_message(chat_id, ctx.storage, &filter, link.message_id).await,
+            "previous_link",
+        ),
+    };
+    match found {
+        Ok(message) => {
+            ctx.tg_client
+                .show_history_message(
+                    ctx.storage,
+                    chat_id,
+                    &message,
+                    &filter,
+                    link_text,
+                    ctx.language(),
+                )
+                .await
+        }
+        Err(e) if storage::is_not_found(&e) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Jumps to the first saved message of the chat.
+pub struct First;
+
+#[async_trait]
+impl Handler for First {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(chat_id) = ctx.chat_id() {
+            let filter = browsing_filter(ctx, chat_id).await;
+            let message = Message::select_first_message(chat_id, ctx.storage, &filter).await?;
+            ctx.tg_client
+                .show_history_message(
+                    ctx.storage,
+                    chat_id,
+                    &message,
+                    &filter,
+                    "first_link",
+                    ctx.language(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Jumps to the latest saved message of the chat.
+pub struct End;
+
+#[async_trait]
+impl Handler for End {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()> {
+        if let Some(chat_id) = ctx.chat_id() {
+            let filter = browsing_filter(ctx, chat_id).await;
+            let message = Message::select_last_message(chat_id, ctx.storage, &filter).await?;
+            ctx.tg_client
+                .show_history_message(
+                    ctx.storage,
+                    chat_id,
+                    &message,
+                    &filter,
+                    "end_link",
+                    ctx.language(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Filter of the open browser message, everything when the browser is closed.
+async fn browsing_filter(ctx: &Context<'_>, chat_id: i64) -> Filter {
+    match ctx.storage.select_link(chat_id).await {
+        Ok(link) => link.filter(),
+        Err(_) => Filter::All,
+    }
+}
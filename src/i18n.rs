@@ -0,0 +1,126 @@
+//! Texts the bot sends, in every language of `settings::LANGUAGES`. They come from the Fluent
+//! catalogs in `locales/` built into the binary, handlers get theirs with `Context::text`.
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue};
+use std::sync::OnceLock;
+use unic_langid::LanguageIdentifier;
+
+/// Catalogs by language, the first one fills in for unknown languages and missing messages.
+const CATALOGS: [(&str, &str); 2] = [
+    ("en", include_str!("../locales/en.ftl")),
+    ("ru", include_str!("../locales/ru.ftl")),
+];
+
+type Bundle = FluentBundle<FluentResource>;
+
+fn bundles() -> &'static [(&'static str, Bundle)] {
+    static BUNDLES: OnceLock<Vec<(&str, Bundle)>> = OnceLock::new();
+    BUNDLES.get_or_init(|| {
+        CATALOGS
+            .iter()
+            .map(|(language, source)| (*language, bundle(language, source)))
+            .collect()
+    })
+}
+
+fn bundle(language: &str, source: &str) -> Bundle {
+    let id: LanguageIdentifier = language.parse().expect("valid language id");
+    let resource = FluentResource::try_new(source.to_string())
+        .unwrap_or_else(|(_, errors)| panic!("locales/{}.ftl: {:?}", language, errors));
+    let mut bundle = FluentBundle::new_concurrent(vec![id]);
+    // Telegram shows the isolation marks around arguments as they are.
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .unwrap_or_else(|errors| panic!("locales/{}.ftl: {:?}", language, errors));
+    bundle
+}
+
+/// Message `key` in `language` with `args` filled in: `text("ru", "forget-done", &[("count", 3.into())])`.
+/// Numbers pick the plural form of the language. Falls back to English, then to the key itself.
+pub fn text(language: &str, key: &str, args: &[(&str, FluentValue)]) -> String {
+    let bundles = bundles();
+    let found = bundles
+        .iter()
+        .filter(|(l, _)| *l == language)
+        .chain(bundles.first())
+        .find_map(|(_, bundle)| Some((bundle, bundle.get_message(key)?.value()?)));
+    let (bundle, pattern) = match found {
+        Some(found) => found,
+        None => {
+            eprintln!("no text {:?}", key);
+            return key.to_string();
+        }
+    };
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, value.clone());
+    }
+    let mut errors = Vec::new();
+    let text = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+    if !errors.is_empty() {
+        eprintln!("text {:?} in {}: {:?}", key, language, errors);
+    }
+    text.into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Message ids, they start a line and are followed by `=`.
+    fn keys(source: &str) -> BTreeSet<&str> {
+        source
+            .lines()
+            .filter(|line| line.starts_with(|c: char| c.is_ascii_lowercase()))
+            .filter_map(|line| Some(line.split_once('=')?.0.trim()))
+            .collect()
+    }
+
+    #[test]
+    fn every_key_exists_in_all_locales() {
+        let (_, english) = CATALOGS[0];
+        let english = keys(english);
+        for (language, source) in &CATALOGS[1..] {
+            let keys = keys(source);
+            let missing: Vec<_> = english.difference(&keys).collect();
+            let extra: Vec<_> = keys.difference(&english).collect();
+            assert!(missing.is_empty(), "missing in {}: {:?}", language, missing);
+            assert!(extra.is_empty(), "only in {}: {:?}", language, extra);
+        }
+        assert_eq!(bundles().len(), CATALOGS.len());
+    }
+
+    #[test]
+    fn picks_plural_forms() {
+        let forgot =
+            |language, count: i64| text(language, "forget-done", &[("count", count.into())]);
+        assert_eq!(forgot("en", 1), "Forgot 1 saved message of this chat");
+        assert_eq!(forgot("en", 5), "Forgot 5 saved messages of this chat");
+        assert_eq!(
+            forgot("ru", 1),
+            "Удалено 1 сохранённое сообщение этого чата"
+        );
+        assert_eq!(
+            forgot("ru", 3),
+            "Удалено 3 сохранённых сообщения этого чата"
+        );
+        assert_eq!(
+            forgot("ru", 5),
+            "Удалено 5 сохранённых сообщений этого чата"
+        );
+        assert_eq!(
+            forgot("ru", 21),
+            "Удалено 21 сохранённое сообщение этого чата"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_english() {
+        assert_eq!(text("de", "trash-empty", &[]), "The trash is empty");
+        assert_eq!(text("ru", "trash-empty", &[]), "Корзина пуста");
+        assert_eq!(text("ru", "no-such-text", &[]), "no-such-text");
+    }
+}
@@ -0,0 +1,315 @@
+//! Reads Telegram Desktop chat exports (`result.json`) into saved messages.
+
+use crate::models::{Message, User};
+use crate::pages;
+use crate::storage::{self, Storage};
+use crate::tags;
+use crate::web::MessageEntity;
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+
+/// Export of one chat, the file Telegram Desktop writes for "Export chat history" in JSON.
+#[derive(Deserialize)]
+struct ChatExport {
+    messages: Vec<ExportedMessage>,
+}
+
+#[derive(Deserialize)]
+struct ExportedMessage {
+    id: i64,
+    #[serde(rename = "type")]
+    kind: String,
+    date: Option<String>,
+    date_unixtime: Option<String>,
+    edited: Option<String>,
+    edited_unixtime: Option<String>,
+    from: Option<String>,
+    from_id: Option<String>,
+    #[serde(default)]
+    text: Text,
+    #[serde(default)]
+    text_entities: Vec<TextPiece>,
+}
+
+/// `text` is a plain string or a list of plain strings and styled pieces.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Text {
+    Plain(String),
+    Pieces(Vec<Piece>),
+}
+
+impl Default for Text {
+    fn default() -> Self {
+        Text::Plain(String::new())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Piece {
+    Plain(String),
+    Styled(TextPiece),
+}
+
+#[derive(Deserialize)]
+struct TextPiece {
+    #[serde(rename = "type")]
+    kind: String,
+    text: String,
+    href: Option<String>,
+}
+
+/// Messages of an export ready to be saved into one chat.
+#[derive(Debug)]
+pub struct ParsedExport {
+    pub messages: Vec<Message>,
+    /// Authors as the export names them, only used for users the bot hasn't seen.
+    pub authors: Vec<User>,
+    /// Service messages and messages without text.
+    pub skipped: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImportReport {
+    pub imported: u64,
+    /// Messages saved before, by an earlier import or by the bot itself.
+    pub existing: u64,
+    pub skipped: usize,
+    /// Ids taken by a different saved message, these messages are not imported.
+    pub conflicts: Vec<i64>,
+}
+
+/// Reads `result.json` of a single chat, its messages go to `chat_id` with their original ids.
+pub fn parse(json: &str, chat_id: i64) -> Result<ParsedExport> {
+    let export: ChatExport = serde_json::from_str(json)
+        .map_err(|e| anyhow!("not a Telegram Desktop chat export: {}", e))?;
+    let mut messages = Vec::new();
+    let mut authors: Vec<User> = Vec::new();
+    let mut skipped = 0;
+    for exported in export.messages {
+        let (text, entities) = exported.text();
+        if exported.kind != "message" || text.is_empty() {
+            skipped += 1;
+            continue;
+        }
+        let from_id = exported.user_id();
+        if let (Some(id), Some(name)) = (from_id, &exported.from) {
+            if !authors.iter().any(|a| a.id == id) {
+                authors.push(User {
+                    id,
+                    username: None,
+                    first_name: name.clone(),
+                    last_name: None,
+                });
+            }
+        }
+        let entities = serde_json::to_string(&entities).ok();
+        messages.push(Message {
+            text,
+            chat_id,
+            message_id: exported.id,
+            date: unix_time(&exported.date_unixtime, &exported.date).unwrap_or(0),
+            edit_date: unix_time(&exported.edited_unixtime, &exported.edited),
+            from_id,
+            entities: entities.filter(|json| json != "[]"),
+            deleted_at: None,
+        });
+    }
+    Ok(ParsedExport {
+        messages,
+        authors,
+        skipped,
+    })
+}
+
+/// Saves parsed messages and their hashtags. Messages saved before are left as they are,
+/// and an id taken by a message with another text is reported as a conflict.
+pub async fn save(storage: &dyn Storage, export: &ParsedExport) -> Result<ImportReport> {
+    for author in &export.authors {
+        if storage.select_user(author.id).await?.is_none() {
+            author.upsert(storage).await?;
+        }
+    }
+    let mut new = Vec::new();
+    let mut existing = 0;
+    let mut conflicts = Vec::new();
+    for message in &export.messages {
+        match storage
+            .select_message(message.chat_id, message.message_id)
+            .await
+        {
+            Ok(saved) if saved.text == message.text => existing += 1,
+            Ok(_) => conflicts.push(message.message_id),
+            Err(e) if storage::is_not_found(&e) => new.push(message.clone()),
+            Err(e) => return Err(e),
+        }
+    }
+    let imported = storage.insert_messages(&new).await?;
+    for message in &new {
+        let tags = tags::extract(&message.text);
+        if !tags.is_empty() {
+            storage
+                .add_tags(message.chat_id, message.message_id, &tags)
+                .await?;
+        }
+    }
+    Ok(ImportReport {
+        imported,
+        existing: existing + new.len() as u64 - imported,
+        skipped: export.skipped,
+        conflicts,
+    })
+}
+
+impl ExportedMessage {
+    /// Text with Bot API entities, from `text_entities` when the export has them.
+    fn text(&self) -> (String, Vec<MessageEntity>) {
+        let pieces: Vec<(&str, &str, Option<&str>)> = if !self.text_entities.is_empty() {
+            self.text_entities
+                .iter()
+                .map(|p| (p.kind.as_str(), p.text.as_str(), p.href.as_deref()))
+                .collect()
+        } else {
+            match &self.text {
+                Text::Plain(text) => vec![("plain", text.as_str(), None)],
+                Text::Pieces(pieces) => pieces
+                    .iter()
+                    .map(|piece| match piece {
+                        Piece::Plain(text) => ("plain", text.as_str(), None),
+                        Piece::Styled(p) => (p.kind.as_str(), p.text.as_str(), p.href.as_deref()),
+                    })
+                    .collect(),
+            }
+        };
+        let mut text = String::new();
+        let mut entities = Vec::new();
+        for (kind, piece, href) in pieces {
+            let offset = pages::utf16_len(&text);
+            text.push_str(piece);
+            let entity_type = match (entity_type(kind), href) {
+                (Some("text_link"), None) | (None, _) => continue,
+                (Some(entity_type), _) => entity_type,
+            };
+            entities.push(MessageEntity {
+                entity_type: entity_type.to_string(),
+                offset,
+                length: pages::utf16_len(piece),
+                url: href
+                    .filter(|_| entity_type == "text_link")
+                    .map(str::to_string),
+                user: None,
+                language: None,
+                custom_emoji_id: None,
+            });
+        }
+        (text, entities)
+    }
+
+    /// `from_id` is `user123` for people and `channel123` for channels, only people become users.
+    fn user_id(&self) -> Option<i64> {
+        self.from_id.as_deref()?.strip_prefix("user")?.parse().ok()
+    }
+}
+
+/// Bot API entity type of a Telegram Desktop text piece, `None` for plain text
+/// and pieces the Bot API can't describe without more data.
+fn entity_type(kind: &str) -> Option<&'static str> {
+    let entity_type = match kind {
+        "bold" => "bold",
+        "italic" => "italic",
+        "underline" => "underline",
+        "strikethrough" => "strikethrough",
+        "spoiler" => "spoiler",
+        "code" => "code",
+        "pre" => "pre",
+        "blockquote" => "blockquote",
+        "link" => "url",
+        "text_link" => "text_link",
+        "mention" => "mention",
+        "hashtag" => "hashtag",
+        "cashtag" => "cashtag",
+        "bot_command" => "bot_command",
+        "email" => "email",
+        "phone" => "phone_number",
+        "bank_card" => "bank_card",
+        _ => return None,
+    };
+    Some(entity_type)
+}
+
+/// Newer exports carry unix time next to the local `2022-06-23T12:00:00`,
+/// older ones only the latter which is read as UTC.
+fn unix_time(unixtime: &Option<String>, date: &Option<String>) -> Option<i64> {
+    if let Some(unixtime) = unixtime.as_deref().and_then(|t| t.parse().ok()) {
+        return Some(unixtime);
+    }
+    let date = NaiveDateTime::parse_from_str(date.as_deref()?, "%Y-%m-%dT%H:%M:%S").ok()?;
+    Some(date.and_utc().timestamp())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXPORT: &str = r#"{
+        "name": "Notes",
+        "type": "personal_chat",
+        "id": 7,
+        "messages": [
+            {"id": 3, "type": "service", "date": "2022-06-23T12:00:00", "action": "phone_call", "text": ""},
+            {"id": 5, "type": "message", "date": "2022-06-23T12:00:00", "date_unixtime": "1655985600",
+             "edited": "2022-06-23T12:05:00", "edited_unixtime": "1655985900",
+             "from": "Ann", "from_id": "user7", "text": "plain #note"},
+            {"id": 6, "type": "message", "date": "2022-06-24T08:00:00", "from": "News", "from_id": "channel9",
+             "text": ["see ", {"type": "text_link", "text": "docs", "href": "https://example.com"}, " 😀 ", {"type": "bold", "text": "now"}]},
+            {"id": 7, "type": "message", "date": "2022-06-24T09:00:00", "from": "Ann", "from_id": "user7",
+             "photo": "photos/1.jpg", "text": ""}
+        ]
+    }"#;
+
+    #[test]
+    fn parses_chat_export() {
+        let export = parse(EXPORT, 42).unwrap();
+        assert_eq!(export.skipped, 2);
+        assert_eq!(export.authors.len(), 1);
+        assert_eq!(export.authors[0].first_name, "Ann");
+
+        let first = &export.messages[0];
+        assert_eq!((first.chat_id, first.message_id), (42, 5));
+        assert_eq!(
+            (first.date, first.edit_date),
+            (1655985600, Some(1655985900))
+        );
+        assert_eq!(first.from_id, Some(7));
+        assert_eq!(first.entities, None);
+
+        let second = &export.messages[1];
+        assert_eq!(second.text, "see docs 😀 now");
+        assert_eq!(second.date, 1656057600);
+        assert_eq!(second.from_id, None);
+        let entities: Vec<_> = second
+            .entities()
+            .into_iter()
+            .map(|e| (e.entity_type, e.offset, e.length, e.url))
+            .collect();
+        assert_eq!(
+            entities,
+            vec![
+                (
+                    "text_link".to_string(),
+                    4,
+                    4,
+                    Some("https://example.com".to_string())
+                ),
+                ("bold".to_string(), 12, 3, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_other_json() {
+        assert!(parse(r#"{"chats": {"list": []}}"#, 42).is_err());
+    }
+}
@@ -0,0 +1,366 @@
+//! Builder for the inline keyboards the bot attaches to its messages.
+
+use crate::i18n;
+use crate::models::{Filter, UserSettings};
+use crate::reminders;
+use crate::settings;
+use crate::web::{InlineKeyboardMarkup, KeyboardButton};
+
+/// Collects buttons row by row, `row()` starts a new one. Empty rows are dropped.
+#[derive(Debug, Default)]
+pub struct Keyboard {
+    rows: Vec<Vec<KeyboardButton>>,
+    row: Vec<KeyboardButton>,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn button(mut self, text: impl Into<String>, callback_data: impl Into<String>) -> Self {
+        self.row
+            .push(KeyboardButton::new(text.into(), callback_data.into()));
+        self
+    }
+
+    /// Adds the button only when `condition` holds, keeps chained calls readable.
+    pub fn button_if(
+        self,
+        condition: bool,
+        text: impl Into<String>,
+        callback_data: impl Into<String>,
+    ) -> Self {
+        if condition {
+            self.button(text, callback_data)
+        } else {
+            self
+        }
+    }
+
+    pub fn row(mut self) -> Self {
+        if !self.row.is_empty() {
+            self.rows.push(std::mem::take(&mut self.row));
+        }
+        self
+    }
+
+    pub fn build(self) -> InlineKeyboardMarkup {
+        InlineKeyboardMarkup::new(self.row().rows)
+    }
+}
+
+/// The history browser: `12 / 348` between the step buttons, ±10 jumps, the star, reminder,
+/// delete and close. Texts longer than one message get a row to turn their pages on top, a filtered
+/// browser shows its filter next to the position and the trash offers restore and purge
+/// instead of star and delete. Buttons that would lead nowhere at the start or the end
+/// of the history are left out.
+pub fn history(
+    position: i64,
+    total: i64,
+    page: usize,
+    pages: usize,
+    filter: &Filter,
+    favorite: bool,
+    language: &str,
+) -> InlineKeyboardMarkup {
+    let has_previous = position > 1;
+    let has_next = position < total;
+    let label = match filter.to_link() {
+        Some(filter) => format!("{} / {} {}", position, total, filter),
+        None => format!("{} / {}", position, total),
+    };
+    let trash = filter.trash();
+    let star = if favorite { "⭐" } else { "☆" };
+    Keyboard::new()
+        .button_if(page > 1, "◂", format!("/page:{}", page.saturating_sub(1)))
+        .button_if(
+            pages > 1,
+            i18n::text(
+                language,
+                "history-page",
+                &[("page", page.into()), ("pages", pages.into())],
+            ),
+            "/ignore",
+        )
+        .button_if(page < pages, "▸", format!("/page:{}", page + 1))
+        .row()
+        .button_if(has_previous, "⏮", "/first")
+        .button_if(has_previous, "‹", "/previous")
+        .button(label, "/ignore")
+        .button_if(has_next, "›", "/next")
+        .button_if(has_next, "⏭", "/end")
+        .row()
+        .button_if(
+            has_previous,
+            "-10",
+            format!("/goto:{}", (position - 10).max(1)),
+        )
+        .button("📅", "/calendar")
+        .button_if(!trash, star, format!("/star:{}", page))
+        .button_if(!trash, "⏰", "/remind")
+        .button_if(!trash, "🗑", "/delete")
+        .button_if(trash, "♻", "/restore")
+        .button_if(trash, "🔥", "/purge")
+        .button_if(
+            has_next,
+            "+10",
+            format!("/goto:{}", (position + 10).min(total)),
+        )
+        .button("✖", "/exit")
+        .build()
+}
+
+/// Adds a row with the undo button for the message `message_id` just deleted.
+pub fn with_undo(
+    mut keyboard: InlineKeyboardMarkup,
+    message_id: i64,
+    language: &str,
+) -> InlineKeyboardMarkup {
+    let undo = KeyboardButton::new(
+        i18n::text(language, "history-undo", &[]),
+        format!("/undo:{}", message_id),
+    );
+    keyboard.inline_keyboard.push(vec![undo]);
+    keyboard
+}
+
+/// Delays the ⏰ button offers, two per row, and a row to go back to the browser.
+pub fn reminder_presets(language: &str) -> InlineKeyboardMarkup {
+    let mut keyboard = Keyboard::new();
+    for (i, (label, delay)) in reminders::PRESETS.iter().enumerate() {
+        if i % 2 == 0 {
+            keyboard = keyboard.row();
+        }
+        keyboard = keyboard.button(
+            i18n::text(language, label, &[]),
+            format!("/remind:{}", delay),
+        );
+    }
+    keyboard
+        .row()
+        .button(i18n::text(language, "back", &[]), "/browse")
+        .build()
+}
+
+/// The `/settings` menu, each button opens the choices of one setting
+/// except the bell which toggles quiet reminders right away.
+pub fn settings_menu(user: &UserSettings) -> InlineKeyboardMarkup {
+    let text = |key| i18n::text(&user.language, key, &[]);
+    let (bell, quiet) = if user.quiet_reminders {
+        ("settings-quiet-on", "off")
+    } else {
+        ("settings-quiet-off", "on")
+    };
+    Keyboard::new()
+        .button(text("settings-timezone"), "/settings:tz")
+        .button(text("settings-language"), "/settings:lang")
+        .row()
+        .button(text("settings-export"), "/settings:export")
+        .row()
+        .button(text(bell), format!("/settings:quiet={}", quiet))
+        .row()
+        .button(text("settings-done"), "/settings:close")
+        .build()
+}
+
+/// Choices of the setting `key`, `(value, label)` laid out `per_row` in a row,
+/// the current one marked.
+pub fn settings_choices(
+    key: &str,
+    choices: &[(&str, &str)],
+    current: &str,
+    per_row: usize,
+    language: &str,
+) -> InlineKeyboardMarkup {
+    let mut keyboard = Keyboard::new();
+    for (i, (value, label)) in choices.iter().enumerate() {
+        if i % per_row == 0 {
+            keyboard = keyboard.row();
+        }
+        let label = if *value == current {
+            format!("• {}", label)
+        } else {
+            label.to_string()
+        };
+        keyboard = keyboard.button(label, format!("/settings:{}={}", key, value));
+    }
+    keyboard
+        .row()
+        .button(i18n::text(language, "back", &[]), "/settings")
+        .build()
+}
+
+/// `settings_choices` of `key` for the user, `None` for an unknown key.
+pub fn settings_submenu(key: &str, user: &UserSettings) -> Option<InlineKeyboardMarkup> {
+    let keyboard = match key {
+        "tz" => {
+            let zones: Vec<(&str, &str)> = settings::TIMEZONES.iter().map(|z| (*z, *z)).collect();
+            settings_choices(key, &zones, &user.timezone, 2, &user.language)
+        }
+        "lang" => settings_choices(key, &settings::LANGUAGES, &user.language, 2, &user.language),
+        "export" => {
+            let formats: Vec<(&str, &str)> =
+                settings::EXPORT_FORMATS.iter().map(|f| (*f, *f)).collect();
+            settings_choices(key, &formats, &user.export_format, 4, &user.language)
+        }
+        _ => return None,
+    };
+    Some(keyboard)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn callbacks(keyboard: &InlineKeyboardMarkup) -> Vec<Vec<&str>> {
+        keyboard
+            .inline_keyboard
+            .iter()
+            .map(|row| row.iter().map(|b| b.callback_data.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn builds_rows() {
+        let keyboard = Keyboard::new()
+            .button("a", "/a")
+            .row()
+            .row()
+            .button("b", "/b")
+            .button_if(false, "c", "/c")
+            .build();
+        assert_eq!(callbacks(&keyboard), vec![vec!["/a"], vec!["/b"]]);
+    }
+
+    #[test]
+    fn history_shows_position_and_jumps() {
+        let keyboard = history(12, 348, 1, 1, &Filter::All, false, "en");
+        assert_eq!(keyboard.inline_keyboard[0][2].text, "12 / 348");
+        assert_eq!(
+            callbacks(&keyboard),
+            vec![
+                vec!["/first", "/previous", "/ignore", "/next", "/end"],
+                vec![
+                    "/goto:2",
+                    "/calendar",
+                    "/star:1",
+                    "/remind",
+                    "/delete",
+                    "/goto:22",
+                    "/exit"
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn history_hides_steps_past_the_ends() {
+        assert_eq!(
+            callbacks(&history(1, 3, 1, 1, &Filter::All, false, "en")),
+            vec![
+                vec!["/ignore", "/next", "/end"],
+                vec![
+                    "/calendar",
+                    "/star:1",
+                    "/remind",
+                    "/delete",
+                    "/goto:3",
+                    "/exit"
+                ]
+            ]
+        );
+        assert_eq!(
+            callbacks(&history(1, 1, 1, 1, &Filter::All, false, "en")),
+            vec![
+                vec!["/ignore"],
+                vec!["/calendar", "/star:1", "/remind", "/delete", "/exit"]
+            ]
+        );
+    }
+
+    #[test]
+    fn history_shows_filter() {
+        let keyboard = history(2, 5, 1, 1, &Filter::Tag("work".to_string()), false, "en");
+        assert_eq!(keyboard.inline_keyboard[0][2].text, "2 / 5 #work");
+    }
+
+    #[test]
+    fn history_shows_star() {
+        assert_eq!(
+            history(2, 5, 1, 1, &Filter::All, false, "en").inline_keyboard[1][2].text,
+            "☆"
+        );
+        let keyboard = history(2, 5, 1, 1, &Filter::Favorites, true, "en");
+        assert_eq!(keyboard.inline_keyboard[0][2].text, "2 / 5 ⭐");
+        assert_eq!(keyboard.inline_keyboard[1][2].text, "⭐");
+    }
+
+    #[test]
+    fn trash_restores_and_purges() {
+        let keyboard = with_undo(history(1, 1, 1, 1, &Filter::Trash, false, "en"), 7, "en");
+        assert_eq!(keyboard.inline_keyboard[0][0].text, "1 / 1 🗑");
+        assert_eq!(
+            callbacks(&keyboard)[1..],
+            vec![
+                vec!["/calendar", "/restore", "/purge", "/exit"],
+                vec!["/undo:7"]
+            ]
+        );
+    }
+
+    #[test]
+    fn reminder_presets_go_back_to_the_browser() {
+        assert_eq!(
+            callbacks(&reminder_presets("en")),
+            vec![
+                vec!["/remind:1h", "/remind:3h"],
+                vec!["/remind:tomorrow", "/remind:1w"],
+                vec!["/browse"],
+            ]
+        );
+    }
+
+    #[test]
+    fn history_turns_pages_of_long_texts() {
+        let keyboard = history(1, 1, 2, 3, &Filter::All, false, "en");
+        assert_eq!(keyboard.inline_keyboard[0][1].text, "page 2 / 3");
+        let keyboard = history(1, 1, 2, 3, &Filter::All, false, "ru");
+        assert_eq!(keyboard.inline_keyboard[0][1].text, "стр. 2 / 3");
+        assert_eq!(
+            callbacks(&keyboard)[0],
+            vec!["/page:1", "/ignore", "/page:3"]
+        );
+    }
+
+    #[test]
+    fn settings_mark_the_current_choice() {
+        let mut user = UserSettings::new(7, None);
+        user.quiet_reminders = true;
+        assert_eq!(
+            callbacks(&settings_menu(&user)),
+            vec![
+                vec!["/settings:tz", "/settings:lang"],
+                vec!["/settings:export"],
+                vec!["/settings:quiet=off"],
+                vec!["/settings:close"],
+            ]
+        );
+        let keyboard = settings_submenu("export", &user).unwrap();
+        assert_eq!(
+            callbacks(&keyboard),
+            vec![
+                vec![
+                    "/settings:export=json",
+                    "/settings:export=csv",
+                    "/settings:export=md",
+                    "/settings:export=html"
+                ],
+                vec!["/settings"],
+            ]
+        );
+        assert_eq!(keyboard.inline_keyboard[0][0].text, "• json");
+        assert_eq!(keyboard.inline_keyboard[0][1].text, "csv");
+        assert!(settings_submenu("colour", &user).is_none());
+    }
+}
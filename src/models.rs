@@ -1,11 +1,22 @@
-use sqlx::postgres::PgQueryResult;
-use sqlx::{Error, PgPool};
+use crate::storage::Storage;
+use crate::web::{MessageEntity, WChat, WEditedMessage, WMessage, WUpdate, WUser};
+use anyhow::Result;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+/// `date` and `edit_date` are unix seconds as sent by Telegram, `date` is 0 for messages saved before it was stored.
+/// `entities` is the JSON array of Telegram message entities, `None` for plain text.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Message {
     pub text: String,
     pub chat_id: i64,
     pub message_id: i64,
+    pub date: i64,
+    pub edit_date: Option<i64>,
+    pub from_id: Option<i64>,
+    pub entities: Option<String>,
+    /// When the message went to the trash, `None` for messages in the history.
+    pub deleted_at: Option<i64>,
 }
 impl Message {
     pub async fn new(text: String, chat_id: i64, message_id: i64) -> Self {
@@ -13,76 +24,202 @@ impl Message {
             text,
             chat_id,
             message_id,
+            date: 0,
+            edit_date: None,
+            from_id: None,
+            entities: None,
+            deleted_at: None,
         }
     }
-    pub async fn insert(&self, pg_pool: &PgPool) -> Result<PgQueryResult, Error> {
-        return sqlx::query(
-            r#"
-    INSERT INTO message (message_id,text,chat_id)
-    VALUES ( $1::bigint,$2,$3::bigint)
-    ON CONFLICT DO NOTHING
-        "#,
-        )
-        .bind(&self.message_id)
-        .bind(&self.text)
-        .bind(&self.chat_id)
-        .execute(pg_pool)
-        .await;
+
+    /// Formatting of `text`, empty when there is none or it can't be read.
+    pub fn entities(&self) -> Vec<MessageEntity> {
+        parse_entities(&self.entities)
+    }
+    /// Returns whether the message is new, see `Storage::insert_message`.
+    pub async fn insert(&self, storage: &dyn Storage) -> Result<bool> {
+        storage.insert_message(self).await
     }
 
     pub async fn select_next_message(
         chat_id: i64,
-        pg_pool: &PgPool,
+        storage: &dyn Storage,
+        filter: &Filter,
         id: i64,
-    ) -> Result<Message, Error> {
-        let record = sqlx::query_as!(
-            Message,
-            r#"SELECT * FROM message WHERE chat_id = $1 AND message_id > $2  LIMIT 1"#,
-            chat_id,
-            id
-        )
-        .fetch_one(pg_pool)
-        .await;
-        return record;
+    ) -> Result<Message> {
+        storage.select_next_message(chat_id, filter, id).await
     }
 
-    pub async fn select_last_message(
+    pub async fn select_previous_message(
         chat_id: i64,
-        pg_pool: &PgPool,
+        storage: &dyn Storage,
+        filter: &Filter,
         id: i64,
-    ) -> Result<Message, Error> {
-        let record = sqlx::query_as!(
-            Message,
-            r#"SELECT text, chat_id, message_id FROM message WHERE chat_id = $1 AND message_id = (select MAX(message_id) from message WHERE message_id < $2)  LIMIT 1"#,
-            chat_id,
-            id
-        )
-        .fetch_one(pg_pool)
-        .await;
-        return record;
+    ) -> Result<Message> {
+        storage.select_previous_message(chat_id, filter, id).await
     }
 
-    pub async fn select_first_user_message_by_chat_id(
+    pub async fn select_first_message(
         chat_id: i64,
-        pg_pool: &PgPool,
-    ) -> Result<Message, Error> {
-        let record = sqlx::query_as!(
-            Message,
-            r#"SELECT * FROM message WHERE chat_id = $1 LIMIT 1"#,
-            chat_id
-        )
-        .fetch_one(pg_pool)
-        .await;
-        return record;
+        storage: &dyn Storage,
+        filter: &Filter,
+    ) -> Result<Message> {
+        storage.select_first_message(chat_id, filter).await
+    }
+
+    pub async fn select_last_message(
+        chat_id: i64,
+        storage: &dyn Storage,
+        filter: &Filter,
+    ) -> Result<Message> {
+        storage.select_last_message(chat_id, filter).await
     }
 }
 
-#[derive(Debug, Clone)]
+impl From<&WMessage> for Message {
+    fn from(wm: &WMessage) -> Self {
+        Self {
+            text: wm.text.clone(),
+            chat_id: wm.chat.id,
+            message_id: wm.message_id,
+            date: wm.date,
+            edit_date: wm.edit_date,
+            from_id: wm.from.as_ref().map(|u| u.id),
+            entities: entities_json(&wm.entities),
+            deleted_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Chat {
+    pub id: i64,
+    pub chat_type: String,
+    pub title: Option<String>,
+    pub username: Option<String>,
+}
+
+impl Chat {
+    pub async fn upsert(&self, storage: &dyn Storage) -> Result<()> {
+        storage.upsert_chat(self).await
+    }
+}
+
+impl From<&WChat> for Chat {
+    fn from(wc: &WChat) -> Self {
+        Self {
+            id: wc.id,
+            chat_type: wc.chat_type.clone(),
+            title: wc.title.clone(),
+            username: wc.username.clone(),
+        }
+    }
+}
+
+/// Author of saved messages, the table is `tg_user` because `user` is reserved in Postgres.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct User {
+    pub id: i64,
+    pub username: Option<String>,
+    pub first_name: String,
+    pub last_name: Option<String>,
+}
+
+impl User {
+    pub async fn upsert(&self, storage: &dyn Storage) -> Result<()> {
+        storage.upsert_user(self).await
+    }
+
+    pub fn display_name(&self) -> String {
+        match (&self.last_name, &self.username) {
+            (Some(last_name), _) => format!("{} {}", self.first_name, last_name),
+            (None, Some(username)) => format!("{} (@{})", self.first_name, username),
+            (None, None) => self.first_name.clone(),
+        }
+    }
+}
+
+impl From<&WUser> for User {
+    fn from(wu: &WUser) -> Self {
+        Self {
+            id: wu.id,
+            username: wu.username.clone(),
+            first_name: wu.first_name.clone(),
+            last_name: wu.last_name.clone(),
+        }
+    }
+}
+
+/// Which saved messages of the chat the history browser walks through.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Filter {
+    #[default]
+    All,
+    /// Messages tagged with the name, kept without `#`.
+    Tag(String),
+    /// Starred messages.
+    Favorites,
+    /// Deleted messages waiting to be restored or purged, the other filters skip them.
+    Trash,
+}
+
+impl Filter {
+    pub fn tag(&self) -> Option<&str> {
+        match self {
+            Filter::Tag(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn favorites(&self) -> bool {
+        *self == Filter::Favorites
+    }
+
+    pub fn trash(&self) -> bool {
+        *self == Filter::Trash
+    }
+
+    /// How the filter is kept in `link_message.filter` and shown on the keyboard, `None` for all.
+    pub fn to_link(&self) -> Option<String> {
+        match self {
+            Filter::All => None,
+            Filter::Tag(name) => Some(format!("#{}", name)),
+            Filter::Favorites => Some(FAVORITES_LINK.to_string()),
+            Filter::Trash => Some(TRASH_LINK.to_string()),
+        }
+    }
+
+    pub fn from_link(link: Option<&str>) -> Self {
+        match link {
+            Some(FAVORITES_LINK) => Filter::Favorites,
+            Some(TRASH_LINK) => Filter::Trash,
+            Some(link) => match link.strip_prefix('#') {
+                Some(name) => Filter::Tag(name.to_string()),
+                None => Filter::All,
+            },
+            None => Filter::All,
+        }
+    }
+}
+
+const FAVORITES_LINK: &str = "⭐";
+const TRASH_LINK: &str = "🗑";
+
+/// A tag of the chat with the number of messages tagged with it.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct TagSummary {
+    pub name: String,
+    pub messages: i64,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct LinkMessage {
     pub id: i64,
     pub text: String,
     pub chat_id: i64,
     pub message_id: i64,
+    /// `Filter` the browser walks through, see `Filter::to_link`.
+    pub filter: Option<String>,
 }
 
 impl LinkMessage {
@@ -92,40 +229,28 @@ impl LinkMessage {
             text,
             chat_id,
             message_id,
+            filter: None,
         }
     }
 
-    pub async fn insert(&self, pg_pool: &PgPool) -> Result<PgQueryResult, Error> {
-        return sqlx::query(
-            r#"
-    INSERT INTO link_message (id,text,chat_id, message_id)
-    VALUES ( $1::bigint,$2,$3::bigint, $4::bigint)
-    ON CONFLICT DO NOTHING
-        "#,
-        )
-        .bind(&self.id)
-        .bind(&self.text)
-        .bind(&self.chat_id)
-        .bind(&self.message_id)
-        .execute(pg_pool)
-        .await;
+    pub fn with_filter(mut self, filter: &Filter) -> Self {
+        self.filter = filter.to_link();
+        self
+    }
+
+    pub fn filter(&self) -> Filter {
+        Filter::from_link(self.filter.as_deref())
+    }
+
+    pub async fn insert(&self, storage: &dyn Storage) -> Result<()> {
+        storage.insert_link(self).await
     }
 
     pub async fn delete_and_return_link(
-        pg_pool: &PgPool,
+        storage: &dyn Storage,
         chat_id: i64,
-    ) -> Result<LinkMessage, Error> {
-        let select_element_after_delete = sqlx::query_as!(
-            LinkMessage,
-            r#"DELETE FROM link_message
-                    WHERE chat_id = $1
-                    RETURNING *
-                    "#,
-            chat_id
-        )
-        .fetch_one(pg_pool)
-        .await;
-        return select_element_after_delete;
+    ) -> Result<LinkMessage> {
+        storage.delete_and_return_link(chat_id).await
     }
 }
 
@@ -133,28 +258,43 @@ impl LinkMessage {
 pub struct EditedMessage {
     pub message_id: i64,
     pub text: String,
+    pub chat_id: i64,
+    pub edit_date: Option<i64>,
+    pub entities: Option<String>,
 }
 impl EditedMessage {
-    pub async fn new(message_id: i64, text: String) -> Self {
-        Self { message_id, text }
+    pub async fn new(message_id: i64, text: String, chat_id: i64, edit_date: Option<i64>) -> Self {
+        Self {
+            message_id,
+            text,
+            chat_id,
+            edit_date,
+            entities: None,
+        }
+    }
+
+    pub async fn change_message_text(&self, storage: &dyn Storage) -> Result<bool> {
+        storage.change_message_text(self).await
     }
 
-    pub async fn change_message_text(&self, pg_pool: &PgPool) -> Result<PgQueryResult, Error> {
-        return sqlx::query(
-            r#"
-                    UPDATE message
-                    SET text = $1
-                    WHERE message_id = $2
-                    "#,
-        )
-        .bind(&self.text)
-        .bind(&self.message_id)
-        .execute(pg_pool)
-        .await;
+    pub fn entities(&self) -> Vec<MessageEntity> {
+        parse_entities(&self.entities)
     }
 }
 
-#[derive(Debug, Clone)]
+impl From<&WEditedMessage> for EditedMessage {
+    fn from(wem: &WEditedMessage) -> Self {
+        Self {
+            message_id: wem.message_id,
+            text: wem.text.clone(),
+            chat_id: wem.chat.id,
+            edit_date: wem.edit_date,
+            entities: entities_json(&wem.entities),
+        }
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct Update {
     pub id: i64,
     pub update_id: i64,
@@ -162,25 +302,178 @@ pub struct Update {
 
 impl Update {
     pub async fn new(id: i64, update_id: i64) -> Self {
-        return Self { id, update_id };
+        Self { id, update_id }
+    }
+
+    pub async fn insert(&self, storage: &dyn Storage) -> Result<()> {
+        storage.insert_update(self).await
+    }
+
+    pub async fn get_last_update(storage: &dyn Storage) -> Result<Update> {
+        storage.get_last_update().await
+    }
+}
+
+/// Saved messages per chat, listed by `tgbot-admin chats`.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ChatSummary {
+    pub chat_id: i64,
+    pub messages: i64,
+    pub title: Option<String>,
+}
+
+/// Raw update as it came from `getUpdates`, kept for `tgbot-admin replay`.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct LoggedUpdate {
+    pub update_id: i64,
+    pub body: String,
+    /// Chat the update came from, lets `/forget` drop it with the rest of the chat.
+    pub chat_id: Option<i64>,
+}
+
+/// A tag of one message, see `Storage::select_message_tags`.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct MessageTag {
+    pub message_id: i64,
+    pub name: String,
+}
+
+/// A text the message had before an edit, kept by `Storage::change_message_text`.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct MessageRevision {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub text: String,
+    pub entities: Option<String>,
+    /// When this text was written, unix seconds.
+    pub date: i64,
+}
+
+impl MessageRevision {
+    pub fn entities(&self) -> Vec<MessageEntity> {
+        parse_entities(&self.entities)
+    }
+}
+
+/// Key of the REST API issued with `/apikey`, it reads the messages of one chat.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ApiKey {
+    pub key: String,
+    pub chat_id: i64,
+    pub created_at: i64,
+}
+
+/// The Atom feed of a chat turned on with `/feed`, served at a URL with the secret `token`.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ChatFeed {
+    pub chat_id: i64,
+    pub token: String,
+    /// Chat title when the feed was turned on, the feed's title.
+    pub title: Option<String>,
+    pub created_at: i64,
+}
+
+/// A text the bot sends back to the chat at `due_at`, set with `/remind` or the ⏰ button.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Reminder {
+    /// Given by the storage on insert.
+    pub id: i64,
+    pub chat_id: i64,
+    /// The saved message the reminder is about, the reminder replies to it.
+    pub message_id: Option<i64>,
+    pub text: String,
+    /// Unix seconds.
+    pub due_at: i64,
+    pub created_at: i64,
+    /// `None` until the scheduler sends the reminder.
+    pub sent_at: Option<i64>,
+    /// Sent without a sound, for users who turned on quiet reminders in `/settings`.
+    pub silent: bool,
+    /// Language of whoever set the reminder, for what the bot adds to the text.
+    pub language: String,
+    /// IANA name of the timezone of whoever set the reminder, for dates the bot adds.
+    pub timezone: String,
+}
+
+impl Reminder {
+    /// The timezone, UTC when the stored name is no longer known.
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+}
+
+/// Preferences of one Telegram user changed with `/settings`, they follow the user across chats.
+/// Users without a row get `UserSettings::new`.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct UserSettings {
+    pub user_id: i64,
+    /// IANA name such as `Europe/Moscow`, dates the user types and reads are in this zone.
+    pub timezone: String,
+    /// Interface language, one of `settings::LANGUAGES`.
+    pub language: String,
+    /// What `/export` without arguments produces, an argument of `export::Format::parse`.
+    pub export_format: String,
+    /// Reminders set by the user arrive without a sound.
+    pub quiet_reminders: bool,
+}
+
+impl UserSettings {
+    /// Defaults: UTC, JSON exports, reminders with a sound and the language
+    /// of the user's Telegram app when the bot speaks it, English otherwise.
+    pub fn new(user_id: i64, language_code: Option<&str>) -> Self {
+        let language = match language_code {
+            Some(code) if code == "ru" || code.starts_with("ru-") => "ru",
+            _ => "en",
+        };
+        UserSettings {
+            user_id,
+            timezone: "UTC".to_string(),
+            language: language.to_string(),
+            export_format: "json".to_string(),
+            quiet_reminders: false,
+        }
+    }
+
+    /// The timezone, UTC when the stored name is no longer known.
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
     }
 
-    pub async fn insert(&self, pg_pool: &PgPool) -> Result<PgQueryResult, Error> {
-        return sqlx::query(
-            r#"
-    UPDATE update
-    SET update_id = $1::bigint
-    WHERE id = 1;
-        "#,
-        )
-        .bind(self.update_id + 1)
-        .execute(pg_pool)
-        .await;
+    /// Settings of the user behind the update, defaults for updates without one.
+    pub async fn load(storage: &dyn Storage, update: &WUpdate) -> Result<Self> {
+        let user = match update.user() {
+            Some(user) => user,
+            None => return Ok(Self::new(0, None)),
+        };
+        let stored = storage.select_user_settings(user.id).await?;
+        Ok(stored.unwrap_or_else(|| Self::new(user.id, user.language_code.as_deref())))
     }
 
-    pub async fn get_last_update(pg_pool: &PgPool) -> Result<Update, Error> {
-        sqlx::query_as!(Update, r#"SELECT * FROM UPDATE WHERE ID = 1"#)
-            .fetch_one(pg_pool)
-            .await
+    pub async fn upsert(&self, storage: &dyn Storage) -> Result<()> {
+        storage.upsert_user_settings(self).await
     }
 }
+
+/// Audit record of a chat forgotten with `/forget` or `tgbot-admin purge`.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PurgeRecord {
+    pub chat_id: i64,
+    /// Who confirmed the purge, `None` when it was run by the admin tool.
+    pub user_id: Option<i64>,
+    pub messages: i64,
+    /// Unix seconds.
+    pub purged_at: i64,
+}
+
+fn entities_json(entities: &[MessageEntity]) -> Option<String> {
+    if entities.is_empty() {
+        return None;
+    }
+    serde_json::to_string(entities).ok()
+}
+
+fn parse_entities(json: &Option<String>) -> Vec<MessageEntity> {
+    json.as_deref()
+        .and_then(|json| serde_json::from_str(json).ok())
+        .unwrap_or_default()
+}
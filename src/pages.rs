@@ -0,0 +1,140 @@
+//! Splits saved texts into pages that fit into one Telegram message.
+
+use crate::web::MessageEntity;
+
+/// `sendMessage` and `editMessageText` accept at most 4096 UTF-16 code units.
+pub const MESSAGE_LIMIT: usize = 4096;
+
+/// Cuts `text` into pages of at most `limit` UTF-16 code units, preferring to break after
+/// a newline or a space in the second half of the page. Joined pages give back `text`.
+pub fn split(text: &str, limit: usize) -> Vec<&str> {
+    let mut pages = Vec::new();
+    let mut rest = text;
+    while utf16_len(rest) > limit {
+        let mut end = 0;
+        let mut units = 0;
+        let mut newline = None;
+        let mut space = None;
+        for (i, c) in rest.char_indices() {
+            units += c.len_utf16();
+            if units > limit {
+                break;
+            }
+            end = i + c.len_utf8();
+            if units > limit / 2 {
+                match c {
+                    '\n' => newline = Some(end),
+                    ' ' => space = Some(end),
+                    _ => {}
+                }
+            }
+        }
+        let cut = newline.or(space).unwrap_or(end);
+        pages.push(&rest[..cut]);
+        rest = &rest[cut..];
+    }
+    pages.push(rest);
+    pages
+}
+
+pub fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+/// The first line of `text` cut to `max_chars` characters with `…`, for titles and previews.
+pub fn headline(text: &str, max_chars: usize) -> String {
+    let line = text.lines().next().unwrap_or_default().trim();
+    if line.chars().count() <= max_chars {
+        return line.to_string();
+    }
+    let cut: String = line.chars().take(max_chars - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// Entities of the `length` units long part of a text starting at `start`,
+/// cut to that part and moved so their offsets count from its beginning.
+pub fn clip_entities(
+    entities: &[MessageEntity],
+    start: usize,
+    length: usize,
+) -> Vec<MessageEntity> {
+    let end = start + length;
+    entities
+        .iter()
+        .filter_map(|entity| {
+            let from = entity.offset.max(start);
+            let to = (entity.offset + entity.length).min(end);
+            (from < to).then(|| MessageEntity {
+                offset: from - start,
+                length: to - from,
+                ..entity.clone()
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cuts_headline() {
+        let cut = headline(&"word ".repeat(30), 80);
+        assert_eq!(cut.chars().count(), 80);
+        assert!(cut.ends_with("word…"));
+        assert_eq!(headline("first\nsecond", 80), "first");
+        assert_eq!(headline("", 80), "");
+    }
+
+    #[test]
+    fn keeps_short_text_whole() {
+        assert_eq!(split("short", MESSAGE_LIMIT), vec!["short"]);
+        assert_eq!(split("", MESSAGE_LIMIT), vec![""]);
+    }
+
+    #[test]
+    fn breaks_at_newlines_then_spaces() {
+        assert_eq!(
+            split("one two\nthree four", 12),
+            vec!["one two\n", "three four"]
+        );
+        assert_eq!(split("one two three", 10), vec!["one two ", "three"]);
+        assert_eq!(split("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+    }
+
+    fn entity(offset: usize, length: usize) -> MessageEntity {
+        MessageEntity {
+            entity_type: "bold".to_string(),
+            offset,
+            length,
+            url: None,
+            user: None,
+            language: None,
+            custom_emoji_id: None,
+        }
+    }
+
+    #[test]
+    fn clips_entities_to_page() {
+        let entities = [entity(0, 3), entity(4, 6), entity(12, 2)];
+        let clipped: Vec<_> = clip_entities(&entities, 8, 4)
+            .iter()
+            .map(|e| (e.offset, e.length))
+            .collect();
+        assert_eq!(clipped, vec![(0, 2)]);
+        let shifted: Vec<_> = clip_entities(&entities, 0, 100)
+            .iter()
+            .map(|e| (e.offset, e.length))
+            .collect();
+        assert_eq!(shifted, vec![(0, 3), (4, 6), (12, 2)]);
+    }
+
+    #[test]
+    fn counts_utf16_units() {
+        // every emoji takes two units
+        let text = "😀".repeat(5);
+        let pages = split(&text, 4);
+        assert_eq!(pages, vec!["😀😀", "😀😀", "😀"]);
+        assert_eq!(pages.concat(), text);
+    }
+}
@@ -7,7 +7,7 @@ pub struct PgService {
 
 impl PgService {
     pub async fn new() -> Self {
-        let pg_pool = PgPool::connect(&*dotenv::var("DATABASE_URL").unwrap())
+        let pg_pool = PgPool::connect(&dotenv::var("DATABASE_URL").unwrap())
             .await
             .expect("Cant connect to the pg server");
         Self { pg_pool }
@@ -0,0 +1,38 @@
+use mobc::Pool;
+use mobc_redis::redis;
+use mobc_redis::RedisConnectionManager;
+
+const DEFAULT_CACHE_TTL: usize = 600;
+const MAX_OPEN_CONNECTIONS: u64 = 16;
+
+/// Pooled Redis client backing the history cache.
+/// With `REDIS_CACHE_ENABLED=false` or an empty `REDIS_URL` there is no pool and caching is off.
+#[derive(Clone)]
+pub struct RedisService {
+    pub pool: Option<Pool<RedisConnectionManager>>,
+    pub ttl: usize,
+}
+
+impl RedisService {
+    pub async fn new() -> Self {
+        let enabled = dotenv::var("REDIS_CACHE_ENABLED")
+            .map(|v| v != "false" && v != "0")
+            .unwrap_or(true);
+        let url = dotenv::var("REDIS_URL").unwrap_or_default();
+        let ttl = dotenv::var("REDIS_CACHE_TTL")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_CACHE_TTL);
+        if !enabled || url.is_empty() {
+            return Self { pool: None, ttl };
+        }
+        let client = redis::Client::open(url).expect("Cant parse the redis url");
+        let pool = Pool::builder()
+            .max_open(MAX_OPEN_CONNECTIONS)
+            .build(RedisConnectionManager::new(client));
+        Self {
+            pool: Some(pool),
+            ttl,
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Reminders set with `/remind` or the ⏰ button of the history browser.
+//!
+//! They live in the `reminder` table, a task of the bot looks for due ones every `TICK`
+//! seconds. Reminders that fell due while the bot was down go out on its first tick
+//! after the start and say when they were due.
+
+use crate::i18n;
+use crate::storage::Storage;
+use crate::tg_service::{self, TgClient};
+use crate::web::{SendMessage, WMessage};
+use crate::when;
+use anyhow::Result;
+use chrono::Utc;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// Seconds between two looks for due reminders.
+pub const TICK: u64 = 30;
+
+/// A reminder sent later than this many seconds after it was due says so.
+const LATE_AFTER: i64 = 120;
+
+/// Reminders of saved messages quote this many characters of their first line.
+pub const PREVIEW_CHARS: usize = 200;
+
+/// Delays offered by the ⏰ button, the key of its label and the `/remind:` argument
+/// for `when::parse`.
+pub const PRESETS: [(&str, &str); 4] = [
+    ("remind-in-hour", "1h"),
+    ("remind-in-hours", "3h"),
+    ("remind-tomorrow", "tomorrow"),
+    ("remind-in-week", "1w"),
+];
+
+/// Sends every reminder due at `now`, as a reply to its message when it has one
+/// and without a sound when it is silent.
+/// Reminders Telegram refuses to deliver are dropped. Network errors, rate limits and
+/// errors of Telegram stop the round and leave the rest pending for the next tick.
+/// Returns the number of sent reminders.
+pub async fn deliver_due(tg_client: &TgClient, storage: &dyn Storage, now: i64) -> Result<usize> {
+    let mut delivered = 0;
+    for reminder in storage.select_due_reminders(now).await? {
+        let mut text = format!("⏰ {}", reminder.text);
+        if now - reminder.due_at > LATE_AFTER {
+            let date = when::human_date(reminder.due_at, reminder.tz());
+            let late = i18n::text(
+                &reminder.language,
+                "reminder-late",
+                &[("date", date.into())],
+            );
+            text.push_str(&format!("\n\n{}", late));
+        }
+        // A reminder of a message that is gone goes out as a plain message.
+        let send_message = SendMessage {
+            reply_to_message_id: reminder.message_id,
+            allow_sending_without_reply: reminder.message_id.map(|_| true),
+            disable_notification: reminder.silent.then_some(true),
+            ..SendMessage::new(reminder.chat_id, text)
+        };
+        match tg_client
+            .call::<_, WMessage>("sendMessage", &send_message)
+            .await
+        {
+            Ok(_) => delivered += 1,
+            Err(e) if tg_service::is_rejected(&e) => eprintln!("{:?}", e),
+            Err(e) => return Err(e),
+        }
+        storage.mark_reminder_sent(reminder.id, now).await?;
+    }
+    Ok(delivered)
+}
+
+/// Runs `deliver_due` every `TICK` seconds, starting right away.
+pub fn spawn(tg_client: Arc<TgClient>, storage: Arc<dyn Storage>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(TICK));
+        loop {
+            interval.tick().await;
+            let now = Utc::now().timestamp();
+            if let Err(e) = deliver_due(&tg_client, storage.as_ref(), now).await {
+                eprintln!("{:?}", e);
+                // a burst of reminders missed during downtime hits the rate limit
+                if let Some(seconds) = tg_service::retry_after(&e) {
+                    tokio::time::sleep(std::time::Duration::from_secs(seconds)).await;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::Reminder;
+    use crate::storage::MemoryStorage;
+    use crate::testkit::FakeBotApi;
+    use chrono::{TimeZone, Utc};
+
+    fn reminder(message_id: Option<i64>, text: &str, due_at: i64) -> Reminder {
+        Reminder {
+            id: 0,
+            chat_id: 42,
+            message_id,
+            text: text.to_string(),
+            due_at,
+            created_at: 0,
+            sent_at: None,
+            silent: false,
+            language: "en".to_string(),
+            timezone: "UTC".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn delivers_due_reminders_once() {
+        let api = FakeBotApi::start().await;
+        let storage = MemoryStorage::new();
+        storage
+            .insert_reminder(&reminder(Some(7), "call Ann", 1000))
+            .await
+            .unwrap();
+        storage
+            .insert_reminder(&reminder(None, "later", 5000))
+            .await
+            .unwrap();
+
+        let client = api.client();
+        assert_eq!(deliver_due(&client, &storage, 1060).await.unwrap(), 1);
+        assert_eq!(deliver_due(&client, &storage, 1090).await.unwrap(), 0);
+        let sent = api.calls_to("sendMessage");
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["text"], "⏰ call Ann");
+        assert_eq!(sent[0]["reply_to_message_id"], 7);
+        assert_eq!(sent[0]["allow_sending_without_reply"], true);
+        assert!(sent[0].get("disable_notification").is_none());
+    }
+
+    #[tokio::test]
+    async fn sends_silent_reminders_without_sound() {
+        let api = FakeBotApi::start().await;
+        let storage = MemoryStorage::new();
+        let silent = Reminder {
+            silent: true,
+            ..reminder(None, "stretch", 1000)
+        };
+        storage.insert_reminder(&silent).await.unwrap();
+
+        deliver_due(&api.client(), &storage, 1000).await.unwrap();
+        assert_eq!(api.calls_to("sendMessage")[0]["disable_notification"], true);
+    }
+
+    #[tokio::test]
+    async fn says_when_a_missed_reminder_was_due() {
+        let api = FakeBotApi::start().await;
+        let storage = MemoryStorage::new();
+        let due_at = Utc
+            .with_ymd_and_hms(2022, 6, 23, 12, 0, 0)
+            .unwrap()
+            .timestamp();
+        storage
+            .insert_reminder(&reminder(None, "rent", due_at))
+            .await
+            .unwrap();
+
+        deliver_due(&api.client(), &storage, due_at + 3600)
+            .await
+            .unwrap();
+        assert_eq!(
+            api.sent_texts(),
+            ["⏰ rent\n\nThis was due 2022-06-23 12:00 UTC, the bot was offline then"]
+        );
+        assert!(api.calls_to("sendMessage")[0]
+            .get("reply_to_message_id")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn dates_missed_reminders_in_the_timezone_they_were_set_in() {
+        let api = FakeBotApi::start().await;
+        let storage = MemoryStorage::new();
+        let due_at = Utc
+            .with_ymd_and_hms(2022, 6, 23, 12, 0, 0)
+            .unwrap()
+            .timestamp();
+        let moscow = Reminder {
+            timezone: "Europe/Moscow".to_string(),
+            ..reminder(None, "rent", due_at)
+        };
+        storage.insert_reminder(&moscow).await.unwrap();
+
+        deliver_due(&api.client(), &storage, due_at + 3600)
+            .await
+            .unwrap();
+        assert_eq!(
+            api.sent_texts(),
+            ["⏰ rent\n\nThis was due 2022-06-23 15:00 MSK, the bot was offline then"]
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_reminders_pending_when_rate_limited() {
+        let api = FakeBotApi::start().await;
+        let storage = MemoryStorage::new();
+        for text in ["rent", "gym"] {
+            storage
+                .insert_reminder(&reminder(None, text, 1000))
+                .await
+                .unwrap();
+        }
+        api.fail_next(
+            "sendMessage",
+            serde_json::json!({
+                "ok": false,
+                "error_code": 429,
+                "description": "Too Many Requests: retry after 5",
+                "parameters": { "retry_after": 5 },
+            }),
+        );
+
+        let client = api.client();
+        let e = deliver_due(&client, &storage, 1000).await.unwrap_err();
+        assert_eq!(tg_service::retry_after(&e), Some(5));
+        assert_eq!(storage.select_due_reminders(1000).await.unwrap().len(), 2);
+
+        assert_eq!(deliver_due(&client, &storage, 1030).await.unwrap(), 2);
+        // the first try was answered with 429
+        assert_eq!(api.sent_texts(), ["⏰ rent", "⏰ rent", "⏰ gym"]);
+    }
+
+    #[tokio::test]
+    async fn drops_reminders_telegram_refuses() {
+        let api = FakeBotApi::start().await;
+        let storage = MemoryStorage::new();
+        storage
+            .insert_reminder(&reminder(None, "rent", 1000))
+            .await
+            .unwrap();
+        api.fail_next(
+            "sendMessage",
+            serde_json::json!({
+                "ok": false,
+                "error_code": 403,
+                "description": "Forbidden: bot was blocked by the user",
+            }),
+        );
+
+        assert_eq!(deliver_due(&api.client(), &storage, 1000).await.unwrap(), 0);
+        assert!(storage.select_due_reminders(1000).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+use crate::i18n;
+use crate::models::UserSettings;
+use crate::storage::Storage;
+use crate::tg_service::TgClient;
+use crate::web::{BotCommand, BotCommandScope, WUpdate};
+use anyhow::Result;
+use async_trait::async_trait;
+use fluent_bundle::FluentValue;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// What a handler gets for one update.
+pub struct Context<'a> {
+    pub tg_client: &'a TgClient,
+    pub storage: &'a dyn Storage,
+    pub update: &'a WUpdate,
+    /// Text after the command (`/history 2022-01-01`) or after `:` in callback data (`/jump:12`).
+    pub args: &'a str,
+    /// Settings of whoever sent the update, defaults when the user never changed them.
+    pub settings: &'a UserSettings,
+}
+
+impl Context<'_> {
+    pub fn chat_id(&self) -> Option<i64> {
+        self.update.chat_id()
+    }
+
+    /// `i18n::text` in the language of whoever sent the update.
+    pub fn text(&self, key: &str, args: &[(&str, FluentValue)]) -> String {
+        i18n::text(&self.settings.language, key, args)
+    }
+
+    pub fn language(&self) -> &str {
+        &self.settings.language
+    }
+}
+
+impl Context<'_> {
+    /// Whether whoever sent the update may change the whole chat: anyone in a private chat,
+    /// the creator and administrators in groups.
+    pub async fn is_admin(&self) -> Result<bool> {
+        match (self.chat_id(), self.update.user_id()) {
+            // private chats have the positive id of the user, groups and channels negative ones
+            (Some(chat_id), _) if chat_id > 0 => Ok(true),
+            (Some(chat_id), Some(user_id)) => self.tg_client.is_chat_admin(chat_id, user_id).await,
+            _ => Ok(false),
+        }
+    }
+
+    /// The bot message whose inline button was pressed.
+    pub fn callback_message_id(&self) -> Option<i64> {
+        self.update
+            .callback_query
+            .as_ref()
+            .map(|wc| wc.message.message_id)
+    }
+}
+
+#[async_trait]
+pub trait Handler: Send + Sync {
+    async fn handle(&self, ctx: &Context<'_>) -> Result<()>;
+}
+
+/// Who finds a command in the menu Telegram shows next to the input field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Audience {
+    /// Private chats and every member of a group.
+    Everyone,
+    /// Private chats and group administrators, for commands that change the whole chat.
+    Admins,
+}
+
+/// Routes commands, callback data, plain messages and edits to their handlers.
+/// Messages starting with an unknown command are treated as plain messages.
+#[derive(Default, Clone)]
+pub struct Router {
+    commands: HashMap<String, Arc<dyn Handler>>,
+    /// Commands in the order they were added, for `menu`.
+    menu: Vec<(String, Audience)>,
+    callbacks: HashMap<String, Arc<dyn Handler>>,
+    message: Option<Arc<dyn Handler>>,
+    edited_message: Option<Arc<dyn Handler>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn command(&mut self, command: &str, handler: Arc<dyn Handler>) {
+        self.command_for(command, Audience::Everyone, handler);
+    }
+
+    /// Adds a command to the menu of `audience`, its description is the `command-<name>` text.
+    pub fn command_for(&mut self, command: &str, audience: Audience, handler: Arc<dyn Handler>) {
+        self.commands.insert(command.to_string(), handler);
+        match self.menu.iter_mut().find(|(c, _)| c == command) {
+            Some((_, a)) => *a = audience,
+            None => self.menu.push((command.to_string(), audience)),
+        }
+    }
+
+    /// Commands for `setMyCommands` in `scope`, described in `language`.
+    pub fn menu(&self, scope: BotCommandScope, language: &str) -> Vec<BotCommand> {
+        self.menu
+            .iter()
+            .filter(|(_, audience)| {
+                *audience == Audience::Everyone || scope != BotCommandScope::AllGroupChats
+            })
+            .map(|(command, _)| {
+                let command = command.trim_start_matches('/');
+                BotCommand {
+                    command: command.to_string(),
+                    description: i18n::text(language, &format!("command-{}", command), &[]),
+                }
+            })
+            .collect()
+    }
+
+    pub fn callback(&mut self, data: &str, handler: Arc<dyn Handler>) {
+        self.callbacks.insert(data.to_string(), handler);
+    }
+
+    pub fn message(&mut self, handler: Arc<dyn Handler>) {
+        self.message = Some(handler);
+    }
+
+    pub fn edited_message(&mut self, handler: Arc<dyn Handler>) {
+        self.edited_message = Some(handler);
+    }
+
+    pub async fn dispatch(
+        &self,
+        tg_client: &TgClient,
+        storage: &dyn Storage,
+        update: &WUpdate,
+    ) -> Result<()> {
+        let settings = UserSettings::load(storage, update).await?;
+        let ctx = |args| Context {
+            tg_client,
+            storage,
+            update,
+            args,
+            settings: &settings,
+        };
+        if update.edited_message.is_some() {
+            if let Some(handler) = &self.edited_message {
+                handler.handle(&ctx("")).await?;
+            }
+        }
+        if let Some(wm) = &update.message {
+            // Documents carry their command in the caption.
+            let text = match &wm.caption {
+                Some(caption) if wm.text.is_empty() => caption,
+                _ => &wm.text,
+            };
+            let (command, args) = split_command(text);
+            match self.commands.get(command) {
+                Some(handler) => handler.handle(&ctx(args)).await?,
+                None => {
+                    if let Some(handler) = &self.message {
+                        handler.handle(&ctx("")).await?;
+                    }
+                }
+            }
+        }
+        if let Some(wc) = &update.callback_query {
+            let (data, args) = wc.data.split_once(':').unwrap_or((&wc.data, ""));
+            match self.callbacks.get(data) {
+                Some(handler) => handler.handle(&ctx(args)).await?,
+                None => eprintln!("no handler for callback {:?}", wc.data),
+            }
+        }
+        Ok(())
+    }
+}
+
+// "/history@my_bot 2022" -> ("/history", "2022")
+fn split_command(text: &str) -> (&str, &str) {
+    if !text.starts_with('/') {
+        return ("", text);
+    }
+    let (command, args) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let command = command.split('@').next().unwrap_or(command);
+    (command, args.trim())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_command_and_args() {
+        assert_eq!(split_command("/history"), ("/history", ""));
+        assert_eq!(
+            split_command("/history@tg_bot  2022-01-01 "),
+            ("/history", "2022-01-01")
+        );
+        assert_eq!(split_command("just a note"), ("", "just a note"));
+    }
+
+    struct Nothing;
+
+    #[async_trait]
+    impl Handler for Nothing {
+        async fn handle(&self, _ctx: &Context<'_>) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn menu_hides_admin_commands_from_groups() {
+        let mut router = Router::new();
+        router.command_for("/history", Audience::Admins, Arc::new(Nothing));
+        router.command_for("/forget", Audience::Admins, Arc::new(Nothing));
+        router.command("/trash", Arc::new(Nothing));
+        // replacing a handler keeps the place in the menu
+        router.command("/history", Arc::new(Nothing));
+
+        let names = |scope| -> Vec<String> {
+            router
+                .menu(scope, "en")
+                .into_iter()
+                .map(|c| c.command)
+                .collect()
+        };
+        assert_eq!(names(BotCommandScope::AllGroupChats), ["history", "trash"]);
+        assert_eq!(
+            names(BotCommandScope::AllChatAdministrators),
+            ["history", "forget", "trash"]
+        );
+        assert_eq!(
+            names(BotCommandScope::AllPrivateChats),
+            names(BotCommandScope::AllChatAdministrators)
+        );
+
+        let trash = &router.menu(BotCommandScope::AllPrivateChats, "ru")[2];
+        assert_eq!(
+            trash.description,
+            "Удалённые сообщения, их можно восстановить"
+        );
+    }
+}
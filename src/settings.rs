@@ -0,0 +1,130 @@
+//! What `/settings` lets a user change, see `UserSettings`. The menu buttons and
+//! `/settings <key> <value>` both go through `apply`.
+
+use crate::export;
+use crate::i18n;
+use crate::models::UserSettings;
+use chrono::Utc;
+use chrono_tz::Tz;
+
+/// Interface languages, the code kept in `UserSettings::language` and its name.
+pub const LANGUAGES: [(&str, &str); 2] = [("en", "English"), ("ru", "Русский")];
+
+/// Timezones offered by the menu, `/settings tz <name>` takes any IANA name.
+pub const TIMEZONES: [&str; 12] = [
+    "UTC",
+    "Europe/London",
+    "Europe/Berlin",
+    "Europe/Kyiv",
+    "Europe/Moscow",
+    "Asia/Yekaterinburg",
+    "Asia/Novosibirsk",
+    "Asia/Dubai",
+    "Asia/Kolkata",
+    "Asia/Tokyo",
+    "America/New_York",
+    "America/Los_Angeles",
+];
+
+/// Formats `/export` takes, in the order the menu shows them.
+pub const EXPORT_FORMATS: [&str; 4] = ["json", "csv", "md", "html"];
+
+/// Text of the menu message, in the language of the settings.
+pub fn describe(settings: &UserSettings) -> String {
+    let now = Utc::now().with_timezone(&settings.tz());
+    let quiet = if settings.quiet_reminders {
+        "on"
+    } else {
+        "off"
+    };
+    i18n::text(
+        &settings.language,
+        "settings-summary",
+        &[
+            ("timezone", settings.timezone.as_str().into()),
+            ("time", now.format("%H:%M").to_string().into()),
+            ("language", language_name(&settings.language).into()),
+            ("format", settings.export_format.as_str().into()),
+            ("quiet", quiet.into()),
+        ],
+    )
+}
+
+pub fn language_name(code: &str) -> &str {
+    LANGUAGES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map_or(code, |(_, name)| name)
+}
+
+/// Sets `key` (`tz`, `lang`, `export` or `quiet`) to `value`, the error is a text for the user
+/// in the language the settings had.
+pub fn apply(settings: &mut UserSettings, key: &str, value: &str) -> Result<(), String> {
+    let value = value.trim();
+    let error = |key, args: &[_]| Err(i18n::text(&settings.language, key, args));
+    match key {
+        "tz" | "timezone" => match value.parse::<Tz>() {
+            Ok(tz) => settings.timezone = tz.name().to_string(),
+            Err(_) => {
+                let name = format!("{:?}", value);
+                return error("settings-bad-timezone", &[("name", name.into())]);
+            }
+        },
+        "lang" | "language" => {
+            let value = value.to_lowercase();
+            match LANGUAGES.iter().find(|(code, _)| *code == value) {
+                Some((code, _)) => settings.language = code.to_string(),
+                None => return error("settings-bad-language", &[]),
+            }
+        }
+        "export" => match export::Format::parse(value).filter(|_| !value.is_empty()) {
+            Some(format) => settings.export_format = format.extension().to_string(),
+            None => return error("settings-bad-format", &[]),
+        },
+        "quiet" => match value {
+            "on" => settings.quiet_reminders = true,
+            "off" => settings.quiet_reminders = false,
+            _ => return error("settings-bad-quiet", &[]),
+        },
+        _ => return error("settings-usage", &[]),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn applies_settings() {
+        let mut settings = UserSettings::new(7, None);
+        apply(&mut settings, "tz", "Europe/Moscow").unwrap();
+        apply(&mut settings, "lang", "RU").unwrap();
+        apply(&mut settings, "export", "markdown").unwrap();
+        apply(&mut settings, "quiet", "on").unwrap();
+        assert_eq!(settings.timezone, "Europe/Moscow");
+        assert_eq!(settings.language, "ru");
+        assert_eq!(settings.export_format, "md");
+        assert!(settings.quiet_reminders);
+
+        assert_eq!(
+            apply(&mut settings, "tz", "Mars/Olympus"),
+            Err(
+                "Неизвестный часовой пояс \"Mars/Olympus\", укажите название \
+                 вроде Europe/Moscow или Asia/Yekaterinburg"
+                    .to_string()
+            )
+        );
+        assert!(apply(&mut settings, "lang", "de").is_err());
+        assert!(apply(&mut settings, "export", "").is_err());
+        assert!(apply(&mut settings, "colour", "red").is_err());
+        assert_eq!(settings.timezone, "Europe/Moscow");
+    }
+
+    #[test]
+    fn offers_known_timezones() {
+        for name in TIMEZONES {
+            assert!(name.parse::<Tz>().is_ok(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use crate::models::{
+    ApiKey, Chat, ChatFeed, ChatSummary, EditedMessage, Filter, LinkMessage, LoggedUpdate, Message,
+    MessageRevision, MessageTag, PurgeRecord, Reminder, TagSummary, Update, User, UserSettings,
+};
+use crate::redis_service::RedisService;
+use crate::storage::Storage;
+use anyhow::Result;
+use async_trait::async_trait;
+use mobc::{Connection, Pool};
+use mobc_redis::redis::{self, AsyncCommands};
+use mobc_redis::RedisConnectionManager;
+use std::sync::Arc;
+
+/// Read-through cache of per-chat message id sequences used by history navigation.
+/// Ids live in a sorted set scored by message id, messages as JSON in a hash keyed by message id.
+/// Inserts, edits and deletes drop the chat's keys, cache errors are only logged.
+/// Filtered browsing goes straight to the inner storage.
+pub struct CachedStorage {
+    inner: Arc<dyn Storage>,
+    redis: RedisService,
+}
+
+enum Lookup {
+    First,
+    Next(i64),
+    Previous(i64),
+    Last,
+}
+
+impl CachedStorage {
+    pub fn new(inner: Arc<dyn Storage>, redis: RedisService) -> Self {
+        Self { inner, redis }
+    }
+
+    async fn invalidate(&self, chat_id: i64) {
+        let pool = match &self.redis.pool {
+            Some(pool) => pool,
+            None => return,
+        };
+        let result = async {
+            let mut conn = pool.get().await?;
+            conn.del::<_, ()>(&[ids_key(chat_id), messages_key(chat_id)])
+                .await?;
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+        if let Err(e) = result {
+            eprintln!("{:?}", e)
+        }
+    }
+
+    async fn cached(&self, chat_id: i64, filter: &Filter, lookup: Lookup) -> Option<Message> {
+        if *filter != Filter::All {
+            return None;
+        }
+        let pool = self.redis.pool.as_ref()?;
+        match self.lookup(pool, chat_id, lookup).await {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                None
+            }
+        }
+    }
+
+    async fn lookup(
+        &self,
+        pool: &Pool<RedisConnectionManager>,
+        chat_id: i64,
+        lookup: Lookup,
+    ) -> Result<Option<Message>> {
+        let mut conn = pool.get().await?;
+        if !conn.exists::<_, bool>(ids_key(chat_id)).await? {
+            self.fill(&mut conn, chat_id).await?;
+        }
+        let ids: Vec<i64> = match lookup {
+            Lookup::First => conn.zrange(ids_key(chat_id), 0, 0).await?,
+            Lookup::Next(id) => {
+                conn.zrangebyscore_limit(ids_key(chat_id), format!("({}", id), "+inf", 0, 1)
+                    .await?
+            }
+            Lookup::Previous(id) => {
+                conn.zrevrangebyscore_limit(ids_key(chat_id), format!("({}", id), "-inf", 0, 1)
+                    .await?
+            }
+            Lookup::Last => conn.zrange(ids_key(chat_id), -1, -1).await?,
+        };
+        let message_id = match ids.first() {
+            Some(message_id) => *message_id,
+            None => return Ok(None),
+        };
+        let json: Option<String> = conn.hget(messages_key(chat_id), message_id).await?;
+        match json {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn fill(
+        &self,
+        conn: &mut Connection<RedisConnectionManager>,
+        chat_id: i64,
+    ) -> Result<()> {
+        let messages = self.inner.select_all_by_chat_id(chat_id).await?;
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let scores: Vec<(i64, i64)> = messages
+            .iter()
+            .map(|m| (m.message_id, m.message_id))
+            .collect();
+        let jsons = messages
+            .iter()
+            .map(|m| Ok((m.message_id, serde_json::to_string(m)?)))
+            .collect::<Result<Vec<(i64, String)>>>()?;
+        redis::pipe()
+            .atomic()
+            .del(&[ids_key(chat_id), messages_key(chat_id)])
+            .zadd_multiple(ids_key(chat_id), &scores)
+            .hset_multiple(messages_key(chat_id), &jsons)
+            .expire(ids_key(chat_id), self.redis.ttl)
+            .expire(messages_key(chat_id), self.redis.ttl)
+            .query_async::<_, ()>(&mut **conn)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for CachedStorage {
+    async fn insert_message(&self, message: &Message) -> Result<bool> {
+        let inserted = self.inner.insert_message(message).await?;
+        self.invalidate(message.chat_id).await;
+        Ok(inserted)
+    }
+
+    async fn insert_messages(&self, messages: &[Message]) -> Result<u64> {
+        let inserted = self.inner.insert_messages(messages).await?;
+        let mut chats: Vec<i64> = messages.iter().map(|m| m.chat_id).collect();
+        chats.sort_unstable();
+        chats.dedup();
+        for chat_id in chats {
+            self.invalidate(chat_id).await;
+        }
+        Ok(inserted)
+    }
+
+    async fn select_message(&self, chat_id: i64, message_id: i64) -> Result<Message> {
+        self.inner.select_message(chat_id, message_id).await
+    }
+
+    async fn select_first_message(&self, chat_id: i64, filter: &Filter) -> Result<Message> {
+        if let Some(message) = self.cached(chat_id, filter, Lookup::First).await {
+            return Ok(message);
+        }
+        self.inner.select_first_message(chat_id, filter).await
+    }
+
+    async fn select_next_message(&self, chat_id: i64, filter: &Filter, id: i64) -> Result<Message> {
+        if let Some(message) = self.cached(chat_id, filter, Lookup::Next(id)).await {
+            return Ok(message);
+        }
+        self.inner.select_next_message(chat_id, filter, id).await
+    }
+
+    async fn select_previous_message(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        id: i64,
+    ) -> Result<Message> {
+        if let Some(message) = self.cached(chat_id, filter, Lookup::Previous(id)).await {
+            return Ok(message);
+        }
+        self.inner
+            .select_previous_message(chat_id, filter, id)
+            .await
+    }
+
+    async fn select_last_message(&self, chat_id: i64, filter: &Filter) -> Result<Message> {
+        if let Some(message) = self.cached(chat_id, filter, Lookup::Last).await {
+            return Ok(message);
+        }
+        self.inner.select_last_message(chat_id, filter).await
+    }
+
+    async fn select_first_message_since(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        date: i64,
+    ) -> Result<Message> {
+        self.inner
+            .select_first_message_since(chat_id, filter, date)
+            .await
+    }
+
+    async fn select_all_by_chat_id(&self, chat_id: i64) -> Result<Vec<Message>> {
+        self.inner.select_all_by_chat_id(chat_id).await
+    }
+
+    async fn select_messages_after(
+        &self,
+        chat_id: i64,
+        after: i64,
+        limit: i64,
+    ) -> Result<Vec<Message>> {
+        self.inner
+            .select_messages_after(chat_id, after, limit)
+            .await
+    }
+
+    async fn select_latest_messages(&self, chat_id: i64, limit: i64) -> Result<Vec<Message>> {
+        self.inner.select_latest_messages(chat_id, limit).await
+    }
+
+    async fn search_messages(&self, chat_id: i64, query: &str, limit: i64) -> Result<Vec<Message>> {
+        self.inner.search_messages(chat_id, query, limit).await
+    }
+
+    async fn count_messages(&self, chat_id: i64, filter: &Filter) -> Result<i64> {
+        self.inner.count_messages(chat_id, filter).await
+    }
+
+    async fn message_position(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        message_id: i64,
+    ) -> Result<i64> {
+        self.inner
+            .message_position(chat_id, filter, message_id)
+            .await
+    }
+
+    async fn select_message_at(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        position: i64,
+    ) -> Result<Message> {
+        self.inner
+            .select_message_at(chat_id, filter, position)
+            .await
+    }
+
+    async fn change_message_text(&self, edited_message: &EditedMessage) -> Result<bool> {
+        let updated = self.inner.change_message_text(edited_message).await?;
+        self.invalidate(edited_message.chat_id).await;
+        Ok(updated)
+    }
+
+    async fn select_revisions(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Vec<MessageRevision>> {
+        self.inner.select_revisions(chat_id, message_id).await
+    }
+
+    async fn delete_message(&self, chat_id: i64, message_id: i64, deleted_at: i64) -> Result<()> {
+        self.inner
+            .delete_message(chat_id, message_id, deleted_at)
+            .await?;
+        self.invalidate(chat_id).await;
+        Ok(())
+    }
+
+    async fn restore_message(&self, chat_id: i64, message_id: i64) -> Result<()> {
+        self.inner.restore_message(chat_id, message_id).await?;
+        self.invalidate(chat_id).await;
+        Ok(())
+    }
+
+    async fn purge_message(&self, chat_id: i64, message_id: i64) -> Result<()> {
+        self.inner.purge_message(chat_id, message_id).await?;
+        self.invalidate(chat_id).await;
+        Ok(())
+    }
+
+    async fn upsert_chat(&self, chat: &Chat) -> Result<()> {
+        self.inner.upsert_chat(chat).await
+    }
+
+    async fn upsert_user(&self, user: &User) -> Result<()> {
+        self.inner.upsert_user(user).await
+    }
+
+    async fn select_user(&self, user_id: i64) -> Result<Option<User>> {
+        self.inner.select_user(user_id).await
+    }
+
+    async fn add_tags(&self, chat_id: i64, message_id: i64, tags: &[String]) -> Result<()> {
+        self.inner.add_tags(chat_id, message_id, tags).await
+    }
+
+    async fn list_tags(&self, chat_id: i64) -> Result<Vec<TagSummary>> {
+        self.inner.list_tags(chat_id).await
+    }
+
+    async fn select_message_tags(&self, chat_id: i64) -> Result<Vec<MessageTag>> {
+        self.inner.select_message_tags(chat_id).await
+    }
+
+    async fn toggle_favorite(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        self.inner.toggle_favorite(chat_id, message_id).await
+    }
+
+    async fn is_favorite(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        self.inner.is_favorite(chat_id, message_id).await
+    }
+
+    async fn insert_link(&self, link_message: &LinkMessage) -> Result<()> {
+        self.inner.insert_link(link_message).await
+    }
+
+    async fn delete_and_return_link(&self, chat_id: i64) -> Result<LinkMessage> {
+        self.inner.delete_and_return_link(chat_id).await
+    }
+
+    async fn select_link(&self, chat_id: i64) -> Result<LinkMessage> {
+        self.inner.select_link(chat_id).await
+    }
+
+    async fn insert_update(&self, update: &Update) -> Result<()> {
+        self.inner.insert_update(update).await
+    }
+
+    async fn get_last_update(&self) -> Result<Update> {
+        self.inner.get_last_update().await
+    }
+
+    async fn set_update_offset(&self, update_id: i64) -> Result<()> {
+        self.inner.set_update_offset(update_id).await
+    }
+
+    async fn log_update(&self, logged_update: &LoggedUpdate) -> Result<()> {
+        self.inner.log_update(logged_update).await
+    }
+
+    async fn select_logged_updates(&self, from_update_id: i64) -> Result<Vec<LoggedUpdate>> {
+        self.inner.select_logged_updates(from_update_id).await
+    }
+
+    async fn list_chats(&self) -> Result<Vec<ChatSummary>> {
+        self.inner.list_chats().await
+    }
+
+    async fn purge_chat(&self, chat_id: i64) -> Result<u64> {
+        let deleted = self.inner.purge_chat(chat_id).await?;
+        self.invalidate(chat_id).await;
+        Ok(deleted)
+    }
+
+    async fn log_purge(&self, record: &PurgeRecord) -> Result<()> {
+        self.inner.log_purge(record).await
+    }
+
+    async fn list_purges(&self) -> Result<Vec<PurgeRecord>> {
+        self.inner.list_purges().await
+    }
+
+    async fn insert_api_key(&self, api_key: &ApiKey) -> Result<()> {
+        self.inner.insert_api_key(api_key).await
+    }
+
+    async fn select_api_key(&self, key: &str) -> Result<Option<ApiKey>> {
+        self.inner.select_api_key(key).await
+    }
+
+    async fn revoke_api_keys(&self, chat_id: i64) -> Result<u64> {
+        self.inner.revoke_api_keys(chat_id).await
+    }
+
+    async fn upsert_feed(&self, feed: &ChatFeed) -> Result<()> {
+        self.inner.upsert_feed(feed).await
+    }
+
+    async fn select_feed(&self, token: &str) -> Result<Option<ChatFeed>> {
+        self.inner.select_feed(token).await
+    }
+
+    async fn select_chat_feed(&self, chat_id: i64) -> Result<Option<ChatFeed>> {
+        self.inner.select_chat_feed(chat_id).await
+    }
+
+    async fn delete_feed(&self, chat_id: i64) -> Result<bool> {
+        self.inner.delete_feed(chat_id).await
+    }
+
+    async fn insert_reminder(&self, reminder: &Reminder) -> Result<i64> {
+        self.inner.insert_reminder(reminder).await
+    }
+
+    async fn select_due_reminders(&self, now: i64) -> Result<Vec<Reminder>> {
+        self.inner.select_due_reminders(now).await
+    }
+
+    async fn select_reminders(&self, chat_id: i64) -> Result<Vec<Reminder>> {
+        self.inner.select_reminders(chat_id).await
+    }
+
+    async fn mark_reminder_sent(&self, id: i64, sent_at: i64) -> Result<()> {
+        self.inner.mark_reminder_sent(id, sent_at).await
+    }
+
+    async fn delete_reminder(&self, chat_id: i64, id: i64) -> Result<bool> {
+        self.inner.delete_reminder(chat_id, id).await
+    }
+
+    async fn select_user_settings(&self, user_id: i64) -> Result<Option<UserSettings>> {
+        self.inner.select_user_settings(user_id).await
+    }
+
+    async fn upsert_user_settings(&self, settings: &UserSettings) -> Result<()> {
+        self.inner.upsert_user_settings(settings).await
+    }
+
+    async fn migrate(&self) -> Result<()> {
+        self.inner.migrate().await
+    }
+}
+
+fn ids_key(chat_id: i64) -> String {
+    format!("history:{}:ids", chat_id)
+}
+
+fn messages_key(chat_id: i64) -> String {
+    format!("history:{}:messages", chat_id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+
+    #[tokio::test]
+    async fn navigates_through_without_redis() {
+        let redis = RedisService {
+            pool: None,
+            ttl: 600,
+        };
+        let storage = CachedStorage::new(Arc::new(MemoryStorage::new()), redis);
+        crate::storage::check_navigation(&storage).await;
+    }
+
+    /// Runs when `REDIS_URL` points at a server the test may write to.
+    #[tokio::test]
+    async fn navigates_through_redis() {
+        let url = match std::env::var("REDIS_URL") {
+            Ok(url) if !url.is_empty() => url,
+            _ => return,
+        };
+        let client = redis::Client::open(url).unwrap();
+        let redis = RedisService {
+            pool: Some(Pool::builder().build(RedisConnectionManager::new(client))),
+            ttl: 600,
+        };
+        let storage = CachedStorage::new(Arc::new(MemoryStorage::new()), redis);
+        crate::storage::check_navigation(&storage).await;
+    }
+}
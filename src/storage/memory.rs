@@ -0,0 +1,747 @@
+use crate::models::{
+    ApiKey, Chat, ChatFeed, ChatSummary, EditedMessage, Filter, LinkMessage, LoggedUpdate, Message,
+    MessageRevision, MessageTag, PurgeRecord, Reminder, TagSummary, Update, User, UserSettings,
+};
+use crate::storage::Storage;
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::Error;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::{Bound, RangeBounds};
+use std::sync::Mutex;
+
+/// Keeps everything in process memory, meant for unit tests.
+#[derive(Debug)]
+pub struct MemoryStorage {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Keyed by `(chat_id, message_id)`, message ids are only unique within a chat.
+    messages: BTreeMap<(i64, i64), Message>,
+    chats: BTreeMap<i64, Chat>,
+    users: BTreeMap<i64, User>,
+    links: Vec<LinkMessage>,
+    update: Update,
+    update_log: BTreeMap<i64, LoggedUpdate>,
+    purges: Vec<PurgeRecord>,
+    /// Ids of the messages tagged with `(chat_id, name)`.
+    tags: BTreeMap<(i64, String), BTreeSet<i64>>,
+    /// Starred `(chat_id, message_id)`.
+    favorites: BTreeSet<(i64, i64)>,
+    revisions: Vec<MessageRevision>,
+    api_keys: BTreeMap<String, ApiKey>,
+    feeds: BTreeMap<i64, ChatFeed>,
+    reminders: BTreeMap<i64, Reminder>,
+    user_settings: BTreeMap<i64, UserSettings>,
+}
+
+impl State {
+    fn matches(&self, filter: &Filter, message: &Message) -> bool {
+        if message.deleted_at.is_some() != filter.trash() {
+            return false;
+        }
+        match filter {
+            Filter::All | Filter::Trash => true,
+            Filter::Tag(name) => self
+                .tags
+                .get(&(message.chat_id, name.clone()))
+                .is_some_and(|ids| ids.contains(&message.message_id)),
+            Filter::Favorites => self
+                .favorites
+                .contains(&(message.chat_id, message.message_id)),
+        }
+    }
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                messages: BTreeMap::new(),
+                chats: BTreeMap::new(),
+                users: BTreeMap::new(),
+                links: Vec::new(),
+                update: Update {
+                    id: 1,
+                    update_id: 1,
+                },
+                update_log: BTreeMap::new(),
+                purges: Vec::new(),
+                tags: BTreeMap::new(),
+                favorites: BTreeSet::new(),
+                revisions: Vec::new(),
+                api_keys: BTreeMap::new(),
+                feeds: BTreeMap::new(),
+                reminders: BTreeMap::new(),
+                user_settings: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Messages of the chat with ids in `ids`, ordered by id.
+    fn chat(
+        messages: &BTreeMap<(i64, i64), Message>,
+        chat_id: i64,
+        ids: impl RangeBounds<i64>,
+    ) -> impl DoubleEndedIterator<Item = &Message> {
+        let start = match ids.start_bound() {
+            Bound::Included(id) => Bound::Included((chat_id, *id)),
+            Bound::Excluded(id) => Bound::Excluded((chat_id, *id)),
+            Bound::Unbounded => Bound::Included((chat_id, i64::MIN)),
+        };
+        let end = match ids.end_bound() {
+            Bound::Included(id) => Bound::Included((chat_id, *id)),
+            Bound::Excluded(id) => Bound::Excluded((chat_id, *id)),
+            Bound::Unbounded => Bound::Included((chat_id, i64::MAX)),
+        };
+        messages.range((start, end)).map(|(_, m)| m)
+    }
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn insert_message(&self, message: &Message) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let key = (message.chat_id, message.message_id);
+        if state.messages.contains_key(&key) {
+            return Ok(false);
+        }
+        state.messages.insert(key, message.clone());
+        Ok(true)
+    }
+
+    async fn insert_messages(&self, messages: &[Message]) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let mut inserted = 0;
+        for message in messages {
+            let key = (message.chat_id, message.message_id);
+            if let Entry::Vacant(entry) = state.messages.entry(key) {
+                entry.insert(message.clone());
+                inserted += 1;
+            }
+        }
+        Ok(inserted)
+    }
+
+    async fn select_message(&self, chat_id: i64, message_id: i64) -> Result<Message> {
+        let state = self.state.lock().unwrap();
+        let message = state.messages.get(&(chat_id, message_id));
+        message.cloned().ok_or_else(|| Error::RowNotFound.into())
+    }
+
+    async fn select_first_message(&self, chat_id: i64, filter: &Filter) -> Result<Message> {
+        let state = self.state.lock().unwrap();
+        let first = Self::chat(&state.messages, chat_id, ..).find(|m| state.matches(filter, m));
+        first.cloned().ok_or_else(|| Error::RowNotFound.into())
+    }
+
+    async fn select_next_message(&self, chat_id: i64, filter: &Filter, id: i64) -> Result<Message> {
+        let state = self.state.lock().unwrap();
+        let next = Self::chat(
+            &state.messages,
+            chat_id,
+            (Bound::Excluded(id), Bound::Unbounded),
+        )
+        .find(|m| state.matches(filter, m));
+        next.cloned().ok_or_else(|| Error::RowNotFound.into())
+    }
+
+    async fn select_previous_message(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        id: i64,
+    ) -> Result<Message> {
+        let state = self.state.lock().unwrap();
+        let previous = Self::chat(&state.messages, chat_id, ..id)
+            .rev()
+            .find(|m| state.matches(filter, m));
+        previous.cloned().ok_or_else(|| Error::RowNotFound.into())
+    }
+
+    async fn select_last_message(&self, chat_id: i64, filter: &Filter) -> Result<Message> {
+        let state = self.state.lock().unwrap();
+        let last = Self::chat(&state.messages, chat_id, ..)
+            .rev()
+            .find(|m| state.matches(filter, m));
+        last.cloned().ok_or_else(|| Error::RowNotFound.into())
+    }
+
+    async fn select_first_message_since(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        date: i64,
+    ) -> Result<Message> {
+        let state = self.state.lock().unwrap();
+        let first = Self::chat(&state.messages, chat_id, ..)
+            .find(|m| m.date >= date && state.matches(filter, m));
+        first.cloned().ok_or_else(|| Error::RowNotFound.into())
+    }
+
+    async fn select_all_by_chat_id(&self, chat_id: i64) -> Result<Vec<Message>> {
+        let state = self.state.lock().unwrap();
+        let kept = Self::chat(&state.messages, chat_id, ..).filter(|m| m.deleted_at.is_none());
+        Ok(kept.cloned().collect())
+    }
+
+    async fn select_messages_after(
+        &self,
+        chat_id: i64,
+        after: i64,
+        limit: i64,
+    ) -> Result<Vec<Message>> {
+        let state = self.state.lock().unwrap();
+        let kept = Self::chat(
+            &state.messages,
+            chat_id,
+            (Bound::Excluded(after), Bound::Unbounded),
+        )
+        .filter(|m| m.deleted_at.is_none());
+        Ok(kept.take(limit.max(0) as usize).cloned().collect())
+    }
+
+    async fn select_latest_messages(&self, chat_id: i64, limit: i64) -> Result<Vec<Message>> {
+        let state = self.state.lock().unwrap();
+        let mut latest: Vec<Message> = Self::chat(&state.messages, chat_id, ..)
+            .filter(|m| m.deleted_at.is_none())
+            .cloned()
+            .collect();
+        latest.sort_by_key(|m| std::cmp::Reverse((m.date, m.message_id)));
+        latest.truncate(limit.max(0) as usize);
+        Ok(latest)
+    }
+
+    async fn search_messages(&self, chat_id: i64, query: &str, limit: i64) -> Result<Vec<Message>> {
+        let state = self.state.lock().unwrap();
+        let query = query.to_lowercase();
+        let mut found: Vec<Message> = Self::chat(&state.messages, chat_id, ..)
+            .filter(|m| m.deleted_at.is_none() && m.text.to_lowercase().contains(&query))
+            .cloned()
+            .collect();
+        found.sort_by_key(|m| std::cmp::Reverse((m.date, m.message_id)));
+        found.truncate(limit.max(0) as usize);
+        Ok(found)
+    }
+
+    async fn count_messages(&self, chat_id: i64, filter: &Filter) -> Result<i64> {
+        let state = self.state.lock().unwrap();
+        let matching =
+            Self::chat(&state.messages, chat_id, ..).filter(|m| state.matches(filter, m));
+        Ok(matching.count() as i64)
+    }
+
+    async fn message_position(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        message_id: i64,
+    ) -> Result<i64> {
+        let state = self.state.lock().unwrap();
+        let before = Self::chat(&state.messages, chat_id, ..=message_id)
+            .filter(|m| state.matches(filter, m));
+        Ok(before.count() as i64)
+    }
+
+    async fn select_message_at(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        position: i64,
+    ) -> Result<Message> {
+        let state = self.state.lock().unwrap();
+        let skip = (position - 1).max(0) as usize;
+        let message = Self::chat(&state.messages, chat_id, ..)
+            .filter(|m| state.matches(filter, m))
+            .nth(skip);
+        message.cloned().ok_or_else(|| Error::RowNotFound.into())
+    }
+
+    async fn change_message_text(&self, edited_message: &EditedMessage) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let key = (edited_message.chat_id, edited_message.message_id);
+        let state = &mut *state;
+        if let Some(message) = state.messages.get_mut(&key) {
+            if message.text != edited_message.text || message.entities != edited_message.entities {
+                state.revisions.push(MessageRevision {
+                    chat_id: message.chat_id,
+                    message_id: message.message_id,
+                    text: message.text.clone(),
+                    entities: message.entities.clone(),
+                    date: message.edit_date.unwrap_or(message.date),
+                });
+            }
+            message.text = edited_message.text.clone();
+            message.edit_date = edited_message.edit_date;
+            message.entities = edited_message.entities.clone();
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    async fn select_revisions(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Vec<MessageRevision>> {
+        let state = self.state.lock().unwrap();
+        let mut revisions: Vec<MessageRevision> = state
+            .revisions
+            .iter()
+            .filter(|r| r.chat_id == chat_id && r.message_id == message_id)
+            .cloned()
+            .collect();
+        revisions.sort_by_key(|r| r.date);
+        Ok(revisions)
+    }
+
+    async fn delete_message(&self, chat_id: i64, message_id: i64, deleted_at: i64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(message) = state.messages.get_mut(&(chat_id, message_id)) {
+            message.deleted_at = Some(deleted_at);
+        }
+        Ok(())
+    }
+
+    async fn restore_message(&self, chat_id: i64, message_id: i64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(message) = state.messages.get_mut(&(chat_id, message_id)) {
+            message.deleted_at = None;
+        }
+        Ok(())
+    }
+
+    async fn purge_message(&self, chat_id: i64, message_id: i64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let key = (chat_id, message_id);
+        if state
+            .messages
+            .get(&key)
+            .is_none_or(|m| m.deleted_at.is_none())
+        {
+            return Ok(());
+        }
+        state.messages.remove(&key);
+        for ((tag_chat_id, _), ids) in state.tags.iter_mut() {
+            if *tag_chat_id == chat_id {
+                ids.remove(&message_id);
+            }
+        }
+        state.favorites.remove(&(chat_id, message_id));
+        state
+            .revisions
+            .retain(|r| (r.chat_id, r.message_id) != (chat_id, message_id));
+        Ok(())
+    }
+
+    async fn upsert_chat(&self, chat: &Chat) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.chats.insert(chat.id, chat.clone());
+        Ok(())
+    }
+
+    async fn upsert_user(&self, user: &User) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.users.insert(user.id, user.clone());
+        Ok(())
+    }
+
+    async fn select_user(&self, user_id: i64) -> Result<Option<User>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.users.get(&user_id).cloned())
+    }
+
+    async fn add_tags(&self, chat_id: i64, message_id: i64, tags: &[String]) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        for name in tags {
+            state
+                .tags
+                .entry((chat_id, name.clone()))
+                .or_default()
+                .insert(message_id);
+        }
+        Ok(())
+    }
+
+    async fn list_tags(&self, chat_id: i64) -> Result<Vec<TagSummary>> {
+        let state = self.state.lock().unwrap();
+        let visible = |id: &i64| {
+            state
+                .messages
+                .get(&(chat_id, *id))
+                .is_some_and(|m| m.deleted_at.is_none())
+        };
+        let mut tags: Vec<TagSummary> = state
+            .tags
+            .iter()
+            .filter(|((tag_chat_id, _), _)| *tag_chat_id == chat_id)
+            .map(|((_, name), ids)| TagSummary {
+                name: name.clone(),
+                messages: ids.iter().filter(|id| visible(id)).count() as i64,
+            })
+            .filter(|tag| tag.messages > 0)
+            .collect();
+        tags.sort_by(|a, b| b.messages.cmp(&a.messages).then(a.name.cmp(&b.name)));
+        Ok(tags)
+    }
+
+    async fn select_message_tags(&self, chat_id: i64) -> Result<Vec<MessageTag>> {
+        let state = self.state.lock().unwrap();
+        let mut tags: Vec<MessageTag> = state
+            .tags
+            .iter()
+            .filter(|((tag_chat_id, _), _)| *tag_chat_id == chat_id)
+            .flat_map(|((_, name), ids)| {
+                ids.iter().map(|message_id| MessageTag {
+                    message_id: *message_id,
+                    name: name.clone(),
+                })
+            })
+            .collect();
+        tags.sort_by(|a, b| a.message_id.cmp(&b.message_id).then(a.name.cmp(&b.name)));
+        Ok(tags)
+    }
+
+    async fn toggle_favorite(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let key = (chat_id, message_id);
+        if state.favorites.remove(&key) {
+            return Ok(false);
+        }
+        state.favorites.insert(key);
+        Ok(true)
+    }
+
+    async fn is_favorite(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        let state = self.state.lock().unwrap();
+        Ok(state.favorites.contains(&(chat_id, message_id)))
+    }
+
+    async fn insert_link(&self, link_message: &LinkMessage) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let taken = |l: &LinkMessage| (l.chat_id, l.id) == (link_message.chat_id, link_message.id);
+        if !state.links.iter().any(taken) {
+            state.links.push(link_message.clone());
+        }
+        Ok(())
+    }
+
+    async fn delete_and_return_link(&self, chat_id: i64) -> Result<LinkMessage> {
+        let mut state = self.state.lock().unwrap();
+        let (deleted, kept): (Vec<_>, Vec<_>) =
+            state.links.drain(..).partition(|l| l.chat_id == chat_id);
+        state.links = kept;
+        deleted
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::RowNotFound.into())
+    }
+
+    async fn select_link(&self, chat_id: i64) -> Result<LinkMessage> {
+        let state = self.state.lock().unwrap();
+        let link = state.links.iter().find(|l| l.chat_id == chat_id);
+        link.cloned().ok_or_else(|| Error::RowNotFound.into())
+    }
+
+    async fn insert_update(&self, update: &Update) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.update.update_id = update.update_id + 1;
+        Ok(())
+    }
+
+    async fn get_last_update(&self) -> Result<Update> {
+        let state = self.state.lock().unwrap();
+        Ok(state.update.clone())
+    }
+
+    async fn set_update_offset(&self, update_id: i64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.update.update_id = update_id;
+        Ok(())
+    }
+
+    async fn log_update(&self, logged_update: &LoggedUpdate) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .update_log
+            .entry(logged_update.update_id)
+            .or_insert_with(|| logged_update.clone());
+        Ok(())
+    }
+
+    async fn select_logged_updates(&self, from_update_id: i64) -> Result<Vec<LoggedUpdate>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .update_log
+            .range(from_update_id..)
+            .map(|(_, u)| u.clone())
+            .collect())
+    }
+
+    async fn list_chats(&self) -> Result<Vec<ChatSummary>> {
+        let state = self.state.lock().unwrap();
+        let mut chats: BTreeMap<i64, i64> = BTreeMap::new();
+        for message in state.messages.values().filter(|m| m.deleted_at.is_none()) {
+            *chats.entry(message.chat_id).or_default() += 1;
+        }
+        Ok(chats
+            .into_iter()
+            .map(|(chat_id, messages)| ChatSummary {
+                chat_id,
+                messages,
+                title: state.chats.get(&chat_id).and_then(|c| c.title.clone()),
+            })
+            .collect())
+    }
+
+    async fn purge_chat(&self, chat_id: i64) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let before = state.messages.len();
+        state.messages.retain(|_, m| m.chat_id != chat_id);
+        state.links.retain(|l| l.chat_id != chat_id);
+        state
+            .tags
+            .retain(|(tag_chat_id, _), _| *tag_chat_id != chat_id);
+        state
+            .favorites
+            .retain(|(star_chat_id, _)| *star_chat_id != chat_id);
+        state.revisions.retain(|r| r.chat_id != chat_id);
+        state.api_keys.retain(|_, k| k.chat_id != chat_id);
+        state.feeds.remove(&chat_id);
+        state.reminders.retain(|_, r| r.chat_id != chat_id);
+        state.chats.remove(&chat_id);
+        state.update_log.retain(|_, u| u.chat_id != Some(chat_id));
+        Ok((before - state.messages.len()) as u64)
+    }
+
+    async fn log_purge(&self, record: &PurgeRecord) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.purges.push(record.clone());
+        Ok(())
+    }
+
+    async fn list_purges(&self) -> Result<Vec<PurgeRecord>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.purges.clone())
+    }
+
+    async fn insert_api_key(&self, api_key: &ApiKey) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.api_keys.insert(api_key.key.clone(), api_key.clone());
+        Ok(())
+    }
+
+    async fn select_api_key(&self, key: &str) -> Result<Option<ApiKey>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.api_keys.get(key).cloned())
+    }
+
+    async fn revoke_api_keys(&self, chat_id: i64) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let before = state.api_keys.len();
+        state.api_keys.retain(|_, k| k.chat_id != chat_id);
+        Ok((before - state.api_keys.len()) as u64)
+    }
+
+    async fn upsert_feed(&self, feed: &ChatFeed) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .feeds
+            .entry(feed.chat_id)
+            .and_modify(|f| f.title = feed.title.clone())
+            .or_insert_with(|| feed.clone());
+        Ok(())
+    }
+
+    async fn select_feed(&self, token: &str) -> Result<Option<ChatFeed>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.feeds.values().find(|f| f.token == token).cloned())
+    }
+
+    async fn select_chat_feed(&self, chat_id: i64) -> Result<Option<ChatFeed>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.feeds.get(&chat_id).cloned())
+    }
+
+    async fn delete_feed(&self, chat_id: i64) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        Ok(state.feeds.remove(&chat_id).is_some())
+    }
+
+    async fn insert_reminder(&self, reminder: &Reminder) -> Result<i64> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.reminders.keys().next_back().map_or(1, |id| id + 1);
+        let reminder = Reminder {
+            id,
+            ..reminder.clone()
+        };
+        state.reminders.insert(id, reminder);
+        Ok(id)
+    }
+
+    async fn select_due_reminders(&self, now: i64) -> Result<Vec<Reminder>> {
+        let state = self.state.lock().unwrap();
+        let mut due: Vec<Reminder> = state
+            .reminders
+            .values()
+            .filter(|r| r.sent_at.is_none() && r.due_at <= now)
+            .cloned()
+            .collect();
+        due.sort_by_key(|r| (r.due_at, r.id));
+        Ok(due)
+    }
+
+    async fn select_reminders(&self, chat_id: i64) -> Result<Vec<Reminder>> {
+        let state = self.state.lock().unwrap();
+        let mut pending: Vec<Reminder> = state
+            .reminders
+            .values()
+            .filter(|r| r.chat_id == chat_id && r.sent_at.is_none())
+            .cloned()
+            .collect();
+        pending.sort_by_key(|r| (r.due_at, r.id));
+        Ok(pending)
+    }
+
+    async fn mark_reminder_sent(&self, id: i64, sent_at: i64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(reminder) = state.reminders.get_mut(&id) {
+            reminder.sent_at = Some(sent_at);
+        }
+        Ok(())
+    }
+
+    async fn delete_reminder(&self, chat_id: i64, id: i64) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let pending = state
+            .reminders
+            .get(&id)
+            .is_some_and(|r| r.chat_id == chat_id && r.sent_at.is_none());
+        if pending {
+            state.reminders.remove(&id);
+        }
+        Ok(pending)
+    }
+
+    async fn select_user_settings(&self, user_id: i64) -> Result<Option<UserSettings>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.user_settings.get(&user_id).cloned())
+    }
+
+    async fn upsert_user_settings(&self, settings: &UserSettings) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .user_settings
+            .insert(settings.user_id, settings.clone());
+        Ok(())
+    }
+
+    async fn migrate(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn navigates_saved_messages() {
+        let storage = MemoryStorage::new();
+        crate::storage::check_navigation(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn filters_by_tag() {
+        let storage = MemoryStorage::new();
+        crate::storage::check_tags(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn filters_favorites() {
+        let storage = MemoryStorage::new();
+        crate::storage::check_favorites(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn keeps_deleted_messages_in_trash() {
+        let storage = MemoryStorage::new();
+        crate::storage::check_trash(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn inserts_messages_once() {
+        let storage = MemoryStorage::new();
+        crate::storage::check_insert_messages(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn answers_api_queries() {
+        let storage = MemoryStorage::new();
+        crate::storage::check_api_queries(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn keeps_feeds() {
+        let storage = MemoryStorage::new();
+        crate::storage::check_feeds(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn schedules_reminders() {
+        let storage = MemoryStorage::new();
+        crate::storage::check_reminders(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn keeps_user_settings() {
+        let storage = MemoryStorage::new();
+        crate::storage::check_user_settings(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn purges_only_one_chat() {
+        let storage = MemoryStorage::new();
+        for (chat_id, message_id) in [(10, 1), (10, 2), (20, 3)] {
+            let message = Message::new("text".to_string(), chat_id, message_id).await;
+            storage.insert_message(&message).await.unwrap();
+        }
+
+        for (update_id, chat_id) in [(1, Some(10)), (2, Some(20)), (3, None)] {
+            let logged = LoggedUpdate {
+                update_id,
+                body: "{}".to_string(),
+                chat_id,
+            };
+            storage.log_update(&logged).await.unwrap();
+        }
+
+        assert_eq!(storage.purge_chat(10).await.unwrap(), 2);
+        let chats = storage.list_chats().await.unwrap();
+        assert_eq!(chats.len(), 1);
+        assert_eq!((chats[0].chat_id, chats[0].messages), (20, 1));
+        let logged = storage.select_logged_updates(0).await.unwrap();
+        let ids: Vec<_> = logged.iter().map(|u| u.update_id).collect();
+        assert_eq!(ids, vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn returns_and_removes_link() {
+        let storage = MemoryStorage::new();
+        let link = LinkMessage::new(100, "link".to_string(), 10, 3).await;
+        storage.insert_link(&link).await.unwrap();
+
+        let deleted = storage.delete_and_return_link(10).await.unwrap();
+        assert_eq!(deleted.message_id, 3);
+        assert!(storage.delete_and_return_link(10).await.is_err());
+    }
+}
@@ -0,0 +1,657 @@
+use crate::models::{
+    ApiKey, Chat, ChatFeed, ChatSummary, EditedMessage, Filter, LinkMessage, LoggedUpdate, Message,
+    MessageRevision, MessageTag, PurgeRecord, Reminder, TagSummary, Update, User, UserSettings,
+};
+use crate::pg_service::PgService;
+use crate::redis_service::RedisService;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+mod cached;
+mod memory;
+mod notifying;
+mod postgres;
+mod sqlite;
+
+pub use cached::CachedStorage;
+pub use memory::MemoryStorage;
+pub use notifying::{NotifyingStorage, StorageEvent};
+pub use sqlite::SqliteStorage;
+
+/// Everything the bot persists: saved messages, the history browser links and the update offset.
+/// Lookups that find nothing fail with `sqlx::Error::RowNotFound` on every backend.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Saves a message unless it was saved before, returns whether it is new.
+    async fn insert_message(&self, message: &Message) -> Result<bool>;
+
+    /// Saves messages in one go, those already saved are kept as they are.
+    /// Returns how many were new.
+    async fn insert_messages(&self, messages: &[Message]) -> Result<u64>;
+
+    async fn select_message(&self, chat_id: i64, message_id: i64) -> Result<Message>;
+
+    /// History navigation works on the ids of one chat in ascending order,
+    /// skipping messages that don't pass `filter`. Deleted messages are only seen through `Filter::Trash`.
+    async fn select_first_message(&self, chat_id: i64, filter: &Filter) -> Result<Message>;
+
+    /// The message right after `id` in the chat.
+    async fn select_next_message(&self, chat_id: i64, filter: &Filter, id: i64) -> Result<Message>;
+
+    /// The message right before `id` in the chat.
+    async fn select_previous_message(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        id: i64,
+    ) -> Result<Message>;
+
+    async fn select_last_message(&self, chat_id: i64, filter: &Filter) -> Result<Message>;
+
+    /// First message dated on or after `date` (unix seconds).
+    async fn select_first_message_since(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        date: i64,
+    ) -> Result<Message>;
+
+    /// Messages of the chat that are not in the trash.
+    async fn select_all_by_chat_id(&self, chat_id: i64) -> Result<Vec<Message>>;
+
+    /// Up to `limit` messages with ids above `after` in ascending order, without the trash.
+    async fn select_messages_after(
+        &self,
+        chat_id: i64,
+        after: i64,
+        limit: i64,
+    ) -> Result<Vec<Message>>;
+
+    /// The newest `limit` messages by date, without the trash.
+    async fn select_latest_messages(&self, chat_id: i64, limit: i64) -> Result<Vec<Message>>;
+
+    /// Messages containing `query`, case-insensitive, newest first, without the trash.
+    async fn search_messages(&self, chat_id: i64, query: &str, limit: i64) -> Result<Vec<Message>>;
+
+    async fn count_messages(&self, chat_id: i64, filter: &Filter) -> Result<i64>;
+
+    /// 1-based position of the message in the chat history.
+    async fn message_position(&self, chat_id: i64, filter: &Filter, message_id: i64)
+        -> Result<i64>;
+
+    /// Message at the 1-based `position` of the chat history.
+    async fn select_message_at(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        position: i64,
+    ) -> Result<Message>;
+
+    /// Keeps the previous text as a revision when the edit changes it.
+    /// Returns whether the message was saved at all.
+    async fn change_message_text(&self, edited_message: &EditedMessage) -> Result<bool>;
+
+    /// Earlier texts of the message, oldest first.
+    async fn select_revisions(&self, chat_id: i64, message_id: i64)
+        -> Result<Vec<MessageRevision>>;
+
+    /// Moves the message to the trash, `deleted_at` in unix seconds.
+    async fn delete_message(&self, chat_id: i64, message_id: i64, deleted_at: i64) -> Result<()>;
+
+    /// Takes the message back from the trash.
+    async fn restore_message(&self, chat_id: i64, message_id: i64) -> Result<()>;
+
+    /// Deletes the message for good together with its tags, star and revisions,
+    /// messages outside the trash are left alone.
+    async fn purge_message(&self, chat_id: i64, message_id: i64) -> Result<()>;
+
+    async fn upsert_chat(&self, chat: &Chat) -> Result<()>;
+
+    async fn upsert_user(&self, user: &User) -> Result<()>;
+
+    async fn select_user(&self, user_id: i64) -> Result<Option<User>>;
+
+    /// Tags the message, tags are created on first use. Names come without `#`.
+    async fn add_tags(&self, chat_id: i64, message_id: i64, tags: &[String]) -> Result<()>;
+
+    /// Tags of the chat, the most used first.
+    async fn list_tags(&self, chat_id: i64) -> Result<Vec<TagSummary>>;
+
+    /// Every tag of every message of the chat, ordered by message id and tag name.
+    async fn select_message_tags(&self, chat_id: i64) -> Result<Vec<MessageTag>>;
+
+    /// Stars the message or takes the star back, returns whether it is starred now.
+    async fn toggle_favorite(&self, chat_id: i64, message_id: i64) -> Result<bool>;
+
+    async fn is_favorite(&self, chat_id: i64, message_id: i64) -> Result<bool>;
+
+    async fn insert_link(&self, link_message: &LinkMessage) -> Result<()>;
+
+    async fn delete_and_return_link(&self, chat_id: i64) -> Result<LinkMessage>;
+
+    /// The open history message of the chat, left in place.
+    async fn select_link(&self, chat_id: i64) -> Result<LinkMessage>;
+
+    async fn insert_update(&self, update: &Update) -> Result<()>;
+
+    async fn get_last_update(&self) -> Result<Update>;
+
+    /// Overwrites the offset passed to the next `getUpdates`.
+    async fn set_update_offset(&self, update_id: i64) -> Result<()>;
+
+    async fn log_update(&self, logged_update: &LoggedUpdate) -> Result<()>;
+
+    async fn select_logged_updates(&self, from_update_id: i64) -> Result<Vec<LoggedUpdate>>;
+
+    async fn list_chats(&self) -> Result<Vec<ChatSummary>>;
+
+    /// Deletes everything kept about the chat: saved messages, their tags, stars and revisions,
+    /// browser links, API keys, the feed, reminders, the chat itself and its logged updates.
+    /// Returns the number of deleted messages.
+    async fn purge_chat(&self, chat_id: i64) -> Result<u64>;
+
+    async fn log_purge(&self, record: &PurgeRecord) -> Result<()>;
+
+    /// Purge audit records, oldest first.
+    async fn list_purges(&self) -> Result<Vec<PurgeRecord>>;
+
+    async fn insert_api_key(&self, api_key: &ApiKey) -> Result<()>;
+
+    async fn select_api_key(&self, key: &str) -> Result<Option<ApiKey>>;
+
+    /// Revokes every key of the chat, returns how many there were.
+    async fn revoke_api_keys(&self, chat_id: i64) -> Result<u64>;
+
+    /// Turns the feed of the chat on. A chat that has one keeps its token, only the title changes.
+    async fn upsert_feed(&self, feed: &ChatFeed) -> Result<()>;
+
+    async fn select_feed(&self, token: &str) -> Result<Option<ChatFeed>>;
+
+    async fn select_chat_feed(&self, chat_id: i64) -> Result<Option<ChatFeed>>;
+
+    /// Turns the feed off, `false` when it wasn't on.
+    async fn delete_feed(&self, chat_id: i64) -> Result<bool>;
+
+    /// Schedules the reminder and returns its id, `reminder.id` is ignored.
+    async fn insert_reminder(&self, reminder: &Reminder) -> Result<i64>;
+
+    /// Unsent reminders due at `now` or earlier, the longest overdue first.
+    async fn select_due_reminders(&self, now: i64) -> Result<Vec<Reminder>>;
+
+    /// Unsent reminders of the chat, the soonest first.
+    async fn select_reminders(&self, chat_id: i64) -> Result<Vec<Reminder>>;
+
+    async fn mark_reminder_sent(&self, id: i64, sent_at: i64) -> Result<()>;
+
+    /// Cancels an unsent reminder of the chat, `false` when there is none with this id.
+    async fn delete_reminder(&self, chat_id: i64, id: i64) -> Result<bool>;
+
+    /// `None` for users who never opened `/settings`.
+    async fn select_user_settings(&self, user_id: i64) -> Result<Option<UserSettings>>;
+
+    async fn upsert_user_settings(&self, settings: &UserSettings) -> Result<()>;
+
+    /// Creates missing tables, safe to run on every start.
+    async fn migrate(&self) -> Result<()>;
+}
+
+pub fn is_not_found(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<sqlx::Error>(),
+        Some(sqlx::Error::RowNotFound)
+    )
+}
+
+/// Picks the backend from `STORAGE` (`postgres` by default, `sqlite` or `memory`),
+/// brings its tables up to date and puts the Redis history cache in front of it.
+pub async fn from_env() -> Arc<dyn Storage> {
+    let storage: Arc<dyn Storage> = match dotenv::var("STORAGE").unwrap_or_default().as_str() {
+        "sqlite" => Arc::new(
+            SqliteStorage::new(&dotenv::var("SQLITE_URL").unwrap())
+                .await
+                .expect("Cant open the sqlite database"),
+        ),
+        "memory" => Arc::new(MemoryStorage::new()),
+        _ => Arc::new(PgService::new().await),
+    };
+    storage.migrate().await.expect("Cant migrate the database");
+    Arc::new(CachedStorage::new(storage, RedisService::new().await))
+}
+
+/// Navigation scenario every backend has to pass: chats 10 and 20 share message id 5,
+/// ids have gaps and are inserted out of order.
+#[cfg(test)]
+pub(crate) async fn check_navigation(storage: &dyn Storage) {
+    for (chat_id, message_id) in [
+        (10, 9),
+        (20, 12),
+        (10, 3),
+        (20, 1),
+        (10, 5),
+        (20, 5),
+        (20, 7),
+    ] {
+        let text = format!("{}:{}", chat_id, message_id);
+        let message = Message::new(text, chat_id, message_id).await;
+        storage.insert_message(&message).await.unwrap();
+    }
+    let id = |m: Result<Message>| m.map(|m| (m.chat_id, m.message_id)).ok();
+    let all = Filter::All;
+
+    assert_eq!(
+        id(storage.select_first_message(10, &all).await),
+        Some((10, 3))
+    );
+    assert_eq!(
+        id(storage.select_first_message(20, &all).await),
+        Some((20, 1))
+    );
+    assert_eq!(
+        id(storage.select_last_message(10, &all).await),
+        Some((10, 9))
+    );
+    assert_eq!(
+        id(storage.select_last_message(20, &all).await),
+        Some((20, 12))
+    );
+    assert_eq!(id(storage.select_first_message(30, &all).await), None);
+
+    assert_eq!(
+        id(storage.select_next_message(10, &all, 3).await),
+        Some((10, 5))
+    );
+    assert_eq!(
+        id(storage.select_next_message(10, &all, 5).await),
+        Some((10, 9))
+    );
+    assert_eq!(
+        id(storage.select_next_message(10, &all, 4).await),
+        Some((10, 5))
+    );
+    assert_eq!(id(storage.select_next_message(10, &all, 9).await), None);
+    assert_eq!(
+        id(storage.select_previous_message(10, &all, 9).await),
+        Some((10, 5))
+    );
+    assert_eq!(
+        id(storage.select_previous_message(10, &all, 5).await),
+        Some((10, 3))
+    );
+    assert_eq!(id(storage.select_previous_message(10, &all, 3).await), None);
+    assert_eq!(
+        id(storage.select_previous_message(20, &all, 5).await),
+        Some((20, 1))
+    );
+
+    let shared = storage.select_next_message(20, &all, 1).await.unwrap();
+    assert_eq!(shared.text, "20:5");
+    assert_eq!(storage.select_message(10, 5).await.unwrap().text, "10:5");
+    assert_eq!(id(storage.select_message(10, 7).await), None);
+    assert_eq!(storage.count_messages(10, &all).await.unwrap(), 3);
+    assert_eq!(storage.message_position(20, &all, 7).await.unwrap(), 3);
+    assert_eq!(
+        id(storage.select_message_at(10, &all, 2).await),
+        Some((10, 5))
+    );
+    assert_eq!(id(storage.select_message_at(10, &all, 4).await), None);
+
+    // both chats may have a browser under the same bot message id
+    for chat_id in [10, 20] {
+        let link = LinkMessage::new(100, "browser".to_string(), chat_id, 5).await;
+        storage.insert_link(&link).await.unwrap();
+    }
+    assert_eq!(storage.select_link(20).await.unwrap().chat_id, 20);
+    assert_eq!(storage.delete_and_return_link(10).await.unwrap().id, 100);
+    assert_eq!(storage.select_link(20).await.unwrap().message_id, 5);
+}
+
+/// Tag scenario: tags are per chat, adding a tag twice is a no-op and a tag filter
+/// walks only through the tagged messages.
+#[cfg(test)]
+pub(crate) async fn check_tags(storage: &dyn Storage) {
+    for (chat_id, message_id) in [(10, 1), (10, 2), (10, 3), (10, 4), (20, 2)] {
+        let text = format!("{}:{}", chat_id, message_id);
+        let message = Message::new(text, chat_id, message_id).await;
+        storage.insert_message(&message).await.unwrap();
+    }
+    let work = vec!["work".to_string()];
+    storage.add_tags(10, 2, &work).await.unwrap();
+    storage.add_tags(10, 4, &work).await.unwrap();
+    storage.add_tags(10, 4, &work).await.unwrap();
+    storage
+        .add_tags(10, 3, &["later".to_string()])
+        .await
+        .unwrap();
+    storage.add_tags(20, 2, &work).await.unwrap();
+    let id = |m: Result<Message>| m.map(|m| m.message_id).ok();
+    let filter = Filter::Tag("work".to_string());
+
+    let tags: Vec<(String, i64)> = storage
+        .list_tags(10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|t| (t.name, t.messages))
+        .collect();
+    assert_eq!(
+        tags,
+        vec![("work".to_string(), 2), ("later".to_string(), 1)]
+    );
+    assert_eq!(id(storage.select_first_message(10, &filter).await), Some(2));
+    assert_eq!(
+        id(storage.select_next_message(10, &filter, 2).await),
+        Some(4)
+    );
+    assert_eq!(id(storage.select_next_message(10, &filter, 4).await), None);
+    assert_eq!(
+        id(storage.select_previous_message(10, &filter, 4).await),
+        Some(2)
+    );
+    assert_eq!(id(storage.select_last_message(10, &filter).await), Some(4));
+    assert_eq!(storage.count_messages(10, &filter).await.unwrap(), 2);
+    assert_eq!(storage.message_position(10, &filter, 4).await.unwrap(), 2);
+    assert_eq!(id(storage.select_message_at(10, &filter, 2).await), Some(4));
+    let none = Filter::Tag("none".to_string());
+    assert_eq!(id(storage.select_first_message(10, &none).await), None);
+}
+
+/// Favorites scenario: stars toggle and the favorites filter skips unstarred messages.
+#[cfg(test)]
+pub(crate) async fn check_favorites(storage: &dyn Storage) {
+    for message_id in 1..=4 {
+        let message = Message::new(message_id.to_string(), 10, message_id).await;
+        storage.insert_message(&message).await.unwrap();
+    }
+    assert!(storage.toggle_favorite(10, 2).await.unwrap());
+    assert!(storage.toggle_favorite(10, 3).await.unwrap());
+    assert!(storage.toggle_favorite(10, 4).await.unwrap());
+    assert!(!storage.toggle_favorite(10, 3).await.unwrap());
+    assert!(storage.is_favorite(10, 2).await.unwrap());
+    assert!(!storage.is_favorite(10, 3).await.unwrap());
+    assert!(!storage.is_favorite(20, 2).await.unwrap());
+    let id = |m: Result<Message>| m.map(|m| m.message_id).ok();
+    let filter = Filter::Favorites;
+
+    assert_eq!(id(storage.select_first_message(10, &filter).await), Some(2));
+    assert_eq!(
+        id(storage.select_next_message(10, &filter, 2).await),
+        Some(4)
+    );
+    assert_eq!(
+        id(storage.select_previous_message(10, &filter, 4).await),
+        Some(2)
+    );
+    assert_eq!(storage.count_messages(10, &filter).await.unwrap(), 2);
+    assert_eq!(id(storage.select_first_message(20, &filter).await), None);
+}
+
+/// Trash scenario: deleted messages leave every other filter, restore brings them back
+/// and purge forgets them together with tags and stars, but only once they are in the trash.
+#[cfg(test)]
+pub(crate) async fn check_trash(storage: &dyn Storage) {
+    for message_id in 1..=4 {
+        let message = Message::new(message_id.to_string(), 10, message_id).await;
+        storage.insert_message(&message).await.unwrap();
+    }
+    storage
+        .add_tags(10, 2, &["work".to_string()])
+        .await
+        .unwrap();
+    for message_id in [2, 4] {
+        storage
+            .add_tags(10, message_id, &["later".to_string()])
+            .await
+            .unwrap();
+    }
+    storage.toggle_favorite(10, 2).await.unwrap();
+    storage.delete_message(10, 2, 100).await.unwrap();
+    storage.delete_message(10, 3, 100).await.unwrap();
+    let id = |m: Result<Message>| m.map(|m| m.message_id).ok();
+    let (all, trash) = (Filter::All, Filter::Trash);
+
+    assert_eq!(id(storage.select_next_message(10, &all, 1).await), Some(4));
+    assert_eq!(storage.count_messages(10, &all).await.unwrap(), 2);
+    assert_eq!(storage.select_all_by_chat_id(10).await.unwrap().len(), 2);
+    let tags = |tags: Vec<TagSummary>| {
+        tags.into_iter()
+            .map(|t| (t.name, t.messages))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        tags(storage.list_tags(10).await.unwrap()),
+        [("later".to_string(), 1)]
+    );
+    let chats = storage.list_chats().await.unwrap();
+    assert_eq!((chats[0].chat_id, chats[0].messages), (10, 2));
+    let work = Filter::Tag("work".to_string());
+    assert_eq!(id(storage.select_first_message(10, &work).await), None);
+    assert_eq!(
+        id(storage.select_first_message(10, &Filter::Favorites).await),
+        None
+    );
+    assert_eq!(id(storage.select_first_message(10, &trash).await), Some(2));
+    assert_eq!(storage.count_messages(10, &trash).await.unwrap(), 2);
+    assert_eq!(
+        storage.select_message(10, 2).await.unwrap().deleted_at,
+        Some(100)
+    );
+
+    storage.restore_message(10, 3).await.unwrap();
+    assert_eq!(id(storage.select_next_message(10, &all, 1).await), Some(3));
+    storage.purge_message(10, 2).await.unwrap();
+    assert_eq!(id(storage.select_message(10, 2).await), None);
+    assert_eq!(storage.count_messages(10, &trash).await.unwrap(), 0);
+    assert!(!storage.is_favorite(10, 2).await.unwrap());
+    storage.purge_message(10, 4).await.unwrap();
+    assert_eq!(id(storage.select_message(10, 4).await), Some(4));
+    assert_eq!(
+        tags(storage.list_tags(10).await.unwrap()),
+        [("later".to_string(), 1)]
+    );
+}
+
+/// Scenario of the REST API queries: pages skip the trash, search ignores case,
+/// edits keep the previous texts and keys belong to one chat.
+#[cfg(test)]
+pub(crate) async fn check_api_queries(storage: &dyn Storage) {
+    for (message_id, text) in [
+        (1, "Buy milk"),
+        (2, "call Ann"),
+        (3, "MILK again"),
+        (4, "gone"),
+    ] {
+        let mut message = Message::new(text.to_string(), 10, message_id).await;
+        message.date = 100 + message_id;
+        assert!(storage.insert_message(&message).await.unwrap());
+    }
+    let milk = Message::new("milk".to_string(), 20, 1).await;
+    assert!(storage.insert_message(&milk).await.unwrap());
+    assert!(!storage.insert_message(&milk).await.unwrap());
+    storage.delete_message(10, 4, 200).await.unwrap();
+    let ids = |messages: Vec<Message>| messages.iter().map(|m| m.message_id).collect::<Vec<_>>();
+
+    assert_eq!(
+        ids(storage.select_messages_after(10, 0, 2).await.unwrap()),
+        [1, 2]
+    );
+    assert_eq!(
+        ids(storage.select_messages_after(10, 2, 2).await.unwrap()),
+        [3]
+    );
+    assert_eq!(
+        ids(storage.search_messages(10, "milk", 10).await.unwrap()),
+        [3, 1]
+    );
+    assert_eq!(
+        ids(storage.search_messages(10, "gone", 10).await.unwrap()),
+        Vec::<i64>::new()
+    );
+
+    for (text, edit_date) in [("call Bob", 150), ("call Bob", 160), ("call Eve", 170)] {
+        let edited = EditedMessage::new(2, text.to_string(), 10, Some(edit_date)).await;
+        storage.change_message_text(&edited).await.unwrap();
+    }
+    let revisions = storage.select_revisions(10, 2).await.unwrap();
+    let texts: Vec<(&str, i64)> = revisions
+        .iter()
+        .map(|r| (r.text.as_str(), r.date))
+        .collect();
+    assert_eq!(texts, [("call Ann", 102), ("call Bob", 160)]);
+    let unsaved = EditedMessage::new(9, "never saved".to_string(), 10, Some(180)).await;
+    assert!(!storage.change_message_text(&unsaved).await.unwrap());
+
+    let key = ApiKey {
+        key: "secret".to_string(),
+        chat_id: 10,
+        created_at: 300,
+    };
+    storage.insert_api_key(&key).await.unwrap();
+    assert_eq!(
+        storage
+            .select_api_key("secret")
+            .await
+            .unwrap()
+            .unwrap()
+            .chat_id,
+        10
+    );
+    assert!(storage.select_api_key("other").await.unwrap().is_none());
+    assert_eq!(storage.revoke_api_keys(20).await.unwrap(), 0);
+    storage.delete_message(10, 2, 400).await.unwrap();
+    storage.purge_message(10, 2).await.unwrap();
+    assert!(storage.select_revisions(10, 2).await.unwrap().is_empty());
+    storage.purge_chat(10).await.unwrap();
+    assert!(storage.select_api_key("secret").await.unwrap().is_none());
+}
+
+/// Feed scenario: latest messages go by date, a chat keeps its token when the feed
+/// is turned on again and purging the chat turns it off.
+#[cfg(test)]
+pub(crate) async fn check_feeds(storage: &dyn Storage) {
+    for (message_id, date) in [(1, 300), (2, 100), (3, 200), (4, 400)] {
+        let mut message = Message::new(message_id.to_string(), 10, message_id).await;
+        message.date = date;
+        storage.insert_message(&message).await.unwrap();
+    }
+    storage.delete_message(10, 4, 500).await.unwrap();
+    let latest = storage.select_latest_messages(10, 2).await.unwrap();
+    let ids: Vec<i64> = latest.iter().map(|m| m.message_id).collect();
+    assert_eq!(ids, [1, 3]);
+
+    let feed = |token: &str, title: &str| ChatFeed {
+        chat_id: 10,
+        token: token.to_string(),
+        title: Some(title.to_string()),
+        created_at: 100,
+    };
+    storage.upsert_feed(&feed("first", "Notes")).await.unwrap();
+    storage
+        .upsert_feed(&feed("second", "Renamed"))
+        .await
+        .unwrap();
+    let kept = storage.select_chat_feed(10).await.unwrap().unwrap();
+    assert_eq!(
+        (kept.token.as_str(), kept.title.as_deref()),
+        ("first", Some("Renamed"))
+    );
+    assert_eq!(
+        storage.select_feed("first").await.unwrap().unwrap().chat_id,
+        10
+    );
+    assert!(storage.select_feed("second").await.unwrap().is_none());
+    assert!(storage.delete_feed(10).await.unwrap());
+    assert!(!storage.delete_feed(10).await.unwrap());
+
+    storage.upsert_feed(&feed("third", "Notes")).await.unwrap();
+    storage.purge_chat(10).await.unwrap();
+    assert!(storage.select_feed("third").await.unwrap().is_none());
+}
+
+/// Reminder scenario: due reminders come oldest first until they are marked sent,
+/// cancelling only works within the chat and purging the chat drops its reminders.
+#[cfg(test)]
+pub(crate) async fn check_reminders(storage: &dyn Storage) {
+    let reminder = |chat_id: i64, due_at: i64| Reminder {
+        id: 0,
+        chat_id,
+        message_id: Some(3),
+        text: format!("due at {}", due_at),
+        due_at,
+        created_at: 100,
+        sent_at: None,
+        silent: chat_id == 20,
+        language: "ru".to_string(),
+        timezone: "Europe/Moscow".to_string(),
+    };
+    let late = storage.insert_reminder(&reminder(10, 300)).await.unwrap();
+    let early = storage.insert_reminder(&reminder(20, 200)).await.unwrap();
+    let future = storage.insert_reminder(&reminder(10, 900)).await.unwrap();
+    assert!(late != early && early != future && late != future);
+
+    let due = storage.select_due_reminders(300).await.unwrap();
+    let ids: Vec<i64> = due.iter().map(|r| r.id).collect();
+    assert_eq!(ids, [early, late]);
+    assert_eq!(due[1].message_id, Some(3));
+    assert_eq!(due[1].text, "due at 300");
+    assert!(due[0].silent && !due[1].silent);
+    assert_eq!(due[0].language, "ru");
+    assert_eq!(due[0].timezone, "Europe/Moscow");
+    storage.mark_reminder_sent(early, 310).await.unwrap();
+    let due = storage.select_due_reminders(1000).await.unwrap();
+    let ids: Vec<i64> = due.iter().map(|r| r.id).collect();
+    assert_eq!(ids, [late, future]);
+
+    let pending = storage.select_reminders(10).await.unwrap();
+    let ids: Vec<i64> = pending.iter().map(|r| r.id).collect();
+    assert_eq!(ids, [late, future]);
+    assert!(storage.select_reminders(20).await.unwrap().is_empty());
+
+    assert!(!storage.delete_reminder(20, late).await.unwrap());
+    assert!(storage.delete_reminder(10, late).await.unwrap());
+    assert!(!storage.delete_reminder(10, late).await.unwrap());
+    storage.purge_chat(10).await.unwrap();
+    assert!(storage.select_due_reminders(1000).await.unwrap().is_empty());
+}
+
+/// Settings scenario: users start without settings, a second upsert overwrites the first.
+#[cfg(test)]
+pub(crate) async fn check_user_settings(storage: &dyn Storage) {
+    assert!(storage.select_user_settings(7).await.unwrap().is_none());
+    let mut settings = UserSettings::new(7, Some("ru"));
+    storage.upsert_user_settings(&settings).await.unwrap();
+    settings.timezone = "Europe/Moscow".to_string();
+    settings.export_format = "csv".to_string();
+    settings.quiet_reminders = true;
+    storage.upsert_user_settings(&settings).await.unwrap();
+    storage
+        .upsert_user_settings(&UserSettings::new(8, None))
+        .await
+        .unwrap();
+
+    let stored = storage.select_user_settings(7).await.unwrap();
+    assert_eq!(stored, Some(settings));
+    let other = storage.select_user_settings(8).await.unwrap().unwrap();
+    assert_eq!(other.language, "en");
+    assert!(!other.quiet_reminders);
+}
+
+/// Bulk insert scenario: a second run of the same import adds nothing
+/// and leaves messages saved before untouched.
+#[cfg(test)]
+pub(crate) async fn check_insert_messages(storage: &dyn Storage) {
+    let saved = Message::new("saved".to_string(), 10, 2).await;
+    storage.insert_message(&saved).await.unwrap();
+    let mut messages = Vec::new();
+    for message_id in 1..=3 {
+        let mut message = Message::new("imported".to_string(), 10, message_id).await;
+        message.date = 100 + message_id;
+        messages.push(message);
+    }
+
+    assert_eq!(storage.insert_messages(&messages).await.unwrap(), 2);
+    assert_eq!(storage.insert_messages(&messages).await.unwrap(), 0);
+    assert_eq!(storage.count_messages(10, &Filter::All).await.unwrap(), 3);
+    assert_eq!(storage.select_message(10, 2).await.unwrap().text, "saved");
+    assert_eq!(storage.select_message(10, 3).await.unwrap().date, 103);
+}
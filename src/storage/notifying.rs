@@ -0,0 +1,374 @@
+use crate::models::{
+    ApiKey, Chat, ChatFeed, ChatSummary, EditedMessage, Filter, LinkMessage, LoggedUpdate, Message,
+    MessageRevision, MessageTag, PurgeRecord, Reminder, TagSummary, Update, User, UserSettings,
+};
+use crate::storage::Storage;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Messages buffered for a slow subscriber before it starts missing events.
+const CAPACITY: usize = 256;
+
+/// What `NotifyingStorage` announces after a successful write.
+#[derive(Debug, Clone)]
+pub enum StorageEvent {
+    /// `Message::insert` of a message that wasn't saved before.
+    Saved(Message),
+    /// `EditedMessage::change_message_text` of a saved message that isn't in the trash.
+    Edited(EditedMessage),
+}
+
+/// Broadcasts saved and edited messages to subscribers such as the `/stream` endpoint,
+/// everything else goes straight to the inner storage. Nobody listening is not an error.
+pub struct NotifyingStorage {
+    inner: Arc<dyn Storage>,
+    events: broadcast::Sender<StorageEvent>,
+}
+
+impl NotifyingStorage {
+    pub fn new(inner: Arc<dyn Storage>) -> Self {
+        let (events, _) = broadcast::channel(CAPACITY);
+        Self { inner, events }
+    }
+
+    /// Sender to subscribe to, it stays usable after the storage is moved into an `Arc<dyn Storage>`.
+    pub fn events(&self) -> broadcast::Sender<StorageEvent> {
+        self.events.clone()
+    }
+}
+
+#[async_trait]
+impl Storage for NotifyingStorage {
+    async fn insert_message(&self, message: &Message) -> Result<bool> {
+        let inserted = self.inner.insert_message(message).await?;
+        if inserted {
+            let _ = self.events.send(StorageEvent::Saved(message.clone()));
+        }
+        Ok(inserted)
+    }
+
+    async fn insert_messages(&self, messages: &[Message]) -> Result<u64> {
+        self.inner.insert_messages(messages).await
+    }
+
+    async fn select_message(&self, chat_id: i64, message_id: i64) -> Result<Message> {
+        self.inner.select_message(chat_id, message_id).await
+    }
+
+    async fn select_first_message(&self, chat_id: i64, filter: &Filter) -> Result<Message> {
+        self.inner.select_first_message(chat_id, filter).await
+    }
+
+    async fn select_next_message(&self, chat_id: i64, filter: &Filter, id: i64) -> Result<Message> {
+        self.inner.select_next_message(chat_id, filter, id).await
+    }
+
+    async fn select_previous_message(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        id: i64,
+    ) -> Result<Message> {
+        self.inner
+            .select_previous_message(chat_id, filter, id)
+            .await
+    }
+
+    async fn select_last_message(&self, chat_id: i64, filter: &Filter) -> Result<Message> {
+        self.inner.select_last_message(chat_id, filter).await
+    }
+
+    async fn select_first_message_since(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        date: i64,
+    ) -> Result<Message> {
+        self.inner
+            .select_first_message_since(chat_id, filter, date)
+            .await
+    }
+
+    async fn select_all_by_chat_id(&self, chat_id: i64) -> Result<Vec<Message>> {
+        self.inner.select_all_by_chat_id(chat_id).await
+    }
+
+    async fn select_messages_after(
+        &self,
+        chat_id: i64,
+        after: i64,
+        limit: i64,
+    ) -> Result<Vec<Message>> {
+        self.inner
+            .select_messages_after(chat_id, after, limit)
+            .await
+    }
+
+    async fn select_latest_messages(&self, chat_id: i64, limit: i64) -> Result<Vec<Message>> {
+        self.inner.select_latest_messages(chat_id, limit).await
+    }
+
+    async fn search_messages(&self, chat_id: i64, query: &str, limit: i64) -> Result<Vec<Message>> {
+        self.inner.search_messages(chat_id, query, limit).await
+    }
+
+    async fn count_messages(&self, chat_id: i64, filter: &Filter) -> Result<i64> {
+        self.inner.count_messages(chat_id, filter).await
+    }
+
+    async fn message_position(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        message_id: i64,
+    ) -> Result<i64> {
+        self.inner
+            .message_position(chat_id, filter, message_id)
+            .await
+    }
+
+    async fn select_message_at(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        position: i64,
+    ) -> Result<Message> {
+        self.inner
+            .select_message_at(chat_id, filter, position)
+            .await
+    }
+
+    async fn change_message_text(&self, edited_message: &EditedMessage) -> Result<bool> {
+        let updated = self.inner.change_message_text(edited_message).await?;
+        if !updated {
+            return Ok(false);
+        }
+        // the API doesn't show trashed messages, their edits are kept quietly
+        let message = self
+            .inner
+            .select_message(edited_message.chat_id, edited_message.message_id)
+            .await?;
+        if message.deleted_at.is_none() {
+            let _ = self
+                .events
+                .send(StorageEvent::Edited(edited_message.clone()));
+        }
+        Ok(true)
+    }
+
+    async fn select_revisions(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Vec<MessageRevision>> {
+        self.inner.select_revisions(chat_id, message_id).await
+    }
+
+    async fn delete_message(&self, chat_id: i64, message_id: i64, deleted_at: i64) -> Result<()> {
+        self.inner
+            .delete_message(chat_id, message_id, deleted_at)
+            .await
+    }
+
+    async fn restore_message(&self, chat_id: i64, message_id: i64) -> Result<()> {
+        self.inner.restore_message(chat_id, message_id).await
+    }
+
+    async fn purge_message(&self, chat_id: i64, message_id: i64) -> Result<()> {
+        self.inner.purge_message(chat_id, message_id).await
+    }
+
+    async fn upsert_chat(&self, chat: &Chat) -> Result<()> {
+        self.inner.upsert_chat(chat).await
+    }
+
+    async fn upsert_user(&self, user: &User) -> Result<()> {
+        self.inner.upsert_user(user).await
+    }
+
+    async fn select_user(&self, user_id: i64) -> Result<Option<User>> {
+        self.inner.select_user(user_id).await
+    }
+
+    async fn add_tags(&self, chat_id: i64, message_id: i64, tags: &[String]) -> Result<()> {
+        self.inner.add_tags(chat_id, message_id, tags).await
+    }
+
+    async fn list_tags(&self, chat_id: i64) -> Result<Vec<TagSummary>> {
+        self.inner.list_tags(chat_id).await
+    }
+
+    async fn select_message_tags(&self, chat_id: i64) -> Result<Vec<MessageTag>> {
+        self.inner.select_message_tags(chat_id).await
+    }
+
+    async fn toggle_favorite(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        self.inner.toggle_favorite(chat_id, message_id).await
+    }
+
+    async fn is_favorite(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        self.inner.is_favorite(chat_id, message_id).await
+    }
+
+    async fn insert_link(&self, link_message: &LinkMessage) -> Result<()> {
+        self.inner.insert_link(link_message).await
+    }
+
+    async fn delete_and_return_link(&self, chat_id: i64) -> Result<LinkMessage> {
+        self.inner.delete_and_return_link(chat_id).await
+    }
+
+    async fn select_link(&self, chat_id: i64) -> Result<LinkMessage> {
+        self.inner.select_link(chat_id).await
+    }
+
+    async fn insert_update(&self, update: &Update) -> Result<()> {
+        self.inner.insert_update(update).await
+    }
+
+    async fn get_last_update(&self) -> Result<Update> {
+        self.inner.get_last_update().await
+    }
+
+    async fn set_update_offset(&self, update_id: i64) -> Result<()> {
+        self.inner.set_update_offset(update_id).await
+    }
+
+    async fn log_update(&self, logged_update: &LoggedUpdate) -> Result<()> {
+        self.inner.log_update(logged_update).await
+    }
+
+    async fn select_logged_updates(&self, from_update_id: i64) -> Result<Vec<LoggedUpdate>> {
+        self.inner.select_logged_updates(from_update_id).await
+    }
+
+    async fn list_chats(&self) -> Result<Vec<ChatSummary>> {
+        self.inner.list_chats().await
+    }
+
+    async fn purge_chat(&self, chat_id: i64) -> Result<u64> {
+        self.inner.purge_chat(chat_id).await
+    }
+
+    async fn log_purge(&self, record: &PurgeRecord) -> Result<()> {
+        self.inner.log_purge(record).await
+    }
+
+    async fn list_purges(&self) -> Result<Vec<PurgeRecord>> {
+        self.inner.list_purges().await
+    }
+
+    async fn insert_api_key(&self, api_key: &ApiKey) -> Result<()> {
+        self.inner.insert_api_key(api_key).await
+    }
+
+    async fn select_api_key(&self, key: &str) -> Result<Option<ApiKey>> {
+        self.inner.select_api_key(key).await
+    }
+
+    async fn revoke_api_keys(&self, chat_id: i64) -> Result<u64> {
+        self.inner.revoke_api_keys(chat_id).await
+    }
+
+    async fn upsert_feed(&self, feed: &ChatFeed) -> Result<()> {
+        self.inner.upsert_feed(feed).await
+    }
+
+    async fn select_feed(&self, token: &str) -> Result<Option<ChatFeed>> {
+        self.inner.select_feed(token).await
+    }
+
+    async fn select_chat_feed(&self, chat_id: i64) -> Result<Option<ChatFeed>> {
+        self.inner.select_chat_feed(chat_id).await
+    }
+
+    async fn delete_feed(&self, chat_id: i64) -> Result<bool> {
+        self.inner.delete_feed(chat_id).await
+    }
+
+    async fn insert_reminder(&self, reminder: &Reminder) -> Result<i64> {
+        self.inner.insert_reminder(reminder).await
+    }
+
+    async fn select_due_reminders(&self, now: i64) -> Result<Vec<Reminder>> {
+        self.inner.select_due_reminders(now).await
+    }
+
+    async fn select_reminders(&self, chat_id: i64) -> Result<Vec<Reminder>> {
+        self.inner.select_reminders(chat_id).await
+    }
+
+    async fn mark_reminder_sent(&self, id: i64, sent_at: i64) -> Result<()> {
+        self.inner.mark_reminder_sent(id, sent_at).await
+    }
+
+    async fn delete_reminder(&self, chat_id: i64, id: i64) -> Result<bool> {
+        self.inner.delete_reminder(chat_id, id).await
+    }
+
+    async fn select_user_settings(&self, user_id: i64) -> Result<Option<UserSettings>> {
+        self.inner.select_user_settings(user_id).await
+    }
+
+    async fn upsert_user_settings(&self, settings: &UserSettings) -> Result<()> {
+        self.inner.upsert_user_settings(settings).await
+    }
+
+    async fn migrate(&self) -> Result<()> {
+        self.inner.migrate().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[tokio::test]
+    async fn announces_saved_and_edited_messages() {
+        let storage = NotifyingStorage::new(Arc::new(MemoryStorage::new()));
+        let mut events = storage.events().subscribe();
+        let message = Message::new("note".to_string(), 42, 1).await;
+        message.insert(&storage).await.unwrap();
+        let edited = EditedMessage::new(1, "edited note".to_string(), 42, Some(100)).await;
+        edited.change_message_text(&storage).await.unwrap();
+        storage.delete_message(42, 1, 200).await.unwrap();
+
+        match events.recv().await.unwrap() {
+            StorageEvent::Saved(saved) => assert_eq!(saved.text, "note"),
+            event => panic!("unexpected {:?}", event),
+        }
+        match events.recv().await.unwrap() {
+            StorageEvent::Edited(edited) => assert_eq!(edited.text, "edited note"),
+            event => panic!("unexpected {:?}", event),
+        }
+        assert!(events.try_recv().is_err());
+        assert_eq!(
+            storage.select_message(42, 1).await.unwrap().text,
+            "edited note"
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_quiet_about_writes_that_change_nothing_visible() {
+        let storage = NotifyingStorage::new(Arc::new(MemoryStorage::new()));
+        let message = Message::new("note".to_string(), 42, 1).await;
+        message.insert(&storage).await.unwrap();
+        storage.delete_message(42, 1, 200).await.unwrap();
+        let mut events = storage.events().subscribe();
+
+        assert!(!message.insert(&storage).await.unwrap());
+        let unsaved = EditedMessage::new(2, "/history".to_string(), 42, Some(100)).await;
+        assert!(!unsaved.change_message_text(&storage).await.unwrap());
+        let trashed = EditedMessage::new(1, "edited note".to_string(), 42, Some(300)).await;
+        assert!(trashed.change_message_text(&storage).await.unwrap());
+
+        assert!(events.try_recv().is_err());
+        assert_eq!(
+            storage.select_message(42, 1).await.unwrap().text,
+            "edited note"
+        );
+    }
+}
@@ -0,0 +1,978 @@
+use crate::models::{
+    ApiKey, Chat, ChatFeed, ChatSummary, EditedMessage, Filter, LinkMessage, LoggedUpdate, Message,
+    MessageRevision, MessageTag, PurgeRecord, Reminder, TagSummary, Update, User, UserSettings,
+};
+use crate::pg_service::PgService;
+use crate::storage::Storage;
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::Executor;
+
+#[async_trait]
+impl Storage for PgService {
+    async fn insert_message(&self, message: &Message) -> Result<bool> {
+        let inserted = sqlx::query(
+            r#"
+    INSERT INTO message (message_id,text,chat_id,date,edit_date,from_id,entities)
+    VALUES ( $1::bigint,$2,$3::bigint,$4::bigint,$5,$6,$7)
+    ON CONFLICT DO NOTHING
+        "#,
+        )
+        .bind(message.message_id)
+        .bind(&message.text)
+        .bind(message.chat_id)
+        .bind(message.date)
+        .bind(message.edit_date)
+        .bind(message.from_id)
+        .bind(&message.entities)
+        .execute(&self.pg_pool)
+        .await?
+        .rows_affected();
+        Ok(inserted > 0)
+    }
+
+    async fn insert_messages(&self, messages: &[Message]) -> Result<u64> {
+        let mut tx = self.pg_pool.begin().await?;
+        let mut inserted = 0;
+        for message in messages {
+            inserted += sqlx::query(
+                r#"
+    INSERT INTO message (message_id,text,chat_id,date,edit_date,from_id,entities)
+    VALUES ( $1::bigint,$2,$3::bigint,$4::bigint,$5,$6,$7)
+    ON CONFLICT DO NOTHING
+        "#,
+            )
+            .bind(message.message_id)
+            .bind(&message.text)
+            .bind(message.chat_id)
+            .bind(message.date)
+            .bind(message.edit_date)
+            .bind(message.from_id)
+            .bind(&message.entities)
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(inserted)
+    }
+
+    async fn select_message(&self, chat_id: i64, message_id: i64) -> Result<Message> {
+        let record = sqlx::query_as!(
+            Message,
+            r#"SELECT * FROM message WHERE chat_id = $1 AND message_id = $2"#,
+            chat_id,
+            message_id
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_first_message(&self, chat_id: i64, filter: &Filter) -> Result<Message> {
+        let record = sqlx::query_as!(
+            Message,
+            r#"SELECT * FROM message WHERE chat_id = $1 AND ($2::text IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $2))
+               AND (NOT $3::bool OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $4::bool
+               ORDER BY message_id LIMIT 1"#,
+            chat_id,
+            filter.tag(),
+            filter.favorites(),
+            filter.trash()
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_next_message(&self, chat_id: i64, filter: &Filter, id: i64) -> Result<Message> {
+        let record = sqlx::query_as!(
+            Message,
+            r#"SELECT * FROM message WHERE chat_id = $1 AND message_id > $2 AND ($3::text IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $3))
+               AND (NOT $4::bool OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $5::bool
+               ORDER BY message_id LIMIT 1"#,
+            chat_id,
+            id,
+            filter.tag(),
+            filter.favorites(),
+            filter.trash()
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_previous_message(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        id: i64,
+    ) -> Result<Message> {
+        let record = sqlx::query_as!(
+            Message,
+            r#"SELECT * FROM message WHERE chat_id = $1 AND message_id < $2 AND ($3::text IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $3))
+               AND (NOT $4::bool OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $5::bool
+               ORDER BY message_id DESC LIMIT 1"#,
+            chat_id,
+            id,
+            filter.tag(),
+            filter.favorites(),
+            filter.trash()
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_last_message(&self, chat_id: i64, filter: &Filter) -> Result<Message> {
+        let record = sqlx::query_as!(
+            Message,
+            r#"SELECT * FROM message WHERE chat_id = $1 AND ($2::text IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $2))
+               AND (NOT $3::bool OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $4::bool
+               ORDER BY message_id DESC LIMIT 1"#,
+            chat_id,
+            filter.tag(),
+            filter.favorites(),
+            filter.trash()
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_first_message_since(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        date: i64,
+    ) -> Result<Message> {
+        let record = sqlx::query_as!(
+            Message,
+            r#"SELECT * FROM message WHERE chat_id = $1 AND date >= $2 AND ($3::text IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $3))
+               AND (NOT $4::bool OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $5::bool
+               ORDER BY message_id LIMIT 1"#,
+            chat_id,
+            date,
+            filter.tag(),
+            filter.favorites(),
+            filter.trash()
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_all_by_chat_id(&self, chat_id: i64) -> Result<Vec<Message>> {
+        let records = sqlx::query_as!(
+            Message,
+            r#"SELECT * FROM message WHERE chat_id = $1 AND deleted_at IS NULL ORDER BY message_id"#,
+            chat_id
+        )
+        .fetch_all(&self.pg_pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn select_messages_after(
+        &self,
+        chat_id: i64,
+        after: i64,
+        limit: i64,
+    ) -> Result<Vec<Message>> {
+        let records = sqlx::query_as!(
+            Message,
+            r#"SELECT * FROM message WHERE chat_id = $1 AND message_id > $2 AND deleted_at IS NULL
+               ORDER BY message_id LIMIT $3"#,
+            chat_id,
+            after,
+            limit
+        )
+        .fetch_all(&self.pg_pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn select_latest_messages(&self, chat_id: i64, limit: i64) -> Result<Vec<Message>> {
+        let records = sqlx::query_as!(
+            Message,
+            r#"SELECT * FROM message WHERE chat_id = $1 AND deleted_at IS NULL
+               ORDER BY date DESC, message_id DESC LIMIT $2"#,
+            chat_id,
+            limit
+        )
+        .fetch_all(&self.pg_pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn search_messages(&self, chat_id: i64, query: &str, limit: i64) -> Result<Vec<Message>> {
+        let records = sqlx::query_as!(
+            Message,
+            r#"SELECT * FROM message WHERE chat_id = $1 AND deleted_at IS NULL
+               AND strpos(lower(text), lower($2)) > 0
+               ORDER BY date DESC, message_id DESC LIMIT $3"#,
+            chat_id,
+            query,
+            limit
+        )
+        .fetch_all(&self.pg_pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn count_messages(&self, chat_id: i64, filter: &Filter) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM message WHERE chat_id = $1 AND ($2::text IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $2))
+               AND (NOT $3::bool OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $4::bool"#,
+            chat_id,
+            filter.tag(),
+            filter.favorites(),
+            filter.trash()
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(count)
+    }
+
+    async fn message_position(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        message_id: i64,
+    ) -> Result<i64> {
+        let position = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM message
+               WHERE chat_id = $1 AND message_id <= $2 AND ($3::text IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $3))
+               AND (NOT $4::bool OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $5::bool"#,
+            chat_id,
+            message_id,
+            filter.tag(),
+            filter.favorites(),
+            filter.trash()
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(position)
+    }
+
+    async fn select_message_at(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        position: i64,
+    ) -> Result<Message> {
+        let record = sqlx::query_as!(
+            Message,
+            r#"SELECT * FROM message WHERE chat_id = $1 AND ($3::text IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $3))
+               AND (NOT $4::bool OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $5::bool
+               ORDER BY message_id OFFSET $2 LIMIT 1"#,
+            chat_id,
+            (position - 1).max(0),
+            filter.tag(),
+            filter.favorites(),
+            filter.trash()
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn change_message_text(&self, edited_message: &EditedMessage) -> Result<bool> {
+        let mut tx = self.pg_pool.begin().await?;
+        sqlx::query(
+            r#"
+                    INSERT INTO message_revision (chat_id, message_id, text, entities, date)
+                    SELECT chat_id, message_id, text, entities, COALESCE(edit_date, date)
+                    FROM message
+                    WHERE message_id = $2 AND chat_id = $3
+                    AND (text <> $1 OR entities IS DISTINCT FROM $4)
+                    "#,
+        )
+        .bind(&edited_message.text)
+        .bind(edited_message.message_id)
+        .bind(edited_message.chat_id)
+        .bind(&edited_message.entities)
+        .execute(&mut tx)
+        .await?;
+        let updated = sqlx::query(
+            r#"
+                    UPDATE message
+                    SET text = $1, edit_date = $4, entities = $5
+                    WHERE message_id = $2 AND chat_id = $3
+                    "#,
+        )
+        .bind(&edited_message.text)
+        .bind(edited_message.message_id)
+        .bind(edited_message.chat_id)
+        .bind(edited_message.edit_date)
+        .bind(&edited_message.entities)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        tx.commit().await?;
+        Ok(updated > 0)
+    }
+
+    async fn select_revisions(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Vec<MessageRevision>> {
+        let records = sqlx::query_as!(
+            MessageRevision,
+            r#"SELECT * FROM message_revision WHERE chat_id = $1 AND message_id = $2 ORDER BY date"#,
+            chat_id,
+            message_id
+        )
+        .fetch_all(&self.pg_pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn delete_message(&self, chat_id: i64, message_id: i64, deleted_at: i64) -> Result<()> {
+        sqlx::query(r#"UPDATE message SET deleted_at = $3 WHERE chat_id = $1 AND message_id = $2"#)
+            .bind(chat_id)
+            .bind(message_id)
+            .bind(deleted_at)
+            .execute(&self.pg_pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn restore_message(&self, chat_id: i64, message_id: i64) -> Result<()> {
+        sqlx::query(
+            r#"UPDATE message SET deleted_at = NULL WHERE chat_id = $1 AND message_id = $2"#,
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn purge_message(&self, chat_id: i64, message_id: i64) -> Result<()> {
+        let mut tx = self.pg_pool.begin().await?;
+        let purged = sqlx::query(
+            r#"DELETE FROM message WHERE chat_id = $1 AND message_id = $2 AND deleted_at IS NOT NULL"#,
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .execute(&mut tx)
+        .await?;
+        if purged.rows_affected() == 0 {
+            return Ok(());
+        }
+        sqlx::query(
+            r#"DELETE FROM message_tag WHERE message_id = $2 AND tag_id IN (SELECT id FROM tag WHERE chat_id = $1)"#,
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(r#"DELETE FROM favorite WHERE chat_id = $1 AND message_id = $2"#)
+            .bind(chat_id)
+            .bind(message_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM message_revision WHERE chat_id = $1 AND message_id = $2"#)
+            .bind(chat_id)
+            .bind(message_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn upsert_chat(&self, chat: &Chat) -> Result<()> {
+        sqlx::query(
+            r#"
+    INSERT INTO chat (id, chat_type, title, username)
+    VALUES ( $1::bigint, $2, $3, $4)
+    ON CONFLICT (id) DO UPDATE
+    SET chat_type = EXCLUDED.chat_type, title = EXCLUDED.title, username = EXCLUDED.username
+        "#,
+        )
+        .bind(chat.id)
+        .bind(&chat.chat_type)
+        .bind(&chat.title)
+        .bind(&chat.username)
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn upsert_user(&self, user: &User) -> Result<()> {
+        sqlx::query(
+            r#"
+    INSERT INTO tg_user (id, username, first_name, last_name)
+    VALUES ( $1::bigint, $2, $3, $4)
+    ON CONFLICT (id) DO UPDATE
+    SET username = EXCLUDED.username, first_name = EXCLUDED.first_name, last_name = EXCLUDED.last_name
+        "#,
+        )
+        .bind(user.id)
+        .bind(&user.username)
+        .bind(&user.first_name)
+        .bind(&user.last_name)
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn select_user(&self, user_id: i64) -> Result<Option<User>> {
+        let record = sqlx::query_as!(User, r#"SELECT * FROM tg_user WHERE id = $1"#, user_id)
+            .fetch_optional(&self.pg_pool)
+            .await?;
+        Ok(record)
+    }
+
+    async fn add_tags(&self, chat_id: i64, message_id: i64, tags: &[String]) -> Result<()> {
+        let mut tx = self.pg_pool.begin().await?;
+        for name in tags {
+            sqlx::query(
+                r#"INSERT INTO tag (chat_id, name) VALUES ($1, $2) ON CONFLICT DO NOTHING"#,
+            )
+            .bind(chat_id)
+            .bind(name)
+            .execute(&mut tx)
+            .await?;
+            sqlx::query(
+                r#"
+    INSERT INTO message_tag (tag_id, message_id)
+    SELECT id, $3 FROM tag WHERE chat_id = $1 AND name = $2
+    ON CONFLICT DO NOTHING
+        "#,
+            )
+            .bind(chat_id)
+            .bind(name)
+            .bind(message_id)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn list_tags(&self, chat_id: i64) -> Result<Vec<TagSummary>> {
+        let records = sqlx::query_as!(
+            TagSummary,
+            r#"SELECT t.name, COUNT(*) AS "messages!"
+               FROM tag t JOIN message_tag mt ON mt.tag_id = t.id
+               JOIN message m ON m.chat_id = t.chat_id AND m.message_id = mt.message_id
+               WHERE t.chat_id = $1 AND m.deleted_at IS NULL
+               GROUP BY t.name ORDER BY 2 DESC, t.name"#,
+            chat_id
+        )
+        .fetch_all(&self.pg_pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn select_message_tags(&self, chat_id: i64) -> Result<Vec<MessageTag>> {
+        let records = sqlx::query_as!(
+            MessageTag,
+            r#"SELECT mt.message_id, t.name
+               FROM tag t JOIN message_tag mt ON mt.tag_id = t.id
+               WHERE t.chat_id = $1
+               ORDER BY mt.message_id, t.name"#,
+            chat_id
+        )
+        .fetch_all(&self.pg_pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn toggle_favorite(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        let deleted = sqlx::query(r#"DELETE FROM favorite WHERE chat_id = $1 AND message_id = $2"#)
+            .bind(chat_id)
+            .bind(message_id)
+            .execute(&self.pg_pool)
+            .await?;
+        if deleted.rows_affected() > 0 {
+            return Ok(false);
+        }
+        sqlx::query(
+            r#"INSERT INTO favorite (chat_id, message_id) VALUES ($1, $2) ON CONFLICT DO NOTHING"#,
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(true)
+    }
+
+    async fn is_favorite(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        let starred = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM favorite WHERE chat_id = $1 AND message_id = $2) AS "starred!""#,
+            chat_id,
+            message_id
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(starred)
+    }
+
+    async fn insert_link(&self, link_message: &LinkMessage) -> Result<()> {
+        sqlx::query(
+            r#"
+    INSERT INTO link_message (id,text,chat_id, message_id, filter)
+    VALUES ( $1::bigint,$2,$3::bigint, $4::bigint, $5)
+    ON CONFLICT DO NOTHING
+        "#,
+        )
+        .bind(link_message.id)
+        .bind(&link_message.text)
+        .bind(link_message.chat_id)
+        .bind(link_message.message_id)
+        .bind(&link_message.filter)
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_and_return_link(&self, chat_id: i64) -> Result<LinkMessage> {
+        let select_element_after_delete = sqlx::query_as!(
+            LinkMessage,
+            r#"DELETE FROM link_message
+                    WHERE chat_id = $1
+                    RETURNING *
+                    "#,
+            chat_id
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(select_element_after_delete)
+    }
+
+    async fn select_link(&self, chat_id: i64) -> Result<LinkMessage> {
+        let record = sqlx::query_as!(
+            LinkMessage,
+            r#"SELECT * FROM link_message WHERE chat_id = $1 LIMIT 1"#,
+            chat_id
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn insert_update(&self, update: &Update) -> Result<()> {
+        sqlx::query(
+            r#"
+    UPDATE update
+    SET update_id = $1::bigint
+    WHERE id = 1;
+        "#,
+        )
+        .bind(update.update_id + 1)
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_last_update(&self) -> Result<Update> {
+        let record = sqlx::query_as!(Update, r#"SELECT * FROM UPDATE WHERE ID = 1"#)
+            .fetch_one(&self.pg_pool)
+            .await?;
+        Ok(record)
+    }
+
+    async fn set_update_offset(&self, update_id: i64) -> Result<()> {
+        sqlx::query(r#"UPDATE update SET update_id = $1::bigint WHERE id = 1"#)
+            .bind(update_id)
+            .execute(&self.pg_pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn log_update(&self, logged_update: &LoggedUpdate) -> Result<()> {
+        sqlx::query(
+            r#"
+    INSERT INTO update_log (update_id, body, chat_id)
+    VALUES ( $1::bigint, $2, $3)
+    ON CONFLICT DO NOTHING
+        "#,
+        )
+        .bind(logged_update.update_id)
+        .bind(&logged_update.body)
+        .bind(logged_update.chat_id)
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn select_logged_updates(&self, from_update_id: i64) -> Result<Vec<LoggedUpdate>> {
+        let records = sqlx::query_as!(
+            LoggedUpdate,
+            r#"SELECT * FROM update_log WHERE update_id >= $1 ORDER BY update_id"#,
+            from_update_id
+        )
+        .fetch_all(&self.pg_pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn list_chats(&self) -> Result<Vec<ChatSummary>> {
+        let records = sqlx::query_as!(
+            ChatSummary,
+            r#"SELECT m.chat_id, COUNT(*) AS "messages!", c.title
+               FROM message m LEFT JOIN chat c ON c.id = m.chat_id
+               WHERE m.deleted_at IS NULL
+               GROUP BY m.chat_id, c.title ORDER BY m.chat_id"#
+        )
+        .fetch_all(&self.pg_pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn purge_chat(&self, chat_id: i64) -> Result<u64> {
+        let mut tx = self.pg_pool.begin().await?;
+        let deleted = sqlx::query(r#"DELETE FROM message WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM link_message WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM tag WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM favorite WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM message_revision WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM api_key WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM chat_feed WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM reminder WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM chat WHERE id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM update_log WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(deleted.rows_affected())
+    }
+
+    async fn log_purge(&self, record: &PurgeRecord) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO purge_log (chat_id, user_id, messages, purged_at) VALUES ($1, $2, $3, $4)"#,
+        )
+        .bind(record.chat_id)
+        .bind(record.user_id)
+        .bind(record.messages)
+        .bind(record.purged_at)
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_purges(&self) -> Result<Vec<PurgeRecord>> {
+        let records = sqlx::query_as!(PurgeRecord, r#"SELECT * FROM purge_log ORDER BY purged_at"#)
+            .fetch_all(&self.pg_pool)
+            .await?;
+        Ok(records)
+    }
+
+    async fn insert_api_key(&self, api_key: &ApiKey) -> Result<()> {
+        sqlx::query(r#"INSERT INTO api_key (key, chat_id, created_at) VALUES ($1, $2, $3)"#)
+            .bind(&api_key.key)
+            .bind(api_key.chat_id)
+            .bind(api_key.created_at)
+            .execute(&self.pg_pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn select_api_key(&self, key: &str) -> Result<Option<ApiKey>> {
+        let record = sqlx::query_as!(ApiKey, r#"SELECT * FROM api_key WHERE key = $1"#, key)
+            .fetch_optional(&self.pg_pool)
+            .await?;
+        Ok(record)
+    }
+
+    async fn revoke_api_keys(&self, chat_id: i64) -> Result<u64> {
+        let revoked = sqlx::query(r#"DELETE FROM api_key WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&self.pg_pool)
+            .await?;
+        Ok(revoked.rows_affected())
+    }
+
+    async fn upsert_feed(&self, feed: &ChatFeed) -> Result<()> {
+        sqlx::query(
+            r#"
+    INSERT INTO chat_feed (chat_id, token, title, created_at)
+    VALUES ($1, $2, $3, $4)
+    ON CONFLICT (chat_id) DO UPDATE SET title = EXCLUDED.title
+        "#,
+        )
+        .bind(feed.chat_id)
+        .bind(&feed.token)
+        .bind(&feed.title)
+        .bind(feed.created_at)
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn select_feed(&self, token: &str) -> Result<Option<ChatFeed>> {
+        let record = sqlx::query_as!(
+            ChatFeed,
+            r#"SELECT * FROM chat_feed WHERE token = $1"#,
+            token
+        )
+        .fetch_optional(&self.pg_pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_chat_feed(&self, chat_id: i64) -> Result<Option<ChatFeed>> {
+        let record = sqlx::query_as!(
+            ChatFeed,
+            r#"SELECT * FROM chat_feed WHERE chat_id = $1"#,
+            chat_id
+        )
+        .fetch_optional(&self.pg_pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn delete_feed(&self, chat_id: i64) -> Result<bool> {
+        let deleted = sqlx::query(r#"DELETE FROM chat_feed WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&self.pg_pool)
+            .await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn insert_reminder(&self, reminder: &Reminder) -> Result<i64> {
+        let id = sqlx::query_scalar!(
+            r#"
+    INSERT INTO reminder (chat_id, message_id, text, due_at, created_at, silent, language, timezone)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+    RETURNING id
+        "#,
+            reminder.chat_id,
+            reminder.message_id,
+            reminder.text,
+            reminder.due_at,
+            reminder.created_at,
+            reminder.silent,
+            reminder.language,
+            reminder.timezone
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+        Ok(id)
+    }
+
+    async fn select_due_reminders(&self, now: i64) -> Result<Vec<Reminder>> {
+        let records = sqlx::query_as!(
+            Reminder,
+            r#"SELECT * FROM reminder WHERE sent_at IS NULL AND due_at <= $1 ORDER BY due_at, id"#,
+            now
+        )
+        .fetch_all(&self.pg_pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn select_reminders(&self, chat_id: i64) -> Result<Vec<Reminder>> {
+        let records = sqlx::query_as!(
+            Reminder,
+            r#"SELECT * FROM reminder WHERE chat_id = $1 AND sent_at IS NULL ORDER BY due_at, id"#,
+            chat_id
+        )
+        .fetch_all(&self.pg_pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn mark_reminder_sent(&self, id: i64, sent_at: i64) -> Result<()> {
+        sqlx::query(r#"UPDATE reminder SET sent_at = $2 WHERE id = $1"#)
+            .bind(id)
+            .bind(sent_at)
+            .execute(&self.pg_pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_reminder(&self, chat_id: i64, id: i64) -> Result<bool> {
+        let deleted = sqlx::query(
+            r#"DELETE FROM reminder WHERE chat_id = $1 AND id = $2 AND sent_at IS NULL"#,
+        )
+        .bind(chat_id)
+        .bind(id)
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn select_user_settings(&self, user_id: i64) -> Result<Option<UserSettings>> {
+        let record = sqlx::query_as!(
+            UserSettings,
+            r#"SELECT * FROM user_settings WHERE user_id = $1"#,
+            user_id
+        )
+        .fetch_optional(&self.pg_pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn upsert_user_settings(&self, settings: &UserSettings) -> Result<()> {
+        sqlx::query(
+            r#"
+    INSERT INTO user_settings (user_id, timezone, language, export_format, quiet_reminders)
+    VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (user_id) DO UPDATE
+    SET timezone = EXCLUDED.timezone, language = EXCLUDED.language,
+        export_format = EXCLUDED.export_format, quiet_reminders = EXCLUDED.quiet_reminders
+        "#,
+        )
+        .bind(settings.user_id)
+        .bind(&settings.timezone)
+        .bind(&settings.language)
+        .bind(&settings.export_format)
+        .bind(settings.quiet_reminders)
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn migrate(&self) -> Result<()> {
+        self.pg_pool
+            .execute(include_str!("../../migrations/update.sql"))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sqlx::postgres::{PgPool, PgPoolOptions};
+
+    /// Storage in a schema of its own, so every scenario starts empty and they run side by side.
+    /// `None` without `DATABASE_URL`, the scenarios then only run on the other backends.
+    async fn storage(schema: &str) -> Option<PgService> {
+        let url = dotenv::var("DATABASE_URL")
+            .ok()
+            .filter(|url| !url.is_empty())?;
+        let schema = format!("test_{}", schema);
+        PgPool::connect(&url)
+            .await
+            .unwrap()
+            .execute(
+                format!(
+                    "DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0}",
+                    schema
+                )
+                .as_str(),
+            )
+            .await
+            .unwrap();
+        let search_path = format!("SET search_path TO {}", schema);
+        let pg_pool = PgPoolOptions::new()
+            .after_connect(move |conn| {
+                let search_path = search_path.clone();
+                Box::pin(async move {
+                    conn.execute(search_path.as_str()).await?;
+                    Ok(())
+                })
+            })
+            .connect(&url)
+            .await
+            .unwrap();
+        let storage = PgService { pg_pool };
+        storage.migrate().await.unwrap();
+        Some(storage)
+    }
+
+    #[tokio::test]
+    async fn navigates_saved_messages() {
+        if let Some(storage) = storage("navigation").await {
+            crate::storage::check_navigation(&storage).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_by_tag() {
+        if let Some(storage) = storage("tags").await {
+            crate::storage::check_tags(&storage).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_favorites() {
+        if let Some(storage) = storage("favorites").await {
+            crate::storage::check_favorites(&storage).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_deleted_messages_in_trash() {
+        if let Some(storage) = storage("trash").await {
+            crate::storage::check_trash(&storage).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_api_queries() {
+        if let Some(storage) = storage("api_queries").await {
+            crate::storage::check_api_queries(&storage).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_feeds() {
+        if let Some(storage) = storage("feeds").await {
+            crate::storage::check_feeds(&storage).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn schedules_reminders() {
+        if let Some(storage) = storage("reminders").await {
+            crate::storage::check_reminders(&storage).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_user_settings() {
+        if let Some(storage) = storage("user_settings").await {
+            crate::storage::check_user_settings(&storage).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn inserts_messages_once() {
+        if let Some(storage) = storage("insert_messages").await {
+            crate::storage::check_insert_messages(&storage).await;
+        }
+    }
+}
@@ -0,0 +1,1078 @@
+use crate::models::{
+    ApiKey, Chat, ChatFeed, ChatSummary, EditedMessage, Filter, LinkMessage, LoggedUpdate, Message,
+    MessageRevision, MessageTag, PurgeRecord, Reminder, TagSummary, Update, User, UserSettings,
+};
+use crate::storage::Storage;
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Executor, SqlitePool};
+use std::str::FromStr;
+
+/// Single-file backend for single-user deployments, e.g. `SQLITE_URL=sqlite://tgbot.db`.
+/// Uses one connection so `sqlite::memory:` databases are shared by every query.
+#[derive(Debug, Clone)]
+pub struct SqliteStorage {
+    pub pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    pub async fn new(url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+        let storage = Self { pool };
+        storage.migrate().await?;
+        Ok(storage)
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn insert_message(&self, message: &Message) -> Result<bool> {
+        let inserted = sqlx::query(
+            r#"
+    INSERT INTO message (message_id,text,chat_id,date,edit_date,from_id,entities)
+    VALUES ( $1,$2,$3,$4,$5,$6,$7)
+    ON CONFLICT DO NOTHING
+        "#,
+        )
+        .bind(message.message_id)
+        .bind(&message.text)
+        .bind(message.chat_id)
+        .bind(message.date)
+        .bind(message.edit_date)
+        .bind(message.from_id)
+        .bind(&message.entities)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(inserted > 0)
+    }
+
+    async fn insert_messages(&self, messages: &[Message]) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut inserted = 0;
+        for message in messages {
+            inserted += sqlx::query(
+                r#"
+    INSERT INTO message (message_id,text,chat_id,date,edit_date,from_id,entities)
+    VALUES ( $1,$2,$3,$4,$5,$6,$7)
+    ON CONFLICT DO NOTHING
+        "#,
+            )
+            .bind(message.message_id)
+            .bind(&message.text)
+            .bind(message.chat_id)
+            .bind(message.date)
+            .bind(message.edit_date)
+            .bind(message.from_id)
+            .bind(&message.entities)
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(inserted)
+    }
+
+    async fn select_message(&self, chat_id: i64, message_id: i64) -> Result<Message> {
+        let record = sqlx::query_as::<_, Message>(
+            r#"SELECT * FROM message WHERE chat_id = $1 AND message_id = $2"#,
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_first_message(&self, chat_id: i64, filter: &Filter) -> Result<Message> {
+        let record = sqlx::query_as::<_, Message>(
+            r#"SELECT * FROM message WHERE chat_id = $1 AND ($2 IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $2))
+               AND (NOT $3 OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $4
+               ORDER BY message_id LIMIT 1"#,
+        )
+        .bind(chat_id)
+        .bind(filter.tag())
+        .bind(filter.favorites())
+        .bind(filter.trash())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_next_message(&self, chat_id: i64, filter: &Filter, id: i64) -> Result<Message> {
+        let record = sqlx::query_as::<_, Message>(
+            r#"SELECT * FROM message WHERE chat_id = $1 AND message_id > $2 AND ($3 IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $3))
+               AND (NOT $4 OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $5
+               ORDER BY message_id LIMIT 1"#,
+        )
+        .bind(chat_id)
+        .bind(id)
+        .bind(filter.tag())
+        .bind(filter.favorites())
+        .bind(filter.trash())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_previous_message(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        id: i64,
+    ) -> Result<Message> {
+        let record = sqlx::query_as::<_, Message>(
+            r#"SELECT * FROM message WHERE chat_id = $1 AND message_id < $2 AND ($3 IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $3))
+               AND (NOT $4 OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $5
+               ORDER BY message_id DESC LIMIT 1"#,
+        )
+        .bind(chat_id)
+        .bind(id)
+        .bind(filter.tag())
+        .bind(filter.favorites())
+        .bind(filter.trash())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_last_message(&self, chat_id: i64, filter: &Filter) -> Result<Message> {
+        let record = sqlx::query_as::<_, Message>(
+            r#"SELECT * FROM message WHERE chat_id = $1 AND ($2 IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $2))
+               AND (NOT $3 OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $4
+               ORDER BY message_id DESC LIMIT 1"#,
+        )
+        .bind(chat_id)
+        .bind(filter.tag())
+        .bind(filter.favorites())
+        .bind(filter.trash())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_first_message_since(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        date: i64,
+    ) -> Result<Message> {
+        let record = sqlx::query_as::<_, Message>(
+            r#"SELECT * FROM message WHERE chat_id = $1 AND date >= $2 AND ($3 IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $3))
+               AND (NOT $4 OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $5
+               ORDER BY message_id LIMIT 1"#,
+        )
+        .bind(chat_id)
+        .bind(date)
+        .bind(filter.tag())
+        .bind(filter.favorites())
+        .bind(filter.trash())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_all_by_chat_id(&self, chat_id: i64) -> Result<Vec<Message>> {
+        let records = sqlx::query_as::<_, Message>(
+            r#"SELECT * FROM message WHERE chat_id = $1 AND deleted_at IS NULL ORDER BY message_id"#,
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn select_messages_after(
+        &self,
+        chat_id: i64,
+        after: i64,
+        limit: i64,
+    ) -> Result<Vec<Message>> {
+        let records = sqlx::query_as::<_, Message>(
+            r#"SELECT * FROM message WHERE chat_id = $1 AND message_id > $2 AND deleted_at IS NULL
+               ORDER BY message_id LIMIT $3"#,
+        )
+        .bind(chat_id)
+        .bind(after)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn select_latest_messages(&self, chat_id: i64, limit: i64) -> Result<Vec<Message>> {
+        let records = sqlx::query_as::<_, Message>(
+            r#"SELECT * FROM message WHERE chat_id = $1 AND deleted_at IS NULL
+               ORDER BY date DESC, message_id DESC LIMIT $2"#,
+        )
+        .bind(chat_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn search_messages(&self, chat_id: i64, query: &str, limit: i64) -> Result<Vec<Message>> {
+        // SQLite lowercases ASCII letters only.
+        let records = sqlx::query_as::<_, Message>(
+            r#"SELECT * FROM message WHERE chat_id = $1 AND deleted_at IS NULL
+               AND instr(lower(text), lower($2)) > 0
+               ORDER BY date DESC, message_id DESC LIMIT $3"#,
+        )
+        .bind(chat_id)
+        .bind(query)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn count_messages(&self, chat_id: i64, filter: &Filter) -> Result<i64> {
+        let count = sqlx::query_scalar(
+            r#"SELECT COUNT(*) FROM message WHERE chat_id = $1 AND ($2 IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $2))
+               AND (NOT $3 OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $4"#,
+        )
+        .bind(chat_id)
+        .bind(filter.tag())
+        .bind(filter.favorites())
+        .bind(filter.trash())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count)
+    }
+
+    async fn message_position(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        message_id: i64,
+    ) -> Result<i64> {
+        let position = sqlx::query_scalar(
+            r#"SELECT COUNT(*) FROM message WHERE chat_id = $1 AND message_id <= $2 AND ($3 IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $3))
+               AND (NOT $4 OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $5"#,
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .bind(filter.tag())
+        .bind(filter.favorites())
+        .bind(filter.trash())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(position)
+    }
+
+    async fn select_message_at(
+        &self,
+        chat_id: i64,
+        filter: &Filter,
+        position: i64,
+    ) -> Result<Message> {
+        let record = sqlx::query_as::<_, Message>(
+            r#"SELECT * FROM message WHERE chat_id = $1 AND ($3 IS NULL OR message_id IN (SELECT mt.message_id FROM message_tag mt JOIN tag t ON t.id = mt.tag_id WHERE t.chat_id = $1 AND t.name = $3))
+               AND (NOT $4 OR message_id IN (SELECT f.message_id FROM favorite f WHERE f.chat_id = $1))
+               AND (deleted_at IS NOT NULL) = $5
+               ORDER BY message_id LIMIT 1 OFFSET $2"#,
+        )
+        .bind(chat_id)
+        .bind((position - 1).max(0))
+        .bind(filter.tag())
+        .bind(filter.favorites())
+        .bind(filter.trash())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn change_message_text(&self, edited_message: &EditedMessage) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"INSERT INTO message_revision (chat_id, message_id, text, entities, date)
+               SELECT chat_id, message_id, text, entities, COALESCE(edit_date, date) FROM message
+               WHERE message_id = $2 AND chat_id = $3 AND (text <> $1 OR entities IS NOT $4)"#,
+        )
+        .bind(&edited_message.text)
+        .bind(edited_message.message_id)
+        .bind(edited_message.chat_id)
+        .bind(&edited_message.entities)
+        .execute(&mut tx)
+        .await?;
+        let updated = sqlx::query(
+            r#"UPDATE message SET text = $1, edit_date = $4, entities = $5 WHERE message_id = $2 AND chat_id = $3"#,
+        )
+        .bind(&edited_message.text)
+        .bind(edited_message.message_id)
+        .bind(edited_message.chat_id)
+        .bind(edited_message.edit_date)
+        .bind(&edited_message.entities)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        tx.commit().await?;
+        Ok(updated > 0)
+    }
+
+    async fn select_revisions(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Vec<MessageRevision>> {
+        let records = sqlx::query_as::<_, MessageRevision>(
+            r#"SELECT * FROM message_revision WHERE chat_id = $1 AND message_id = $2 ORDER BY date"#,
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn delete_message(&self, chat_id: i64, message_id: i64, deleted_at: i64) -> Result<()> {
+        sqlx::query(r#"UPDATE message SET deleted_at = $3 WHERE chat_id = $1 AND message_id = $2"#)
+            .bind(chat_id)
+            .bind(message_id)
+            .bind(deleted_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn restore_message(&self, chat_id: i64, message_id: i64) -> Result<()> {
+        sqlx::query(
+            r#"UPDATE message SET deleted_at = NULL WHERE chat_id = $1 AND message_id = $2"#,
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn purge_message(&self, chat_id: i64, message_id: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let purged = sqlx::query(
+            r#"DELETE FROM message WHERE chat_id = $1 AND message_id = $2 AND deleted_at IS NOT NULL"#,
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .execute(&mut tx)
+        .await?;
+        if purged.rows_affected() == 0 {
+            return Ok(());
+        }
+        sqlx::query(
+            r#"DELETE FROM message_tag WHERE message_id = $2 AND tag_id IN (SELECT id FROM tag WHERE chat_id = $1)"#,
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(r#"DELETE FROM favorite WHERE chat_id = $1 AND message_id = $2"#)
+            .bind(chat_id)
+            .bind(message_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM message_revision WHERE chat_id = $1 AND message_id = $2"#)
+            .bind(chat_id)
+            .bind(message_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn upsert_chat(&self, chat: &Chat) -> Result<()> {
+        sqlx::query(
+            r#"
+    INSERT INTO chat (id, chat_type, title, username)
+    VALUES ( $1, $2, $3, $4)
+    ON CONFLICT (id) DO UPDATE
+    SET chat_type = excluded.chat_type, title = excluded.title, username = excluded.username
+        "#,
+        )
+        .bind(chat.id)
+        .bind(&chat.chat_type)
+        .bind(&chat.title)
+        .bind(&chat.username)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn upsert_user(&self, user: &User) -> Result<()> {
+        sqlx::query(
+            r#"
+    INSERT INTO tg_user (id, username, first_name, last_name)
+    VALUES ( $1, $2, $3, $4)
+    ON CONFLICT (id) DO UPDATE
+    SET username = excluded.username, first_name = excluded.first_name, last_name = excluded.last_name
+        "#,
+        )
+        .bind(user.id)
+        .bind(&user.username)
+        .bind(&user.first_name)
+        .bind(&user.last_name)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn select_user(&self, user_id: i64) -> Result<Option<User>> {
+        let record = sqlx::query_as::<_, User>(r#"SELECT * FROM tg_user WHERE id = $1"#)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(record)
+    }
+
+    async fn add_tags(&self, chat_id: i64, message_id: i64, tags: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for name in tags {
+            sqlx::query(
+                r#"INSERT INTO tag (chat_id, name) VALUES ($1, $2) ON CONFLICT DO NOTHING"#,
+            )
+            .bind(chat_id)
+            .bind(name)
+            .execute(&mut tx)
+            .await?;
+            sqlx::query(
+                r#"
+    INSERT INTO message_tag (tag_id, message_id)
+    SELECT id, $3 FROM tag WHERE chat_id = $1 AND name = $2
+    ON CONFLICT DO NOTHING
+        "#,
+            )
+            .bind(chat_id)
+            .bind(name)
+            .bind(message_id)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn list_tags(&self, chat_id: i64) -> Result<Vec<TagSummary>> {
+        let records = sqlx::query_as::<_, TagSummary>(
+            r#"SELECT t.name, COUNT(*) AS messages
+               FROM tag t JOIN message_tag mt ON mt.tag_id = t.id
+               JOIN message m ON m.chat_id = t.chat_id AND m.message_id = mt.message_id
+               WHERE t.chat_id = $1 AND m.deleted_at IS NULL
+               GROUP BY t.name ORDER BY 2 DESC, t.name"#,
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn select_message_tags(&self, chat_id: i64) -> Result<Vec<MessageTag>> {
+        let records = sqlx::query_as::<_, MessageTag>(
+            r#"SELECT mt.message_id, t.name
+               FROM tag t JOIN message_tag mt ON mt.tag_id = t.id
+               WHERE t.chat_id = $1
+               ORDER BY mt.message_id, t.name"#,
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn toggle_favorite(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        let deleted = sqlx::query(r#"DELETE FROM favorite WHERE chat_id = $1 AND message_id = $2"#)
+            .bind(chat_id)
+            .bind(message_id)
+            .execute(&self.pool)
+            .await?;
+        if deleted.rows_affected() > 0 {
+            return Ok(false);
+        }
+        sqlx::query(
+            r#"INSERT INTO favorite (chat_id, message_id) VALUES ($1, $2) ON CONFLICT DO NOTHING"#,
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(true)
+    }
+
+    async fn is_favorite(&self, chat_id: i64, message_id: i64) -> Result<bool> {
+        let starred = sqlx::query_scalar(
+            r#"SELECT EXISTS (SELECT 1 FROM favorite WHERE chat_id = $1 AND message_id = $2)"#,
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(starred)
+    }
+
+    async fn insert_link(&self, link_message: &LinkMessage) -> Result<()> {
+        sqlx::query(
+            r#"
+    INSERT INTO link_message (id,text,chat_id, message_id, filter)
+    VALUES ( $1,$2,$3, $4, $5)
+    ON CONFLICT DO NOTHING
+        "#,
+        )
+        .bind(link_message.id)
+        .bind(&link_message.text)
+        .bind(link_message.chat_id)
+        .bind(link_message.message_id)
+        .bind(&link_message.filter)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_and_return_link(&self, chat_id: i64) -> Result<LinkMessage> {
+        let record = sqlx::query_as::<_, LinkMessage>(
+            r#"DELETE FROM link_message WHERE chat_id = $1 RETURNING *"#,
+        )
+        .bind(chat_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn select_link(&self, chat_id: i64) -> Result<LinkMessage> {
+        let record = sqlx::query_as::<_, LinkMessage>(
+            r#"SELECT * FROM link_message WHERE chat_id = $1 LIMIT 1"#,
+        )
+        .bind(chat_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    async fn insert_update(&self, update: &Update) -> Result<()> {
+        sqlx::query(r#"UPDATE "update" SET update_id = $1 WHERE id = 1"#)
+            .bind(update.update_id + 1)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_last_update(&self) -> Result<Update> {
+        let record = sqlx::query_as::<_, Update>(r#"SELECT * FROM "update" WHERE id = 1"#)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(record)
+    }
+
+    async fn set_update_offset(&self, update_id: i64) -> Result<()> {
+        sqlx::query(r#"UPDATE "update" SET update_id = $1 WHERE id = 1"#)
+            .bind(update_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn log_update(&self, logged_update: &LoggedUpdate) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO update_log (update_id, body, chat_id) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING"#,
+        )
+        .bind(logged_update.update_id)
+        .bind(&logged_update.body)
+        .bind(logged_update.chat_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn select_logged_updates(&self, from_update_id: i64) -> Result<Vec<LoggedUpdate>> {
+        let records = sqlx::query_as::<_, LoggedUpdate>(
+            r#"SELECT * FROM update_log WHERE update_id >= $1 ORDER BY update_id"#,
+        )
+        .bind(from_update_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn list_chats(&self) -> Result<Vec<ChatSummary>> {
+        let records = sqlx::query_as::<_, ChatSummary>(
+            r#"SELECT m.chat_id, COUNT(*) AS messages, c.title
+               FROM message m LEFT JOIN chat c ON c.id = m.chat_id
+               WHERE m.deleted_at IS NULL
+               GROUP BY m.chat_id, c.title ORDER BY m.chat_id"#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn purge_chat(&self, chat_id: i64) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = sqlx::query(r#"DELETE FROM message WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM link_message WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(
+            r#"DELETE FROM message_tag WHERE tag_id IN (SELECT id FROM tag WHERE chat_id = $1)"#,
+        )
+        .bind(chat_id)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(r#"DELETE FROM tag WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM favorite WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM message_revision WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM api_key WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM chat_feed WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM reminder WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM chat WHERE id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM update_log WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(deleted.rows_affected())
+    }
+
+    async fn log_purge(&self, record: &PurgeRecord) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO purge_log (chat_id, user_id, messages, purged_at) VALUES ($1, $2, $3, $4)"#,
+        )
+        .bind(record.chat_id)
+        .bind(record.user_id)
+        .bind(record.messages)
+        .bind(record.purged_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_purges(&self) -> Result<Vec<PurgeRecord>> {
+        let records =
+            sqlx::query_as::<_, PurgeRecord>(r#"SELECT * FROM purge_log ORDER BY purged_at"#)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(records)
+    }
+
+    async fn insert_api_key(&self, api_key: &ApiKey) -> Result<()> {
+        sqlx::query(r#"INSERT INTO api_key (key, chat_id, created_at) VALUES ($1, $2, $3)"#)
+            .bind(&api_key.key)
+            .bind(api_key.chat_id)
+            .bind(api_key.created_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn select_api_key(&self, key: &str) -> Result<Option<ApiKey>> {
+        let record = sqlx::query_as::<_, ApiKey>(r#"SELECT * FROM api_key WHERE key = $1"#)
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(record)
+    }
+
+    async fn revoke_api_keys(&self, chat_id: i64) -> Result<u64> {
+        let revoked = sqlx::query(r#"DELETE FROM api_key WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(revoked.rows_affected())
+    }
+
+    async fn upsert_feed(&self, feed: &ChatFeed) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO chat_feed (chat_id, token, title, created_at) VALUES ($1, $2, $3, $4)
+               ON CONFLICT (chat_id) DO UPDATE SET title = excluded.title"#,
+        )
+        .bind(feed.chat_id)
+        .bind(&feed.token)
+        .bind(&feed.title)
+        .bind(feed.created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn select_feed(&self, token: &str) -> Result<Option<ChatFeed>> {
+        let record = sqlx::query_as::<_, ChatFeed>(r#"SELECT * FROM chat_feed WHERE token = $1"#)
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(record)
+    }
+
+    async fn select_chat_feed(&self, chat_id: i64) -> Result<Option<ChatFeed>> {
+        let record = sqlx::query_as::<_, ChatFeed>(r#"SELECT * FROM chat_feed WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(record)
+    }
+
+    async fn delete_feed(&self, chat_id: i64) -> Result<bool> {
+        let deleted = sqlx::query(r#"DELETE FROM chat_feed WHERE chat_id = $1"#)
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn insert_reminder(&self, reminder: &Reminder) -> Result<i64> {
+        let inserted = sqlx::query(
+            r#"INSERT INTO reminder (chat_id, message_id, text, due_at, created_at, silent, language, timezone)
+               VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+        )
+        .bind(reminder.chat_id)
+        .bind(reminder.message_id)
+        .bind(&reminder.text)
+        .bind(reminder.due_at)
+        .bind(reminder.created_at)
+        .bind(reminder.silent)
+        .bind(&reminder.language)
+        .bind(&reminder.timezone)
+        .execute(&self.pool)
+        .await?;
+        Ok(inserted.last_insert_rowid())
+    }
+
+    async fn select_due_reminders(&self, now: i64) -> Result<Vec<Reminder>> {
+        let records = sqlx::query_as::<_, Reminder>(
+            r#"SELECT * FROM reminder WHERE sent_at IS NULL AND due_at <= $1 ORDER BY due_at, id"#,
+        )
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn select_reminders(&self, chat_id: i64) -> Result<Vec<Reminder>> {
+        let records = sqlx::query_as::<_, Reminder>(
+            r#"SELECT * FROM reminder WHERE chat_id = $1 AND sent_at IS NULL ORDER BY due_at, id"#,
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    async fn mark_reminder_sent(&self, id: i64, sent_at: i64) -> Result<()> {
+        sqlx::query(r#"UPDATE reminder SET sent_at = $2 WHERE id = $1"#)
+            .bind(id)
+            .bind(sent_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_reminder(&self, chat_id: i64, id: i64) -> Result<bool> {
+        let deleted = sqlx::query(
+            r#"DELETE FROM reminder WHERE chat_id = $1 AND id = $2 AND sent_at IS NULL"#,
+        )
+        .bind(chat_id)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn select_user_settings(&self, user_id: i64) -> Result<Option<UserSettings>> {
+        let record =
+            sqlx::query_as::<_, UserSettings>(r#"SELECT * FROM user_settings WHERE user_id = $1"#)
+                .bind(user_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(record)
+    }
+
+    async fn upsert_user_settings(&self, settings: &UserSettings) -> Result<()> {
+        sqlx::query(
+            r#"
+    INSERT INTO user_settings (user_id, timezone, language, export_format, quiet_reminders)
+    VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (user_id) DO UPDATE
+    SET timezone = excluded.timezone, language = excluded.language,
+        export_format = excluded.export_format, quiet_reminders = excluded.quiet_reminders
+        "#,
+        )
+        .bind(settings.user_id)
+        .bind(&settings.timezone)
+        .bind(&settings.language)
+        .bind(&settings.export_format)
+        .bind(settings.quiet_reminders)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn migrate(&self) -> Result<()> {
+        self.pool
+            .execute(include_str!("../../migrations/sqlite.sql"))
+            .await?;
+        let mut tx = self.pool.begin().await?;
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&mut tx)
+            .await?;
+        for upgrade in UPGRADES.iter().skip(version as usize) {
+            match *upgrade {
+                Upgrade::Column(table, column, definition) => {
+                    let exists: i64 = sqlx::query_scalar(
+                        r#"SELECT COUNT(*) FROM pragma_table_info($1) WHERE name = $2"#,
+                    )
+                    .bind(table)
+                    .bind(column)
+                    .fetch_one(&mut tx)
+                    .await?;
+                    if exists == 0 {
+                        let alter = format!(
+                            r#"ALTER TABLE {} ADD COLUMN {} {}"#,
+                            table, column, definition
+                        );
+                        tx.execute(alter.as_str()).await?;
+                    }
+                }
+                Upgrade::Statements(sql) => {
+                    tx.execute(sql).await?;
+                }
+                Upgrade::ChatKey(table, copy) => {
+                    let chat_key: i64 = sqlx::query_scalar(
+                        r#"SELECT pk FROM pragma_table_info($1) WHERE name = 'chat_id'"#,
+                    )
+                    .bind(table)
+                    .fetch_one(&mut tx)
+                    .await?;
+                    if chat_key == 0 {
+                        tx.execute(copy).await?;
+                    }
+                }
+            }
+        }
+        tx.execute(format!("PRAGMA user_version = {}", UPGRADES.len()).as_str())
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Changes to tables that existed before, in the order they were made. `sqlite.sql` creates
+/// missing tables in their latest shape, and `PRAGMA user_version` counts the upgrades
+/// a database has been through, so files written by older builds catch up on start.
+enum Upgrade {
+    /// `ALTER TABLE .. ADD COLUMN`, skipped when the table already has the column.
+    Column(&'static str, &'static str, &'static str),
+    /// The table was keyed by an id that is only unique within a chat, SQLite can only
+    /// add `chat_id` to the key by copying the table, which the statements do.
+    ChatKey(&'static str, &'static str),
+    /// Statements that are harmless to repeat, e.g. backfills of added columns.
+    Statements(&'static str),
+}
+
+const UPGRADES: &[Upgrade] = &[
+    Upgrade::Column("message", "date", "INTEGER NOT NULL DEFAULT 0"),
+    Upgrade::Column("message", "edit_date", "INTEGER"),
+    Upgrade::Column("message", "from_id", "INTEGER"),
+    Upgrade::ChatKey("message", MESSAGE_KEY),
+    Upgrade::Column("message", "entities", "TEXT"),
+    Upgrade::Column("link_message", "filter", "TEXT"),
+    Upgrade::Column("message", "deleted_at", "INTEGER"),
+    Upgrade::Column("update_log", "chat_id", "INTEGER"),
+    Upgrade::Column("reminder", "silent", "BOOLEAN NOT NULL DEFAULT FALSE"),
+    Upgrade::Column("reminder", "language", "TEXT NOT NULL DEFAULT 'en'"),
+    Upgrade::Column("reminder", "timezone", "TEXT NOT NULL DEFAULT 'UTC'"),
+    Upgrade::ChatKey("link_message", LINK_KEY),
+    Upgrade::Statements(UPDATE_LOG_CHATS),
+];
+
+const MESSAGE_KEY: &str = r#"
+ALTER TABLE message RENAME TO message_by_id;
+CREATE TABLE message
+(
+    message_id  INTEGER NOT NULL,
+    text        TEXT    NOT NULL,
+    chat_id     INTEGER NOT NULL,
+    date        INTEGER NOT NULL DEFAULT 0,
+    edit_date   INTEGER,
+    from_id     INTEGER,
+    PRIMARY KEY (chat_id, message_id)
+);
+INSERT INTO message (message_id, text, chat_id, date, edit_date, from_id)
+SELECT message_id, text, chat_id, date, edit_date, from_id FROM message_by_id;
+DROP TABLE message_by_id;
+"#;
+
+/// Chats of updates logged before `update_log.chat_id`, so `/forget` finds them too.
+const UPDATE_LOG_CHATS: &str = r#"
+UPDATE update_log
+SET chat_id = COALESCE(json_extract(body, '$.message.chat.id'),
+                       json_extract(body, '$.edited_message.chat.id'),
+                       json_extract(body, '$.callback_query.message.chat.id'))
+WHERE chat_id IS NULL;
+"#;
+
+const LINK_KEY: &str = r#"
+ALTER TABLE link_message RENAME TO link_message_by_id;
+CREATE TABLE link_message
+(
+    id          INTEGER NOT NULL,
+    text        TEXT    NOT NULL,
+    chat_id     INTEGER NOT NULL,
+    message_id  INTEGER NOT NULL,
+    filter      TEXT,
+    PRIMARY KEY (chat_id, id)
+);
+INSERT INTO link_message (id, text, chat_id, message_id, filter)
+SELECT id, text, chat_id, message_id, filter FROM link_message_by_id;
+DROP TABLE link_message_by_id;
+"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn navigates_saved_messages() {
+        let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+        crate::storage::check_navigation(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn filters_by_tag() {
+        let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+        crate::storage::check_tags(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn filters_favorites() {
+        let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+        crate::storage::check_favorites(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn keeps_deleted_messages_in_trash() {
+        let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+        crate::storage::check_trash(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn inserts_messages_once() {
+        let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+        crate::storage::check_insert_messages(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn answers_api_queries() {
+        let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+        crate::storage::check_api_queries(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn keeps_feeds() {
+        let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+        crate::storage::check_feeds(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn schedules_reminders() {
+        let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+        crate::storage::check_reminders(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn keeps_user_settings() {
+        let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+        crate::storage::check_user_settings(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn upgrades_databases_of_older_builds() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        pool.execute(
+            r#"
+    CREATE TABLE link_message (id INTEGER PRIMARY KEY, text TEXT NOT NULL, chat_id INTEGER NOT NULL, message_id INTEGER NOT NULL);
+    CREATE TABLE message (message_id INTEGER PRIMARY KEY, text TEXT NOT NULL, chat_id INTEGER NOT NULL);
+    CREATE TABLE update_log (update_id INTEGER PRIMARY KEY, body TEXT NOT NULL);
+    INSERT INTO message VALUES (1, 'first', 10);
+    INSERT INTO update_log VALUES (1, '{"update_id": 1, "message": {"chat": {"id": 10}}}');
+    INSERT INTO update_log VALUES (2, '{"update_id": 2, "callback_query": {"message": {"chat": {"id": 20}}}}');
+            "#,
+        )
+        .await
+        .unwrap();
+        let storage = SqliteStorage { pool };
+        storage.migrate().await.unwrap();
+        storage.migrate().await.unwrap();
+
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap();
+        assert_eq!(version, UPGRADES.len() as i64);
+        let first = storage.select_message(10, 1).await.unwrap();
+        assert_eq!((first.text.as_str(), first.date), ("first", 0));
+        let other_chat = Message {
+            chat_id: 20,
+            ..first
+        };
+        assert!(storage.insert_message(&other_chat).await.unwrap());
+        assert_eq!(storage.select_message(20, 1).await.unwrap().text, "first");
+        for chat_id in [10, 20] {
+            let link = LinkMessage::new(100, "browser".to_string(), chat_id, 1).await;
+            storage.insert_link(&link).await.unwrap();
+        }
+        assert_eq!(storage.select_link(20).await.unwrap().id, 100);
+
+        // `/forget` drops updates logged before the upgrade as well
+        storage.purge_chat(10).await.unwrap();
+        let logged = storage.select_logged_updates(0).await.unwrap();
+        let ids: Vec<i64> = logged.iter().map(|u| u.update_id).collect();
+        assert_eq!(ids, [2]);
+    }
+
+    #[tokio::test]
+    async fn keeps_update_offset() {
+        let storage = SqliteStorage::new("sqlite::memory:").await.unwrap();
+        storage
+            .insert_update(&Update::new(1, 41).await)
+            .await
+            .unwrap();
+        assert_eq!(storage.get_last_update().await.unwrap().update_id, 42);
+    }
+}
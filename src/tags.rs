@@ -0,0 +1,75 @@
+//! Hashtags of saved messages.
+
+/// Lowercased `#tags` of `text` in order of appearance, without `#` and duplicates.
+/// A tag starts at `#` that doesn't follow a word character and runs over letters, digits and `_`.
+pub fn extract(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    let mut previous = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let starts_tag = c == '#' && !previous.is_some_and(is_tag_char);
+        previous = Some(c);
+        if !starts_tag {
+            continue;
+        }
+        let start = i + 1;
+        let mut end = start;
+        while let Some(&(j, c)) = chars.peek() {
+            if !is_tag_char(c) {
+                break;
+            }
+            end = j + c.len_utf8();
+            previous = Some(c);
+            chars.next();
+        }
+        if let Some(tag) = normalize(&text[start..end]) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+    tags
+}
+
+/// Tag names given to `/tag`, with or without `#`. `None` when one of them isn't a valid tag.
+pub fn parse_args(args: &str) -> Option<Vec<String>> {
+    let tags: Option<Vec<String>> = args
+        .split_whitespace()
+        .map(|word| normalize(word.strip_prefix('#').unwrap_or(word)))
+        .collect();
+    tags.filter(|tags| !tags.is_empty())
+}
+
+/// Lowercased tag, `None` for empty names, names with other characters or only digits.
+pub fn normalize(name: &str) -> Option<String> {
+    let valid = name.chars().all(is_tag_char) && !name.chars().all(|c| c.is_ascii_digit());
+    (valid && !name.is_empty()).then(|| name.to_lowercase())
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extracts_hashtags() {
+        assert_eq!(
+            extract("#Rust and #rust, #db_2! issue#5 #42 #привет"),
+            vec!["rust", "db_2", "привет"]
+        );
+        assert!(extract("no tags # here").is_empty());
+    }
+
+    #[test]
+    fn parses_tag_args() {
+        assert_eq!(
+            parse_args("#Work later"),
+            Some(vec!["work".to_string(), "later".to_string()])
+        );
+        assert_eq!(parse_args("bad-tag"), None);
+        assert_eq!(parse_args(""), None);
+    }
+}
@@ -0,0 +1,215 @@
+//! In-process fake of the Telegram Bot API for end-to-end tests.
+//! Serves scripted `getUpdates` batches and records every other call the bot makes.
+//! Bodies that aren't JSON, like `sendDocument` uploads, are recorded as text.
+//! Files registered with `serve_file` can be fetched through `getFile` like from Telegram.
+
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use warp::Filter;
+
+use crate::tg_service::TgClient;
+
+const FIRST_SENT_MESSAGE_ID: i64 = 1000;
+
+#[derive(Debug, Clone)]
+pub struct RecordedCall {
+    pub method: String,
+    pub body: Value,
+}
+
+#[derive(Default)]
+struct State {
+    updates: VecDeque<Vec<Value>>,
+    calls: Vec<RecordedCall>,
+    sent_message_id: i64,
+    files: HashMap<String, Vec<u8>>,
+    /// (chat_id, user_id) of group administrators, everyone else is a plain member.
+    admins: HashSet<(i64, i64)>,
+    /// Scripted error responses by method name, each answers one call.
+    failures: HashMap<String, VecDeque<Value>>,
+}
+
+pub struct FakeBotApi {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeBotApi {
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(State {
+            sent_message_id: FIRST_SENT_MESSAGE_ID,
+            ..State::default()
+        }));
+        let files = warp::get()
+            .and(warp::path!("file" / String / "documents" / String))
+            .map({
+                let state = state.clone();
+                move |_token: String, file_id: String| {
+                    let state = state.lock().unwrap();
+                    let content = state.files.get(&file_id).cloned().unwrap_or_default();
+                    warp::http::Response::new(warp::hyper::Body::from(content))
+                }
+            });
+        let methods = warp::path::param::<String>()
+            .and(warp::path::param::<String>())
+            .and(warp::path::end())
+            .and(warp::body::bytes())
+            .map({
+                let state = state.clone();
+                move |_token: String, method: String, body: warp::hyper::body::Bytes| {
+                    let body = serde_json::from_slice(&body).unwrap_or_else(|_| {
+                        Value::String(String::from_utf8_lossy(&body).into_owned())
+                    });
+                    warp::reply::json(&Self::respond(&state, method, body))
+                }
+            });
+        let (addr, server) = warp::serve(files.or(methods)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        Self { addr, state }
+    }
+
+    pub fn client(&self) -> TgClient {
+        TgClient::with_url(format!("http://{}/bottest/", self.addr))
+    }
+
+    /// Queues one `getUpdates` response, later polls get an empty batch.
+    pub fn push_updates(&self, updates: Vec<Value>) {
+        self.state.lock().unwrap().updates.push_back(updates);
+    }
+
+    /// Makes `content` downloadable as the file `file_id`.
+    pub fn serve_file(&self, file_id: &str, content: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        state.files.insert(file_id.to_string(), content.to_vec());
+    }
+
+    /// Makes `user_id` an administrator of the group `chat_id` for `getChatMember`.
+    pub fn add_admin(&self, chat_id: i64, user_id: i64) {
+        self.state.lock().unwrap().admins.insert((chat_id, user_id));
+    }
+
+    /// Answers the next call of `method` with `response`, e.g. an `ok: false` error.
+    pub fn fail_next(&self, method: &str, response: Value) {
+        let mut state = self.state.lock().unwrap();
+        let failures = state.failures.entry(method.to_lowercase()).or_default();
+        failures.push_back(response);
+    }
+
+    pub fn calls(&self) -> Vec<RecordedCall> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// Calls of one Bot API method, method names are case-insensitive like in Telegram.
+    pub fn calls_to(&self, method: &str) -> Vec<Value> {
+        self.calls()
+            .into_iter()
+            .filter(|c| c.method.eq_ignore_ascii_case(method))
+            .map(|c| c.body)
+            .collect()
+    }
+
+    pub fn sent_texts(&self) -> Vec<String> {
+        self.calls_to("sendMessage")
+            .iter()
+            .map(|b| b["text"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    pub fn clear_calls(&self) {
+        self.state.lock().unwrap().calls.clear();
+    }
+
+    fn respond(state: &Mutex<State>, method: String, body: Value) -> Value {
+        let mut state = state.lock().unwrap();
+        if method.eq_ignore_ascii_case("getUpdates") {
+            let updates = state.updates.pop_front().unwrap_or_default();
+            return json!({ "ok": true, "result": updates });
+        }
+        state.calls.push(RecordedCall {
+            method: method.clone(),
+            body: body.clone(),
+        });
+        let failure = state
+            .failures
+            .get_mut(&method.to_lowercase())
+            .and_then(|failures| failures.pop_front());
+        if let Some(response) = failure {
+            return response;
+        }
+        if method.eq_ignore_ascii_case("sendMessage") {
+            state.sent_message_id += 1;
+            return json!({
+                "ok": true,
+                "result": {
+                    "message_id": state.sent_message_id,
+                    "text": body["text"],
+                    "chat": { "id": body["chat_id"] },
+                }
+            });
+        }
+        if method.eq_ignore_ascii_case("getFile") {
+            let file_id = body["file_id"].as_str().unwrap_or_default();
+            return json!({
+                "ok": true,
+                "result": { "file_id": file_id, "file_path": format!("documents/{}", file_id) }
+            });
+        }
+        if method.eq_ignore_ascii_case("getChatMember") {
+            let member = (body["chat_id"].as_i64(), body["user_id"].as_i64());
+            let status = match member {
+                (Some(chat_id), Some(user_id)) if state.admins.contains(&(chat_id, user_id)) => {
+                    "administrator"
+                }
+                _ => "member",
+            };
+            return json!({ "ok": true, "result": { "status": status } });
+        }
+        json!({ "ok": true, "result": true })
+    }
+}
+
+pub fn message_update(update_id: i64, chat_id: i64, message_id: i64, text: &str) -> Value {
+    json!({
+        "update_id": update_id,
+        "message": { "message_id": message_id, "text": text, "chat": { "id": chat_id } },
+    })
+}
+
+/// A document sent with `caption`, like a file upload with a command.
+pub fn document_update(
+    update_id: i64,
+    chat_id: i64,
+    message_id: i64,
+    file_id: &str,
+    caption: &str,
+) -> Value {
+    json!({
+        "update_id": update_id,
+        "message": {
+            "message_id": message_id,
+            "caption": caption,
+            "chat": { "id": chat_id },
+            "document": { "file_id": file_id, "file_name": "result.json" },
+        },
+    })
+}
+
+pub fn edited_message_update(update_id: i64, chat_id: i64, message_id: i64, text: &str) -> Value {
+    json!({
+        "update_id": update_id,
+        "edited_message": { "message_id": message_id, "text": text, "chat": { "id": chat_id } },
+    })
+}
+
+/// Button press on the bot message `message_id`.
+pub fn callback_update(update_id: i64, chat_id: i64, message_id: i64, data: &str) -> Value {
+    json!({
+        "update_id": update_id,
+        "callback_query": {
+            "data": data,
+            "message": { "message_id": message_id, "text": "", "chat": { "id": chat_id } },
+        },
+    })
+}
@@ -1,19 +1,29 @@
 use std::time::Duration;
 
-use crate::models::LinkMessage;
-use crate::{Message, Update};
+use crate::i18n;
+use crate::keyboard;
+use crate::models::{Filter, LinkMessage, Message, Update};
+use crate::pages;
+use crate::storage::Storage;
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
 use futures_core::stream::Stream;
 use futures_util::stream;
-use serde::Deserialize;
-use sqlx::PgPool;
+use reqwest::multipart::{Form, Part};
+use reqwest::Body;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
 use crate::web::{
-    DeleteMessage, InlineKeyboardMarkup, KeyboardButton, WButtons, WMessage, WUpdate, Wrapper,
+    BotCommand, BotCommandScope, DeleteMessage, EditMessageReplyMarkup, EditMessageText,
+    GetChatMember, GetFile, InlineKeyboardMarkup, MessageEntity, SendMessage, SetMyCommands,
+    WButtons, WChatMember, WFile, WMessage, WUpdate, Wrapper,
 };
 
 const CONSUMER_INTERVAL: u64 = 2;
 
+/// `api_url` is the Bot API root with a trailing slash, e.g. `https://api.telegram.org/`,
+/// `bot_id` and `bot_secret` are the two halves of the bot token.
 #[derive(Debug, Deserialize, Clone)]
 pub struct TgClientConfig {
     pub api_url: String,
@@ -28,17 +38,29 @@ pub struct TgClient {
 
 impl TgClient {
     pub fn new() -> Self {
+        Self::with_url(dotenv::var("TG").unwrap())
+    }
+
+    pub fn from_config(config: &TgClientConfig) -> Self {
+        Self::with_url(format!(
+            "{}bot{}:{}/",
+            config.api_url, config.bot_id, config.bot_secret
+        ))
+    }
+
+    /// `url` is the bot endpoint ending with a slash, e.g. `https://api.telegram.org/bot<token>/`.
+    pub fn with_url(url: String) -> Self {
         let client = reqwest::Client::builder()
             .build()
             .expect("failed to create http client");
-        Self {
-            url: dotenv::var("TG").unwrap(),
-            client,
-        }
+        Self { url, client }
     }
 
-    pub async fn get_updates(&self, pg_pool: &PgPool) -> impl Stream<Item = Result<WUpdate>> + '_ {
-        let update = Update::get_last_update(pg_pool).await;
+    pub async fn get_updates(
+        &self,
+        storage: &dyn Storage,
+    ) -> impl Stream<Item = Result<WUpdate>> + '_ {
+        let update = Update::get_last_update(storage).await;
         match update {
             Ok(upd) => {
                 let url = format!(
@@ -80,14 +102,194 @@ impl TgClient {
                     "reconnect after failure on polling pg updates: {:#?}",
                     e
                 ))];
-                return stream::iter(x);
+                stream::iter(x)
+            }
+        }
+    }
+
+    /// Calls a Bot API method and unwraps its result, `ok: false` becomes an error with the description.
+    pub async fn call<B: Serialize + ?Sized, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        body: &B,
+    ) -> Result<R> {
+        let response = self
+            .client
+            .post(format!("{}{}", self.url, method))
+            .json(body)
+            .timeout(Duration::from_secs(CONSUMER_INTERVAL))
+            .send()
+            .await?
+            .json::<Wrapper<R>>()
+            .await?;
+        unwrap_response(method, response)
+    }
+
+    /// Uploads a file named `file_name` with `sendDocument` while its `chunks` come in,
+    /// an error chunk aborts the upload.
+    pub async fn send_document<S>(
+        &self,
+        chat_id: i64,
+        file_name: String,
+        mime: &str,
+        chunks: S,
+    ) -> Result<()>
+    where
+        S: Stream<Item = std::io::Result<Vec<u8>>> + Send + Sync + 'static,
+    {
+        let document = Part::stream(Body::wrap_stream(chunks))
+            .file_name(file_name)
+            .mime_str(mime)?;
+        let form = Form::new()
+            .text("chat_id", chat_id.to_string())
+            .part("document", document);
+        let response = self
+            .client
+            .post(format!("{}sendDocument", self.url))
+            .multipart(form)
+            .send()
+            .await?
+            .json::<Wrapper<serde_json::Value>>()
+            .await?;
+        unwrap_response("sendDocument", response)?;
+        Ok(())
+    }
+
+    /// Whether `user_id` created or administers the group `chat_id`.
+    pub async fn is_chat_admin(&self, chat_id: i64, user_id: i64) -> Result<bool> {
+        let get = GetChatMember { chat_id, user_id };
+        let member: WChatMember = self.call("getChatMember", &get).await?;
+        Ok(matches!(
+            member.status.as_str(),
+            "creator" | "administrator"
+        ))
+    }
+
+    /// Downloads a file users sent to the bot, `getFile` only hands out files up to 20 MB.
+    pub async fn download_file(&self, file_id: &str) -> Result<Vec<u8>> {
+        let get_file = GetFile {
+            file_id: file_id.to_string(),
+        };
+        let file: WFile = self.call("getFile", &get_file).await?;
+        let file_path = file
+            .file_path
+            .ok_or_else(|| anyhow!("getFile returned no file_path for {}", file_id))?;
+        // Files live next to the methods: `<root>/file/bot<token>/<file_path>`.
+        let (root, bot) = self
+            .url
+            .trim_end_matches('/')
+            .rsplit_once('/')
+            .ok_or_else(|| anyhow!("unexpected bot url"))?;
+        let response = self
+            .client
+            .get(format!("{}/file/{}/{}", root, bot, file_path))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    pub async fn send_message(&self, chat_id: i64, text: String) -> Result<WMessage> {
+        self.call("sendMessage", &SendMessage::new(chat_id, text))
+            .await
+    }
+
+    pub async fn edit_message_text(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        text: String,
+        entities: Vec<MessageEntity>,
+        reply_markup: InlineKeyboardMarkup,
+    ) -> Result<()> {
+        let edit = EditMessageText::new(chat_id, message_id, text, entities, reply_markup);
+        self.call::<_, serde_json::Value>("editMessageText", &edit)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_message(&self, chat_id: i64, message_id: i64) -> Result<()> {
+        let delete_message = DeleteMessage::new(chat_id, message_id).await;
+        self.call::<_, bool>("deleteMessage", &delete_message)
+            .await?;
+        Ok(())
+    }
+
+    /// Replaces the command menu of `scope` for users of `language_code`.
+    pub async fn set_my_commands(
+        &self,
+        commands: Vec<BotCommand>,
+        scope: BotCommandScope,
+        language_code: Option<String>,
+    ) -> Result<()> {
+        let set = SetMyCommands {
+            commands,
+            scope,
+            language_code,
+        };
+        self.call::<_, bool>("setMyCommands", &set).await?;
+        Ok(())
+    }
+
+    pub async fn edit_reply_markup(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        reply_markup: InlineKeyboardMarkup,
+    ) -> Result<()> {
+        let edit = EditMessageReplyMarkup::new(chat_id, message_id, reply_markup);
+        self.call::<_, serde_json::Value>("editMessageReplyMarkup", &edit)
+            .await?;
+        Ok(())
+    }
+
+    /// Replaces the open history message of the chat, if any, with `message`.
+    pub async fn show_history_message(
+        &self,
+        storage: &dyn Storage,
+        chat_id: i64,
+        message: &Message,
+        filter: &Filter,
+        link_text: &str,
+        language: &str,
+    ) -> Result<()> {
+        let buttons =
+            TgClient::history_buttons(storage, chat_id, filter, message, language).await?;
+        self.show_history_buttons(storage, message, filter, link_text, buttons)
+            .await
+    }
+
+    /// Like `show_history_message` with `buttons` already built by `history_buttons`.
+    pub async fn show_history_buttons(
+        &self,
+        storage: &dyn Storage,
+        message: &Message,
+        filter: &Filter,
+        link_text: &str,
+        buttons: WButtons,
+    ) -> Result<()> {
+        let chat_id = message.chat_id;
+        if let Ok(link) = LinkMessage::delete_and_return_link(storage, chat_id).await {
+            if let Err(e) = self.delete_message(link.chat_id, link.id).await {
+                eprintln!("{:?}", e)
             }
         }
+        let sent: WMessage = self.call("sendMessage", &buttons).await?;
+        LinkMessage::new(
+            sent.message_id,
+            link_text.to_string(),
+            chat_id,
+            message.message_id,
+        )
+        .await
+        .with_filter(filter)
+        .insert(storage)
+        .await
     }
 
-    pub async fn exit(&self, pg_pool: &PgPool, chat_id: i64) {
+    pub async fn exit(&self, storage: &dyn Storage, chat_id: i64) {
         //delete old message and return last message
-        let current = LinkMessage::delete_and_return_link(pg_pool, chat_id).await;
+        let current = LinkMessage::delete_and_return_link(storage, chat_id).await;
         match current {
             Ok(cur) => {
                 //need to delete old message from chat
@@ -118,272 +320,177 @@ impl TgClient {
         }
     }
 
-    pub async fn next(&self, pg_pool: &PgPool, chat_id: i64) {
-        //delete old message and return last message
-        let current = LinkMessage::delete_and_return_link(pg_pool, chat_id).await;
-        match current {
-            Ok(cur) => {
-                //need to delete old message from chat
-                let deleted_message_id = cur.message_id;
-                let deleted_id = cur.id;
-                let deleted_chat_id = cur.chat_id;
-                let delete_url = format!("{}deleteMessage", self.url);
-                let consume = &self.client;
-                let delete_message = DeleteMessage::new(deleted_chat_id, deleted_id).await;
-
-                let delete_request = consume
-                    .post(delete_url)
-                    .json(&delete_message)
-                    .timeout(Duration::from_secs(CONSUMER_INTERVAL))
-                    .send()
-                    .await;
-                match delete_request {
-                    Ok(_) => {
-                        println!("Deleted")
-                    }
-                    Err(e) => {
-                        eprintln!("{:?}", e)
-                    }
-                }
+    /// Saved text under an "author · date" header, messages saved without a date have no header.
+    pub async fn history_text(storage: &dyn Storage, message: &Message, language: &str) -> String {
+        TgClient::history_header(storage, message, language).await + &message.text
+    }
 
-                let next = Message::select_next_message(chat_id, pg_pool, deleted_message_id).await;
-
-                match next {
-                    Ok(nx) => {
-                        let buttons = TgClient::create_buttons(deleted_chat_id, nx.text).await;
-
-                        let url = format!("{}SendMessage", self.url);
-                        let new_message_id = consume
-                            .post(url)
-                            .json(&buttons)
-                            .timeout(Duration::from_secs(CONSUMER_INTERVAL))
-                            .send()
-                            .await;
-                        match new_message_id {
-                            Ok(new_message_id) => {
-                                let new_message_id =
-                                    new_message_id.json::<Wrapper<WMessage>>().await;
-                                match new_message_id {
-                                    Ok(new_message_id) => {
-                                        let new_message_id = new_message_id.result;
-                                        match new_message_id {
-                                            None => {
-                                                eprintln!("No message id")
-                                            }
-                                            Some(new_message_id) => {
-                                                let new_message_id = new_message_id.message_id;
-                                                let link = LinkMessage::new(
-                                                    new_message_id,
-                                                    "next_link".to_string(),
-                                                    deleted_chat_id,
-                                                    nx.message_id,
-                                                )
-                                                .await
-                                                .insert(pg_pool)
-                                                .await;
-                                                match link {
-                                                    Ok(_) => {
-                                                        println!("message linked")
-                                                    }
-                                                    Err(e) => {
-                                                        eprintln!("{:?}", e)
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                    Err(_) => {}
-                                }
-                            }
-                            Err(e) => {
-                                eprintln!("{:?}", e)
-                            }
-                        }
-                    }
-                    _ => {}
+    /// The "author · date" line with its newline, empty for messages saved without a date.
+    pub async fn history_header(
+        storage: &dyn Storage,
+        message: &Message,
+        language: &str,
+    ) -> String {
+        let date = match DateTime::<Utc>::from_timestamp(message.date, 0) {
+            Some(date) if message.date > 0 => date.format("%Y-%m-%d %H:%M").to_string(),
+            _ => return String::new(),
+        };
+        let author = match message.from_id {
+            Some(from_id) => match storage.select_user(from_id).await {
+                Ok(user) => user.map(|u| u.display_name()),
+                Err(e) => {
+                    eprintln!("{:?}", e);
+                    None
                 }
-            }
-            _ => {}
+            },
+            None => None,
+        };
+        let edited = if message.edit_date.is_some() {
+            format!(" {}", i18n::text(language, "history-edited", &[]))
+        } else {
+            String::new()
+        };
+        match author {
+            Some(author) => format!("{} · {}{}\n", author, date, edited),
+            None => format!("{}{}\n", date, edited),
         }
     }
 
-    pub async fn last(&self, pg_pool: &PgPool, chat_id: i64) {
-        //delete old message and return last message
-        let current = LinkMessage::delete_and_return_link(pg_pool, chat_id).await;
+    pub async fn create_buttons(
+        deleted_chat_id: i64,
+        link_text: String,
+        keyboard: InlineKeyboardMarkup,
+    ) -> WButtons {
+        WButtons::new(deleted_chat_id, link_text.parse().unwrap(), keyboard)
+    }
 
-        match current {
-            Ok(cur) => {
-                //need to delete old message from chat
-                let deleted_id = cur.id;
-                let deleted_message_id = cur.message_id;
-                let deleted_chat_id = cur.chat_id;
-                let delete_url = format!("{}deleteMessage", self.url);
-                let consume = &self.client;
-                let delete_message = DeleteMessage::new(deleted_chat_id, deleted_id).await;
+    /// The browser message for `message`: the first page of its text and the history keyboard.
+    pub async fn history_buttons(
+        storage: &dyn Storage,
+        chat_id: i64,
+        filter: &Filter,
+        message: &Message,
+        language: &str,
+    ) -> Result<WButtons> {
+        let page = TgClient::history_page(storage, chat_id, filter, message, 1, language).await?;
+        let mut buttons = TgClient::create_buttons(chat_id, page.text, page.keyboard).await;
+        buttons.entities = page.entities;
+        Ok(buttons)
+    }
 
-                let delete_request = consume
-                    .post(delete_url)
-                    .json(&delete_message)
-                    .timeout(Duration::from_secs(CONSUMER_INTERVAL))
-                    .send()
-                    .await;
+    /// Page `page` (1-based, clamped) of the text of `message` with its formatting
+    /// and the keyboard to go with it.
+    pub async fn history_page(
+        storage: &dyn Storage,
+        chat_id: i64,
+        filter: &Filter,
+        message: &Message,
+        page: usize,
+        language: &str,
+    ) -> Result<HistoryPage> {
+        let header = TgClient::history_header(storage, message, language).await;
+        let shift = pages::utf16_len(&header);
+        let entities: Vec<MessageEntity> = message
+            .entities()
+            .into_iter()
+            .map(|entity| MessageEntity {
+                offset: entity.offset + shift,
+                ..entity
+            })
+            .collect();
+        let text = header + &message.text;
+        let pages = pages::split(&text, pages::MESSAGE_LIMIT);
+        let page = page.clamp(1, pages.len());
+        let start = pages[..page - 1].iter().map(|p| pages::utf16_len(p)).sum();
+        let page_text = pages[page - 1];
 
-                match delete_request {
-                    Ok(_) => {
-                        println!("Deleted")
-                    }
-                    Err(e) => {
-                        eprintln!("{:?}", e)
-                    }
-                }
+        let position = storage
+            .message_position(chat_id, filter, message.message_id)
+            .await?;
+        let total = storage.count_messages(chat_id, filter).await?;
+        let favorite = storage.is_favorite(chat_id, message.message_id).await?;
+        Ok(HistoryPage {
+            text: page_text.to_string(),
+            entities: pages::clip_entities(&entities, start, pages::utf16_len(page_text)),
+            keyboard: keyboard::history(
+                position,
+                total,
+                page,
+                pages.len(),
+                filter,
+                favorite,
+                language,
+            ),
+        })
+    }
+}
 
-                let last = Message::select_last_message(chat_id, pg_pool, deleted_message_id).await;
-
-                match last {
-                    Ok(last) => {
-                        let buttons = TgClient::create_buttons(deleted_chat_id, last.text).await;
-                        let url = format!("{}SendMessage", self.url);
-                        let new_message_id = consume
-                            .post(url)
-                            .json(&buttons)
-                            .timeout(Duration::from_secs(CONSUMER_INTERVAL))
-                            .send()
-                            .await;
-                        match new_message_id {
-                            Ok(new_message_id) => {
-                                let new_message_id =
-                                    new_message_id.json::<Wrapper<WMessage>>().await;
-                                match new_message_id {
-                                    Ok(new_message_id) => {
-                                        let new_message_id = new_message_id.result;
-                                        match new_message_id {
-                                            None => {
-                                                eprintln!("message id is None")
-                                            }
-                                            Some(new_message_id) => {
-                                                let new_message_id = new_message_id.message_id;
-                                                let link_message = LinkMessage::new(
-                                                    new_message_id,
-                                                    "last_link".to_string(),
-                                                    deleted_chat_id,
-                                                    last.message_id,
-                                                )
-                                                .await
-                                                .insert(pg_pool)
-                                                .await;
-                                                match link_message {
-                                                    Ok(_) => {
-                                                        println!("Link Set")
-                                                    }
-                                                    Err(e) => {
-                                                        eprintln!("{:?}", e)
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        eprintln!("{:?}", e)
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                eprintln!("{:?}", e)
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("{:?}", e)
-                    }
-                }
-            }
-            Err(e) => {
-                eprintln!("{:?}", e)
-            }
+fn unwrap_response<R>(method: &str, response: Wrapper<R>) -> Result<R> {
+    match response.result {
+        Some(result) if response.ok => Ok(result),
+        _ => Err(Rejected {
+            method: method.to_string(),
+            error_code: response.error_code,
+            description: response.description,
+            retry_after: response
+                .parameters
+                .as_ref()
+                .and_then(|p| p["retry_after"].as_u64()),
         }
+        .into()),
     }
+}
 
-    pub async fn history(&self, pg_pool: &PgPool, chat_id: i64) {
-        let first_message_from_history =
-            Message::select_first_user_message_by_chat_id(chat_id, pg_pool).await;
-        match first_message_from_history {
-            Ok(first_message_from_history) => {
-                let url = format!("{}SendMessage", self.url);
-                let buttons =
-                    TgClient::create_buttons(chat_id, first_message_from_history.text).await;
-                let consumer = &self.client;
-                match consumer
-                    .post(url)
-                    .json(&buttons)
-                    .timeout(Duration::from_secs(CONSUMER_INTERVAL))
-                    .send()
-                    .await
-                {
-                    Ok(rs) if rs.status().is_success() => {
-                        let val = rs
-                            .json::<Wrapper<WMessage>>()
-                            .await
-                            .unwrap()
-                            .result
-                            .unwrap()
-                            .message_id;
-                        let linked_message = LinkMessage::new(
-                            val,
-                            "link".parse().unwrap(),
-                            chat_id,
-                            first_message_from_history.message_id,
-                        )
-                        .await
-                        .insert(pg_pool)
-                        .await;
-
-                        match linked_message {
-                            Ok(_) => {
-                                println!("Linked message send")
-                            }
-                            Err(e) => {
-                                eprintln!("{:?}", e)
-                            }
-                        }
-                    }
-                    bad_res => match bad_res {
-                        Ok(x) => println!("{:?}", x),
-                        Err(e) => println!("{:?}", e),
-                    },
-                };
-            }
-            Err(e) => {
-                eprintln!("{:?}", e)
-            }
-        }
+/// Telegram answered a call with `ok: false`.
+#[derive(Debug)]
+pub struct Rejected {
+    pub method: String,
+    pub error_code: Option<i64>,
+    pub description: Option<String>,
+    /// Seconds to wait when Telegram limits the rate of calls.
+    pub retry_after: Option<u64>,
+}
+
+impl Rejected {
+    /// Too many requests or a failure on the side of Telegram, the same call may work later.
+    pub fn is_temporary(&self) -> bool {
+        matches!(self.error_code, Some(429) | Some(500..=599))
     }
+}
 
-    pub async fn create_buttons(deleted_chat_id: i64, link_text: String) -> WButtons {
-        let buttons = WButtons::new(
-            deleted_chat_id,
-            link_text.parse().unwrap(),
-            InlineKeyboardMarkup::new(vec![vec![
-                KeyboardButton::new("next".to_string(), "/next".to_string()),
-                KeyboardButton::new("last".to_string(), "/last".to_string()),
-            ]]),
-        );
-        return buttons;
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} failed: {:?} {:?}",
+            self.method, self.error_code, self.description
+        )
     }
 }
 
-#[cfg(test)]
-mod test {
-    use futures_util::stream::StreamExt;
+impl std::error::Error for Rejected {}
 
-    use super::*;
+/// Whether the error is Telegram refusing a call for good, rather than a network failure,
+/// a rate limit or an error of Telegram that retrying may get past.
+pub fn is_rejected(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<Rejected>()
+        .is_some_and(|rejected| !rejected.is_temporary())
+}
 
-    fn tg_client() -> TgClient {
-        TgClient::new()
-    }
+/// Seconds Telegram asked to wait before the next call, see `Rejected::retry_after`.
+pub fn retry_after(e: &anyhow::Error) -> Option<u64> {
+    e.downcast_ref::<Rejected>()?.retry_after
+}
+
+/// One page of a saved message as the history browser shows it.
+pub struct HistoryPage {
+    pub text: String,
+    pub entities: Vec<MessageEntity>,
+    pub keyboard: InlineKeyboardMarkup,
+}
 
-    #[test]
-    fn synchronous_test_example() {
-        tg_client();
-        assert!(true);
+impl Default for TgClient {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -6,8 +6,37 @@ use serde_json::Value;
 #[serde(rename_all = "snake_case")]
 pub struct WMessage {
     pub message_id: i64,
+    #[serde(default)]
     pub text: String,
     pub chat: WChat,
+    #[serde(default)]
+    pub date: i64,
+    pub edit_date: Option<i64>,
+    pub from: Option<WUser>,
+    #[serde(default)]
+    pub entities: Vec<MessageEntity>,
+    pub reply_to_message: Option<Box<WMessage>>,
+    /// Text that comes with a document or a photo, `text` is empty then.
+    pub caption: Option<String>,
+    pub document: Option<WDocument>,
+}
+
+/// https://core.telegram.org/bots/api#document
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct WDocument {
+    pub file_id: String,
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+    pub file_size: Option<i64>,
+}
+
+/// https://core.telegram.org/bots/api#file
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct WFile {
+    pub file_id: String,
+    pub file_path: Option<String>,
 }
 
 /// https://core.telegram.org/bots/api#message
@@ -16,7 +45,31 @@ pub struct WMessage {
 #[serde(rename(serialize = "edited_message", deserialize = "edited_message"))]
 pub struct WEditedMessage {
     pub message_id: i64,
+    #[serde(default)]
     pub text: String,
+    pub chat: WChat,
+    pub edit_date: Option<i64>,
+    #[serde(default)]
+    pub entities: Vec<MessageEntity>,
+}
+
+/// https://core.telegram.org/bots/api#messageentity
+/// `offset` and `length` are in UTF-16 code units of the text.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct MessageEntity {
+    #[serde(rename = "type")]
+    pub entity_type: String,
+    pub offset: usize,
+    pub length: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<WUser>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom_emoji_id: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -25,12 +78,32 @@ pub struct WEditedMessage {
 pub struct WCallbackQuery {
     pub data: String,
     pub message: WMessage,
+    pub from: Option<WUser>,
 }
 
+/// https://core.telegram.org/bots/api#chat
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(rename(serialize = "chat", deserialize = "chat"))]
 pub struct WChat {
     pub id: i64,
+    #[serde(rename = "type", default)]
+    pub chat_type: String,
+    pub title: Option<String>,
+    pub username: Option<String>,
+}
+
+/// https://core.telegram.org/bots/api#user
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct WUser {
+    pub id: i64,
+    #[serde(default)]
+    pub is_bot: bool,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+    /// IETF tag of the user's Telegram app language, e.g. `en` or `pt-br`.
+    pub language_code: Option<String>,
 }
 
 /// https://core.telegram.org/bots/api#update
@@ -44,6 +117,32 @@ pub struct WUpdate {
     pub edited_message: Option<WEditedMessage>,
 }
 
+impl WUpdate {
+    pub fn chat_id(&self) -> Option<i64> {
+        if let Some(wm) = &self.message {
+            return Some(wm.chat.id);
+        }
+        if let Some(wem) = &self.edited_message {
+            return Some(wem.chat.id);
+        }
+        self.callback_query.as_ref().map(|wc| wc.message.chat.id)
+    }
+
+    /// Who sent the message or pressed the button.
+    pub fn user_id(&self) -> Option<i64> {
+        self.user().map(|u| u.id)
+    }
+
+    /// `from` of the message or of the callback query.
+    pub fn user(&self) -> Option<&WUser> {
+        match (&self.message, &self.callback_query) {
+            (Some(wm), _) => wm.from.as_ref(),
+            (None, Some(wc)) => wc.from.as_ref(),
+            (None, None) => None,
+        }
+    }
+}
+
 /// https://core.telegram.org/bots/api#making-requests
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
@@ -83,6 +182,81 @@ impl KeyboardButton {
     }
 }
 
+/// https://core.telegram.org/bots/api#sendmessage
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct SendMessage {
+    pub chat_id: i64,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to_message_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_sending_without_reply: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_notification: Option<bool>,
+}
+
+impl SendMessage {
+    pub fn new(chat_id: i64, text: String) -> Self {
+        Self {
+            chat_id,
+            text,
+            reply_to_message_id: None,
+            allow_sending_without_reply: None,
+            disable_notification: None,
+        }
+    }
+}
+
+/// https://core.telegram.org/bots/api#editmessagetext
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct EditMessageText {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entities: Vec<MessageEntity>,
+    pub reply_markup: InlineKeyboardMarkup,
+}
+
+impl EditMessageText {
+    pub fn new(
+        chat_id: i64,
+        message_id: i64,
+        text: String,
+        entities: Vec<MessageEntity>,
+        reply_markup: InlineKeyboardMarkup,
+    ) -> Self {
+        Self {
+            chat_id,
+            message_id,
+            text,
+            entities,
+            reply_markup,
+        }
+    }
+}
+
+/// https://core.telegram.org/bots/api#editmessagereplymarkup
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct EditMessageReplyMarkup {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub reply_markup: InlineKeyboardMarkup,
+}
+
+impl EditMessageReplyMarkup {
+    pub fn new(chat_id: i64, message_id: i64, reply_markup: InlineKeyboardMarkup) -> Self {
+        Self {
+            chat_id,
+            message_id,
+            reply_markup,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct DeleteMessage {
@@ -90,6 +264,29 @@ pub struct DeleteMessage {
     pub message_id: i64,
 }
 
+/// https://core.telegram.org/bots/api#getfile
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct GetFile {
+    pub file_id: String,
+}
+
+/// https://core.telegram.org/bots/api#getchatmember
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct GetChatMember {
+    pub chat_id: i64,
+    pub user_id: i64,
+}
+
+/// https://core.telegram.org/bots/api#chatmember
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct WChatMember {
+    /// `creator`, `administrator`, `member`, `restricted`, `left` or `kicked`.
+    pub status: String,
+}
+
 impl DeleteMessage {
     pub async fn new(chat_id: i64, message_id: i64) -> Self {
         Self {
@@ -104,6 +301,8 @@ impl DeleteMessage {
 pub struct WButtons {
     pub chat_id: i64,
     pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entities: Vec<MessageEntity>,
     pub reply_markup: InlineKeyboardMarkup,
 }
 
@@ -112,7 +311,37 @@ impl WButtons {
         Self {
             chat_id,
             text,
+            entities: Vec::new(),
             reply_markup,
         }
     }
 }
+
+/// https://core.telegram.org/bots/api#botcommand
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct BotCommand {
+    /// Without the leading slash.
+    pub command: String,
+    pub description: String,
+}
+
+/// https://core.telegram.org/bots/api#botcommandscope
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotCommandScope {
+    AllPrivateChats,
+    AllGroupChats,
+    AllChatAdministrators,
+}
+
+/// https://core.telegram.org/bots/api#setmycommands
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct SetMyCommands {
+    pub commands: Vec<BotCommand>,
+    pub scope: BotCommandScope,
+    /// Users whose language has no list of its own get the one without a language.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_code: Option<String>,
+}
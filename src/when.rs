@@ -0,0 +1,816 @@
+//! Dates and times as people write them, in English and Russian: "in 2 hours", "tomorrow 9am",
+//! "next monday", "3 days ago", "через час", "в пятницу в 18:00", "23 июня", ISO dates.
+//! `/remind` reads when to remind, `/history` which day to open.
+//!
+//! Words are read from the start of the text as long as they make sense as a date or a time,
+//! in any order ("9am tomorrow", "завтра в 9"), the rest is left to the caller. Clock times
+//! are in the user's timezone.
+
+use chrono::{
+    DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike,
+    Utc, Weekday,
+};
+use chrono_tz::Tz;
+
+/// Which way a weekday, a time or a date without a year points when the text doesn't say:
+/// reminders want the coming one, the history the one that has passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Future,
+    Past,
+}
+
+/// Dates without a time are at this hour.
+pub const DEFAULT_HOUR: u32 = 9;
+
+const MORNING: u32 = 9;
+const AFTERNOON: u32 = 14;
+const EVENING: u32 = 19;
+
+/// Reads a moment from the start of `text`, returns it with the rest of the text.
+pub fn parse(
+    text: &str,
+    now: DateTime<Utc>,
+    tz: Tz,
+    direction: Direction,
+) -> Option<(DateTime<Utc>, &str)> {
+    let words = words(text);
+    let (found, next) = scan(&words)?;
+    let rest = words.get(next).map_or("", |w| &text[w.start..]);
+    let at = match found.delay {
+        Some(delay) => delay.apply(now)?,
+        None => {
+            let local = resolve(&found, now.with_timezone(&tz).naive_local(), direction)?;
+            to_utc(local, tz)?
+        }
+    };
+    Some((at, rest))
+}
+
+/// The day `text` names as a whole, looking back: "yesterday", "monday", "2022-06-23".
+pub fn parse_day(text: &str, now: DateTime<Utc>, tz: Tz) -> Option<NaiveDate> {
+    match parse(text, now, tz, Direction::Past)? {
+        (at, "") => Some(at.with_timezone(&tz).date_naive()),
+        _ => None,
+    }
+}
+
+/// Unix seconds of the start of the day in `tz`.
+pub fn day_start(date: NaiveDate, tz: Tz) -> i64 {
+    to_utc(date.and_time(NaiveTime::MIN), tz)
+        .map_or_else(|| crate::calendar::day_start(date), |at| at.timestamp())
+}
+
+/// `2022-06-23 21:00 MSK`, what the user sees of a moment.
+pub fn human_date(timestamp: i64, tz: Tz) -> String {
+    match Utc.timestamp_opt(timestamp, 0).single() {
+        Some(at) => at
+            .with_timezone(&tz)
+            .format("%Y-%m-%d %H:%M %Z")
+            .to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+/// A local time that falls into a DST gap is moved an hour on.
+fn to_utc(local: NaiveDateTime, tz: Tz) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(local + Duration::hours(1)))
+                .earliest()
+        })
+        .map(|at| at.with_timezone(&Utc))
+}
+
+/// A word of the text lowercased, without a trailing comma and with `ё` read as `е`.
+struct Word {
+    /// Byte offset in the text.
+    start: usize,
+    lower: String,
+}
+
+fn words(text: &str) -> Vec<Word> {
+    text.split_whitespace()
+        .map(|word| Word {
+            start: word.as_ptr() as usize - text.as_ptr() as usize,
+            lower: word.to_lowercase().trim_end_matches(',').replace('ё', "е"),
+        })
+        .collect()
+}
+
+fn word(words: &[Word], i: usize) -> Option<&str> {
+    words.get(i).map(|w| w.lower.as_str())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Delay {
+    count: i64,
+    unit: Unit,
+}
+
+impl Delay {
+    /// `None` when the count is too big for a date, "in 99999999 weeks".
+    fn apply(self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let count = self.count;
+        let duration = match self.unit {
+            Unit::Minute => Duration::try_minutes(count)?,
+            Unit::Hour => Duration::try_hours(count)?,
+            Unit::Day => Duration::try_days(count)?,
+            Unit::Week => Duration::try_weeks(count)?,
+            Unit::Month => {
+                let months = Months::new(u32::try_from(count.unsigned_abs()).ok()?);
+                return if count >= 0 {
+                    now.checked_add_months(months)
+                } else {
+                    now.checked_sub_months(months)
+                };
+            }
+        };
+        now.checked_add_signed(duration)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Day {
+    Date(NaiveDate),
+    /// Days from today: 1 is tomorrow, -1 yesterday.
+    Relative(i64),
+    /// `None` follows the direction of the caller.
+    Weekday(Weekday, Option<Direction>),
+    /// The weekday of the current week, "this friday".
+    ThisWeek(Weekday),
+    /// Day and month, the year follows the direction.
+    OfMonth(u32, u32),
+}
+
+#[derive(Debug, Default)]
+struct Found {
+    delay: Option<Delay>,
+    day: Option<Day>,
+    time: Option<NaiveTime>,
+    /// Hour of "morning", "вечером" and the like.
+    part: Option<u32>,
+}
+
+impl Found {
+    fn day(day: Day) -> Self {
+        Found {
+            day: Some(day),
+            ..Found::default()
+        }
+    }
+
+    fn time(time: NaiveTime) -> Self {
+        Found {
+            time: Some(time),
+            ..Found::default()
+        }
+    }
+
+    fn part(hour: u32) -> Self {
+        Found {
+            part: Some(hour),
+            ..Found::default()
+        }
+    }
+
+    /// Whether `other` only adds what is missing.
+    fn accepts(&self, other: &Found) -> bool {
+        !(self.day.is_some() && other.day.is_some()
+            || self.time.is_some() && other.time.is_some()
+            || self.part.is_some() && other.part.is_some())
+    }
+
+    fn merge(&mut self, other: Found) {
+        self.day = self.day.or(other.day);
+        self.time = self.time.or(other.time);
+        self.part = self.part.or(other.part);
+    }
+}
+
+type Step = Option<(Found, usize)>;
+
+/// What the words starting the text say and the index of the first word left over.
+fn scan(words: &[Word]) -> Step {
+    if let Some((delay, next)) = delay(words) {
+        let found = Found {
+            delay: Some(delay),
+            ..Found::default()
+        };
+        return Some((found, next));
+    }
+    let mut found = Found::default();
+    let mut i = 0;
+    loop {
+        let step = [date(words, i), time(words, i), part_of_day(words, i)]
+            .into_iter()
+            .flatten()
+            .find(|(step, _)| found.accepts(step));
+        match step {
+            Some((step, next)) => {
+                found.merge(step);
+                i = next;
+            }
+            None => break,
+        }
+    }
+    (i > 0).then_some((found, i))
+}
+
+/// `2h`, `in 2 hours`, `in half an hour`, `через час`, `через 3 дня`, `a week ago`, `2 дня назад`.
+fn delay(words: &[Word]) -> Option<(Delay, usize)> {
+    let first = word(words, 0)?;
+    if let Some(delay) = compact_delay(first) {
+        return Some((delay, 1));
+    }
+    let half_hour = Delay {
+        count: 30,
+        unit: Unit::Minute,
+    };
+    if matches!(first, "in" | "через") {
+        match (word(words, 1), word(words, 2), word(words, 3)) {
+            (Some("полчаса"), _, _) => return Some((half_hour, 2)),
+            (Some("half"), Some("an" | "a"), Some("hour")) => return Some((half_hour, 4)),
+            _ => {}
+        }
+        let (count, i) = count(words, 1);
+        let unit = unit(word(words, i)?)?;
+        return Some((Delay { count, unit }, i + 1));
+    }
+    let (count, i) = count(words, 0);
+    let unit = unit(word(words, i)?)?;
+    match word(words, i + 1)? {
+        "ago" | "назад" => Some((
+            Delay {
+                count: -count,
+                unit,
+            },
+            i + 2,
+        )),
+        _ => None,
+    }
+}
+
+/// A number, "a" or nothing at all as in "через час", and the index after it.
+fn count(words: &[Word], i: usize) -> (i64, usize) {
+    match word(words, i) {
+        Some("a" | "an" | "one") => (1, i + 1),
+        Some(w) => match w.parse::<i64>() {
+            Ok(n) if n > 0 => (n, i + 1),
+            _ => (1, i),
+        },
+        None => (1, i),
+    }
+}
+
+fn compact_delay(word: &str) -> Option<Delay> {
+    let digits = word.find(|c: char| !c.is_ascii_digit())?;
+    let count: i64 = word[..digits].parse().ok().filter(|&n| n > 0)?;
+    let unit = match &word[digits..] {
+        "m" | "min" | "мин" => Unit::Minute,
+        "h" | "ч" => Unit::Hour,
+        "d" | "д" => Unit::Day,
+        "w" => Unit::Week,
+        _ => return None,
+    };
+    Some(Delay { count, unit })
+}
+
+fn unit(word: &str) -> Option<Unit> {
+    let unit = match word {
+        "min" | "mins" | "minute" | "minutes" | "мин" | "минуту" | "минута" | "минуты"
+        | "минут" => Unit::Minute,
+        "hour" | "hours" | "hr" | "hrs" | "час" | "часа" | "часов" => Unit::Hour,
+        "day" | "days" | "день" | "дня" | "дней" | "сутки" => Unit::Day,
+        "week" | "weeks" | "неделю" | "неделя" | "недели" | "недель" => {
+            Unit::Week
+        }
+        "month" | "months" | "месяц" | "месяца" | "месяцев" => Unit::Month,
+        _ => return None,
+    };
+    Some(unit)
+}
+
+fn date(words: &[Word], i: usize) -> Step {
+    let first = word(words, i)?;
+    if matches!(first, "on" | "в" | "во") {
+        return date(words, i + 1);
+    }
+    let relative = match first {
+        "today" | "сегодня" => Some(0),
+        "tomorrow" | "завтра" => Some(1),
+        "послезавтра" => Some(2),
+        "yesterday" | "вчера" => Some(-1),
+        "позавчера" => Some(-2),
+        _ => None,
+    };
+    if let Some(days) = relative {
+        return Some((Found::day(Day::Relative(days)), i + 1));
+    }
+    if first == "tonight" {
+        let found = Found {
+            day: Some(Day::Relative(0)),
+            part: Some(EVENING),
+            ..Found::default()
+        };
+        return Some((found, i + 1));
+    }
+    if first == "day" {
+        let days = match (word(words, i + 1), word(words, i + 2)) {
+            (Some("after"), Some("tomorrow")) => 2,
+            (Some("before"), Some("yesterday")) => -2,
+            _ => return None,
+        };
+        return Some((Found::day(Day::Relative(days)), i + 3));
+    }
+    let this_week = matches!(first, "this" | "этот" | "эту" | "это" | "эта");
+    let direction = match first {
+        "next" | "следующий" | "следующую" | "следующее" | "следующая" => {
+            Some(Direction::Future)
+        }
+        "last" | "прошлый" | "прошлую" | "прошлое" | "прошлая" => {
+            Some(Direction::Past)
+        }
+        _ => None,
+    };
+    if this_week || direction.is_some() {
+        let weekday = weekday(word(words, i + 1)?)?;
+        let day = match direction {
+            Some(_) => Day::Weekday(weekday, direction),
+            None => Day::ThisWeek(weekday),
+        };
+        return Some((Found::day(day), i + 2));
+    }
+    if let Some(weekday) = weekday(first) {
+        return Some((Found::day(Day::Weekday(weekday, None)), i + 1));
+    }
+    if let Ok(at) = NaiveDateTime::parse_from_str(first, "%Y-%m-%dt%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(first, "%Y-%m-%dt%H:%M:%S"))
+    {
+        let found = Found {
+            day: Some(Day::Date(at.date())),
+            time: Some(at.time()),
+            ..Found::default()
+        };
+        return Some((found, i + 1));
+    }
+    if let Some(date) = crate::calendar::parse_date(first) {
+        return Some((Found::day(Day::Date(date)), i + 1));
+    }
+    if let Some((day, month)) = first.split_once('.') {
+        if let (Some(day), Ok(month @ 1..=12)) = (day_number(day), month.parse::<u32>()) {
+            return Some((Found::day(Day::OfMonth(day, month)), i + 1));
+        }
+    }
+    // "23 june 2022", "23 июня", "june 23"
+    let (day, month) = match (day_number(first), word(words, i + 1)) {
+        (Some(day), Some(next)) => (day, month(next)?),
+        _ => (word(words, i + 1).and_then(day_number)?, month(first)?),
+    };
+    let year = word(words, i + 2)
+        .and_then(|w| w.parse::<i32>().ok())
+        .filter(|year| (1970..=2100).contains(year));
+    let found = match year {
+        Some(year) => Found::day(Day::Date(NaiveDate::from_ymd_opt(year, month, day)?)),
+        None => Found::day(Day::OfMonth(day, month)),
+    };
+    Some((found, i + 2 + usize::from(year.is_some())))
+}
+
+/// `23`, `23rd`, `1st`.
+fn day_number(word: &str) -> Option<u32> {
+    let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let suffix = &word[digits.len()..];
+    if !matches!(suffix, "" | "st" | "nd" | "rd" | "th") {
+        return None;
+    }
+    digits.parse().ok().filter(|day| (1..=31).contains(day))
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+    let weekday = match word {
+        "monday" | "mon" | "понедельник" => Weekday::Mon,
+        "tuesday" | "tue" | "вторник" => Weekday::Tue,
+        "wednesday" | "wed" | "среда" | "среду" => Weekday::Wed,
+        "thursday" | "thu" | "четверг" => Weekday::Thu,
+        "friday" | "fri" | "пятница" | "пятницу" => Weekday::Fri,
+        "saturday" | "sat" | "суббота" | "субботу" => Weekday::Sat,
+        "sunday" | "sun" | "воскресенье" => Weekday::Sun,
+        _ => return None,
+    };
+    Some(weekday)
+}
+
+fn month(word: &str) -> Option<u32> {
+    const EN: [&str; 12] = [
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ];
+    const RU: [&str; 12] = [
+        "января",
+        "февраля",
+        "марта",
+        "апреля",
+        "мая",
+        "июня",
+        "июля",
+        "августа",
+        "сентября",
+        "октября",
+        "ноября",
+        "декабря",
+    ];
+    let index = EN
+        .iter()
+        .position(|m| *m == word || word.len() == 3 && m.starts_with(word))
+        .or_else(|| RU.iter().position(|m| *m == word))?;
+    Some(index as u32 + 1)
+}
+
+/// `18:00`, `9am`, `9:30 pm`, `at 9`, `noon`, `в 9`, `в 7 вечера`, `в полдень`.
+/// A bare hour needs `at` or `в` in front or am/pm after it.
+fn time(words: &[Word], i: usize) -> Step {
+    let prefixed = matches!(word(words, i)?, "at" | "в");
+    let i = if prefixed { i + 1 } else { i };
+    let first = word(words, i)?;
+    match first {
+        "noon" | "полдень" => {
+            return Some((Found::time(NaiveTime::from_hms_opt(12, 0, 0)?), i + 1))
+        }
+        "midnight" | "полночь" => return Some((Found::time(NaiveTime::MIN), i + 1)),
+        _ => {}
+    }
+    let split = first
+        .find(|c: char| !c.is_ascii_digit() && c != ':')
+        .unwrap_or(first.len());
+    let (clock, attached) = first.split_at(split);
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) if minute.len() == 2 => {
+            (hour.parse().ok()?, Some(minute.parse().ok()?))
+        }
+        Some(_) => return None,
+        None => (clock.parse::<u32>().ok()?, None),
+    };
+    let (suffix, next) = match (attached, word(words, i + 1)) {
+        ("", Some(next)) if half_of_day(next).is_some() || o_clock(next) => (next, i + 2),
+        ("", _) => ("", i + 1),
+        (attached, _) => (attached, i + 1),
+    };
+    let hour = match half_of_day(suffix) {
+        Some(pm) if (1..=12).contains(&hour) => hour % 12 + if pm { 12 } else { 0 },
+        Some(_) => return None,
+        None if !suffix.is_empty() && !o_clock(suffix) => return None,
+        None if minute.is_none() && !prefixed => return None,
+        None => hour,
+    };
+    let time = NaiveTime::from_hms_opt(hour, minute.unwrap_or(0), 0)?;
+    Some((Found::time(time), next))
+}
+
+/// `Some(true)` for words after an afternoon hour, `Some(false)` for a morning one.
+fn half_of_day(word: &str) -> Option<bool> {
+    match word {
+        "am" | "a.m." | "утра" | "ночи" => Some(false),
+        "pm" | "p.m." | "дня" | "вечера" => Some(true),
+        _ => None,
+    }
+}
+
+fn o_clock(word: &str) -> bool {
+    matches!(word, "o'clock" | "час" | "часа" | "часов")
+}
+
+/// `morning`, `in the evening`, `утром`, `вечером`.
+fn part_of_day(words: &[Word], i: usize) -> Step {
+    let (first, next) = match (word(words, i)?, word(words, i + 1)) {
+        ("in", Some("the")) => (word(words, i + 2)?, i + 3),
+        (first, _) => (first, i + 1),
+    };
+    let hour = match first {
+        "morning" | "утром" => MORNING,
+        "afternoon" | "днем" => AFTERNOON,
+        "evening" | "вечером" => EVENING,
+        _ => return None,
+    };
+    Some((Found::part(hour), next))
+}
+
+fn resolve(found: &Found, now: NaiveDateTime, direction: Direction) -> Option<NaiveDateTime> {
+    let today = now.date();
+    let time = match (found.time, found.part) {
+        (Some(time), Some(part)) if time.hour() < 12 && part >= 12 => {
+            Some(time + Duration::hours(12))
+        }
+        (Some(time), _) => Some(time),
+        (None, Some(part)) => NaiveTime::from_hms_opt(part, 0, 0),
+        (None, None) => None,
+    };
+    let day = match found.day {
+        Some(day) => day,
+        None => {
+            let at = today.and_time(time?);
+            return Some(match direction {
+                Direction::Future if at <= now => at + Duration::days(1),
+                Direction::Past if at > now => at - Duration::days(1),
+                _ => at,
+            });
+        }
+    };
+    let date = match day {
+        Day::Date(date) => date,
+        Day::Relative(days) => today + Duration::days(days),
+        Day::Weekday(weekday, forced) => {
+            let ahead = (weekday.num_days_from_monday() as i64
+                - today.weekday().num_days_from_monday() as i64)
+                .rem_euclid(7);
+            match forced.unwrap_or(direction) {
+                Direction::Future if ahead == 0 => today + Duration::days(7),
+                Direction::Future => today + Duration::days(ahead),
+                Direction::Past => today - Duration::days(7 - ahead),
+            }
+        }
+        Day::ThisWeek(weekday) => {
+            today - Duration::days(today.weekday().num_days_from_monday() as i64)
+                + Duration::days(weekday.num_days_from_monday() as i64)
+        }
+        Day::OfMonth(day, month) => {
+            let date = NaiveDate::from_ymd_opt(today.year(), month, day)?;
+            match direction {
+                Direction::Future if date < today => date.with_year(today.year() + 1)?,
+                Direction::Past if date > today => date.with_year(today.year() - 1)?,
+                _ => date,
+            }
+        }
+    };
+    let time = time.or_else(|| NaiveTime::from_hms_opt(DEFAULT_HOUR, 0, 0))?;
+    Some(date.and_time(time))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono_tz::Europe::Moscow;
+
+    /// Thursday, 2022-06-23 12:00 UTC.
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2022, 6, 23, 12, 0, 0).unwrap()
+    }
+
+    fn at(text: &str, direction: Direction) -> Option<(String, &str)> {
+        parse(text, now(), chrono_tz::UTC, direction)
+            .map(|(at, rest)| (at.format("%Y-%m-%d %H:%M").to_string(), rest))
+    }
+
+    fn future(text: &str) -> Option<String> {
+        at(text, Direction::Future).map(|(at, _)| at)
+    }
+
+    fn past(text: &str) -> Option<String> {
+        at(text, Direction::Past).map(|(at, _)| at)
+    }
+
+    fn some(at: &str) -> Option<String> {
+        Some(at.to_string())
+    }
+
+    #[test]
+    fn reads_delays() {
+        assert_eq!(future("30m"), some("2022-06-23 12:30"));
+        assert_eq!(future("2h"), some("2022-06-23 14:00"));
+        assert_eq!(future("1w"), some("2022-06-30 12:00"));
+        assert_eq!(future("in 2 hours"), some("2022-06-23 14:00"));
+        assert_eq!(future("in an hour"), some("2022-06-23 13:00"));
+        assert_eq!(future("in 45 minutes"), some("2022-06-23 12:45"));
+        assert_eq!(future("in half an hour"), some("2022-06-23 12:30"));
+        assert_eq!(future("in 3 days"), some("2022-06-26 12:00"));
+        assert_eq!(future("in a week"), some("2022-06-30 12:00"));
+        assert_eq!(future("in 2 months"), some("2022-08-23 12:00"));
+        assert_eq!(future("in 0 hours"), None);
+        assert_eq!(future("in 2 apples"), None);
+    }
+
+    #[test]
+    fn rejects_huge_delays() {
+        assert_eq!(future("in 99999999 weeks call Ann"), None);
+        assert_eq!(future("9999999999999h call Ann"), None);
+        assert_eq!(future("in 9223372036854775807 minutes"), None);
+        // would wrap around to a single month as u32
+        assert_eq!(future("in 4294967297 months"), None);
+        assert_eq!(past("99999999999 days ago"), None);
+    }
+
+    #[test]
+    fn reads_russian_delays() {
+        assert_eq!(future("через час"), some("2022-06-23 13:00"));
+        assert_eq!(future("через 2 часа"), some("2022-06-23 14:00"));
+        assert_eq!(future("через 5 часов"), some("2022-06-23 17:00"));
+        assert_eq!(future("через 10 минут"), some("2022-06-23 12:10"));
+        assert_eq!(future("через минуту"), some("2022-06-23 12:01"));
+        assert_eq!(future("через полчаса"), some("2022-06-23 12:30"));
+        assert_eq!(future("через 3 дня"), some("2022-06-26 12:00"));
+        assert_eq!(future("через неделю"), some("2022-06-30 12:00"));
+        assert_eq!(future("через месяц"), some("2022-07-23 12:00"));
+        assert_eq!(future("2ч"), some("2022-06-23 14:00"));
+    }
+
+    #[test]
+    fn reads_days_ago() {
+        assert_eq!(past("3 days ago"), some("2022-06-20 12:00"));
+        assert_eq!(past("a week ago"), some("2022-06-16 12:00"));
+        assert_eq!(past("2 дня назад"), some("2022-06-21 12:00"));
+        assert_eq!(past("неделю назад"), some("2022-06-16 12:00"));
+        assert_eq!(past("месяц назад"), some("2022-05-23 12:00"));
+    }
+
+    #[test]
+    fn reads_relative_days() {
+        assert_eq!(future("today 18:00"), some("2022-06-23 18:00"));
+        assert_eq!(future("tomorrow"), some("2022-06-24 09:00"));
+        assert_eq!(future("tomorrow 9am"), some("2022-06-24 09:00"));
+        assert_eq!(future("9am tomorrow"), some("2022-06-24 09:00"));
+        assert_eq!(future("tomorrow at 18:30"), some("2022-06-24 18:30"));
+        assert_eq!(future("day after tomorrow"), some("2022-06-25 09:00"));
+        assert_eq!(past("yesterday"), some("2022-06-22 09:00"));
+        assert_eq!(past("day before yesterday"), some("2022-06-21 09:00"));
+        assert_eq!(future("tonight"), some("2022-06-23 19:00"));
+        assert_eq!(future("tomorrow morning"), some("2022-06-24 09:00"));
+        assert_eq!(future("tomorrow evening at 7"), some("2022-06-24 19:00"));
+        assert_eq!(
+            future("tomorrow in the afternoon"),
+            some("2022-06-24 14:00")
+        );
+    }
+
+    #[test]
+    fn reads_russian_relative_days() {
+        assert_eq!(future("сегодня в 18:00"), some("2022-06-23 18:00"));
+        assert_eq!(future("завтра"), some("2022-06-24 09:00"));
+        assert_eq!(future("завтра в 10"), some("2022-06-24 10:00"));
+        assert_eq!(future("завтра в 7 вечера"), some("2022-06-24 19:00"));
+        assert_eq!(future("завтра в 3 дня"), some("2022-06-24 15:00"));
+        assert_eq!(future("завтра в 2 ночи"), some("2022-06-24 02:00"));
+        assert_eq!(future("завтра утром"), some("2022-06-24 09:00"));
+        assert_eq!(future("завтра вечером"), some("2022-06-24 19:00"));
+        assert_eq!(future("Завтра днём"), some("2022-06-24 14:00"));
+        assert_eq!(future("послезавтра в полдень"), some("2022-06-25 12:00"));
+        assert_eq!(past("вчера"), some("2022-06-22 09:00"));
+        assert_eq!(past("позавчера"), some("2022-06-21 09:00"));
+        assert_eq!(future("в 9 часов"), some("2022-06-24 09:00"));
+    }
+
+    #[test]
+    fn reads_weekdays() {
+        assert_eq!(future("monday"), some("2022-06-27 09:00"));
+        assert_eq!(future("next monday"), some("2022-06-27 09:00"));
+        assert_eq!(future("on friday at 5pm"), some("2022-06-24 17:00"));
+        assert_eq!(future("thursday"), some("2022-06-30 09:00"));
+        assert_eq!(past("monday"), some("2022-06-20 09:00"));
+        assert_eq!(past("thursday"), some("2022-06-16 09:00"));
+        assert_eq!(future("last monday"), some("2022-06-20 09:00"));
+        assert_eq!(past("next sat"), some("2022-06-25 09:00"));
+        assert_eq!(future("this tuesday"), some("2022-06-21 09:00"));
+        assert_eq!(future("в понедельник"), some("2022-06-27 09:00"));
+        assert_eq!(
+            future("в следующую среду в 10:30"),
+            some("2022-06-29 10:30")
+        );
+        assert_eq!(future("во вторник"), some("2022-06-28 09:00"));
+        assert_eq!(past("в прошлую пятницу"), some("2022-06-17 09:00"));
+        assert_eq!(future("в пятницу в 7 вечера"), some("2022-06-24 19:00"));
+    }
+
+    #[test]
+    fn reads_dates() {
+        assert_eq!(future("2022-07-01"), some("2022-07-01 09:00"));
+        assert_eq!(future("2022-07-01 20:00"), some("2022-07-01 20:00"));
+        assert_eq!(future("2022-07-01T20:15"), some("2022-07-01 20:15"));
+        assert_eq!(future("01.07.2022"), some("2022-07-01 09:00"));
+        assert_eq!(future("1 july"), some("2022-07-01 09:00"));
+        assert_eq!(future("july 1st at noon"), some("2022-07-01 12:00"));
+        assert_eq!(future("jun 1"), some("2023-06-01 09:00"));
+        assert_eq!(past("jun 1"), some("2022-06-01 09:00"));
+        assert_eq!(past("1 december"), some("2021-12-01 09:00"));
+        assert_eq!(future("23 june 2023"), some("2023-06-23 09:00"));
+        assert_eq!(future("1 июля"), some("2022-07-01 09:00"));
+        assert_eq!(future("31 декабря в 23:59"), some("2022-12-31 23:59"));
+        assert_eq!(future("05.07"), some("2022-07-05 09:00"));
+        assert_eq!(future("31 february"), None);
+    }
+
+    #[test]
+    fn reads_clock_times() {
+        assert_eq!(future("18:00"), some("2022-06-23 18:00"));
+        assert_eq!(future("08:00"), some("2022-06-24 08:00"));
+        assert_eq!(past("18:00"), some("2022-06-22 18:00"));
+        assert_eq!(future("at 3pm"), some("2022-06-23 15:00"));
+        assert_eq!(future("9:30 pm"), some("2022-06-23 21:30"));
+        assert_eq!(future("12am"), some("2022-06-24 00:00"));
+        assert_eq!(future("at midnight"), some("2022-06-24 00:00"));
+        assert_eq!(future("в 13:00"), some("2022-06-23 13:00"));
+        assert_eq!(future("13pm"), None);
+        assert_eq!(future("25:00"), None);
+        assert_eq!(future("9"), None);
+    }
+
+    #[test]
+    fn leaves_the_rest() {
+        assert_eq!(
+            at("in 2 hours call Ann", Direction::Future),
+            Some(("2022-06-23 14:00".to_string(), "call Ann"))
+        );
+        assert_eq!(
+            at("tomorrow, 9am   standup at the office", Direction::Future),
+            Some(("2022-06-24 09:00".to_string(), "standup at the office"))
+        );
+        assert_eq!(
+            at("завтра в 10 утра позвонить маме", Direction::Future),
+            Some(("2022-06-24 10:00".to_string(), "позвонить маме"))
+        );
+        assert_eq!(
+            at("monday at the gym", Direction::Future),
+            Some(("2022-06-27 09:00".to_string(), "at the gym"))
+        );
+        assert_eq!(at("5 apples", Direction::Future), None);
+        assert_eq!(at("call Ann tomorrow", Direction::Future), None);
+        assert_eq!(at("", Direction::Future), None);
+    }
+
+    #[test]
+    fn honors_timezone() {
+        // 15:00 in Moscow, UTC+3.
+        let (due, _) = parse("18:00", now(), Moscow, Direction::Future).unwrap();
+        assert_eq!(due, Utc.with_ymd_and_hms(2022, 6, 23, 15, 0, 0).unwrap());
+        let (due, _) = parse("14:00", now(), Moscow, Direction::Future).unwrap();
+        assert_eq!(due, Utc.with_ymd_and_hms(2022, 6, 24, 11, 0, 0).unwrap());
+        let (due, _) = parse("завтра", now(), Moscow, Direction::Future).unwrap();
+        assert_eq!(due, Utc.with_ymd_and_hms(2022, 6, 24, 6, 0, 0).unwrap());
+        // Delays don't depend on the clock.
+        let (due, _) = parse("in 2 hours", now(), Moscow, Direction::Future).unwrap();
+        assert_eq!(due, now() + Duration::hours(2));
+        // 23:30 UTC is already tomorrow in Moscow.
+        let late = Utc.with_ymd_and_hms(2022, 6, 23, 23, 30, 0).unwrap();
+        let (due, _) = parse("tomorrow", late, Moscow, Direction::Future).unwrap();
+        assert_eq!(due, Utc.with_ymd_and_hms(2022, 6, 25, 6, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn skips_dst_gaps() {
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        let before = Utc.with_ymd_and_hms(2022, 3, 26, 12, 0, 0).unwrap();
+        let (due, _) = parse("tomorrow 2:30am", before, tz, Direction::Future).unwrap();
+        assert_eq!(due, Utc.with_ymd_and_hms(2022, 3, 27, 1, 30, 0).unwrap());
+    }
+
+    #[test]
+    fn reads_whole_days() {
+        let day = |text| parse_day(text, now(), chrono_tz::UTC);
+        assert_eq!(day("yesterday"), NaiveDate::from_ymd_opt(2022, 6, 22));
+        assert_eq!(day("monday"), NaiveDate::from_ymd_opt(2022, 6, 20));
+        assert_eq!(day("3 дня назад"), NaiveDate::from_ymd_opt(2022, 6, 20));
+        assert_eq!(day("2022-06-01"), NaiveDate::from_ymd_opt(2022, 6, 1));
+        assert_eq!(day("2022-06"), NaiveDate::from_ymd_opt(2022, 6, 1));
+        assert_eq!(day("5 мая"), NaiveDate::from_ymd_opt(2022, 5, 5));
+        assert_eq!(day("yesterday please"), None);
+        let late = Utc.with_ymd_and_hms(2022, 6, 23, 22, 0, 0).unwrap();
+        assert_eq!(
+            parse_day("today", late, Moscow),
+            NaiveDate::from_ymd_opt(2022, 6, 24)
+        );
+    }
+
+    #[test]
+    fn starts_days_in_timezone() {
+        let date = NaiveDate::from_ymd_opt(2022, 6, 23).unwrap();
+        assert_eq!(day_start(date, chrono_tz::UTC), 1655942400);
+        assert_eq!(day_start(date, Moscow), 1655942400 - 3 * 3600);
+    }
+
+    #[test]
+    fn shows_dates_in_timezone() {
+        assert_eq!(
+            human_date(1655985600, chrono_tz::UTC),
+            "2022-06-23 12:00 UTC"
+        );
+        assert_eq!(human_date(1655985600, Moscow), "2022-06-23 15:00 MSK");
+    }
+}